@@ -0,0 +1,568 @@
+//! Parsing `#[haskell(..)]` attributes
+
+use proc_macro2::Span;
+use quote::ToTokens;
+use syn::{
+    parse::{Parse, Parser},
+    punctuated::Punctuated,
+    Attribute, Data, DeriveInput, Error, Ident, Lit, Meta, NestedMeta, Path, Result, Token, Type,
+    WherePredicate,
+};
+
+use crate::generics::Derive;
+
+/*******************************************************************************
+  Container attributes
+*******************************************************************************/
+
+/// Attributes on the type we are deriving an instance for
+#[derive(Default)]
+pub struct Container {
+    /// Only derive the instance for this specific tag (`#[haskell(tag = "..")]`)
+    pub tag: Option<Type>,
+
+    /// Custom bounds, replacing the inferred bounds (`#[haskell(bound = "..")]`)
+    pub bound: Bounds,
+
+    /// Forward to the single non-skipped field (`#[haskell(transparent)]`)
+    pub transparent: bool,
+
+    /// Name of the Haskell type (`#[haskell(rename = "..")]`)
+    pub rename: Option<String>,
+
+    /// Naming convention for Haskell record fields (`#[haskell(rename_all = "..")]`)
+    ///
+    /// This is the name of the corresponding `haskell_ffi::naming::RenameRule`.
+    pub rename_all: Option<Ident>,
+
+    /// Prefix for Haskell record fields (`#[haskell(field_prefix = "..")]`)
+    pub field_prefix: Option<String>,
+
+    /// Existing Haskell type for this type (`#[haskell(maps_to = "..")]`)
+    pub maps_to: Option<String>,
+}
+
+impl Container {
+    pub fn from_ast(input: &DeriveInput) -> Result<Self> {
+        let mut container = Container::default();
+        for meta in haskell_metas(&input.attrs)? {
+            match &meta {
+                Meta::NameValue(nv) if nv.path.is_ident("tag") => {
+                    set_once(&meta, &mut container.tag, parse_lit(&nv.lit)?)?
+                }
+                _ if meta.path().is_ident("bound") => container.bound.add(&meta)?,
+                Meta::Path(path) if path.is_ident("transparent") => {
+                    if container.transparent {
+                        return Err(Error::new_spanned(
+                            &meta,
+                            "duplicate haskell attribute `transparent`",
+                        ));
+                    }
+                    container.transparent = true
+                }
+                Meta::NameValue(nv) if nv.path.is_ident("rename") => {
+                    let name = haskell_constructor(&nv.lit)?;
+                    set_once(&meta, &mut container.rename, name)?
+                }
+                Meta::NameValue(nv) if nv.path.is_ident("rename_all") => {
+                    set_once(&meta, &mut container.rename_all, rename_rule(&nv.lit)?)?
+                }
+                Meta::NameValue(nv) if nv.path.is_ident("field_prefix") => {
+                    let prefix = haskell_field_prefix(&nv.lit)?;
+                    set_once(&meta, &mut container.field_prefix, prefix)?
+                }
+                Meta::NameValue(nv) if nv.path.is_ident("maps_to") => {
+                    let name = qualified_haskell_type(&nv.lit)?;
+                    set_once(&meta, &mut container.maps_to, name)?
+                }
+                _ => return Err(unknown_attribute(&meta)),
+            }
+        }
+        if container.transparent {
+            check_transparent(input)?;
+        }
+        Ok(container)
+    }
+}
+
+/// Transparent types must be structs with exactly one non-skipped field
+fn check_transparent(input: &DeriveInput) -> Result<()> {
+    let msg = "#[haskell(transparent)] requires a struct with exactly one non-skipped field";
+    match &input.data {
+        Data::Struct(data) => {
+            let mut unskipped = 0;
+            for f in &data.fields {
+                if !Field::from_ast(f)?.is_skipped() {
+                    unskipped += 1;
+                }
+            }
+            if unskipped == 1 {
+                Ok(())
+            } else {
+                Err(Error::new_spanned(&input.ident, msg))
+            }
+        }
+        Data::Enum(_) | Data::Union(_) => Err(Error::new_spanned(&input.ident, msg)),
+    }
+}
+
+/*******************************************************************************
+  Field attributes
+*******************************************************************************/
+
+/// How to encode a field
+pub enum Encoding {
+    /// Use the `ToHaskell`/`FromHaskell` instances of the field type
+    Default,
+
+    /// Use a custom module (`#[haskell(with = "module")]`)
+    ///
+    /// The module should provide functions `to_haskell` and `from_haskell`,
+    /// with the same signatures as the methods of `ToHaskell` and `FromHaskell`
+    /// (except that `to_haskell` takes the value as its first argument).
+    /// If `HaskellSize` or `HaskellMaxSize` are derived, the module should
+    /// also provide `haskell_size` or `haskell_max_size`, respectively.
+    With(Path),
+
+    /// Piggy-back on Borsh (`#[haskell(via = "borsh")]`), as for `UseBorsh`
+    Borsh,
+
+    /// Use length-prefixed bincode (`#[haskell(via = "bincode")]`)
+    Bincode,
+
+    /// Do not encode the field at all (`#[haskell(skip)]`)
+    ///
+    /// When decoding, the field is constructed using `Default::default()`, or
+    /// using the specified function (`#[haskell(default = "path")]`, which
+    /// implies `skip`).
+    Skip(Option<Path>),
+}
+
+/// Attributes on a field
+pub struct Field {
+    pub encoding: Encoding,
+
+    /// Custom bounds, replacing the bounds inferred from this field
+    pub bound: Bounds,
+
+    /// Name of the Haskell record field (`#[haskell(rename = "..")]`)
+    pub rename: Option<String>,
+}
+
+impl Field {
+    pub fn is_skipped(&self) -> bool {
+        matches!(self.encoding, Encoding::Skip(_))
+    }
+
+    pub fn from_ast(field: &syn::Field) -> Result<Self> {
+        let mut encoding: Option<Encoding> = None;
+        let mut skip: bool = false;
+        let mut default: Option<Option<Path>> = None;
+        let mut bound = Bounds::default();
+        let mut rename = None;
+        for meta in haskell_metas(&field.attrs)? {
+            match &meta {
+                _ if meta.path().is_ident("bound") => bound.add(&meta)?,
+                Meta::NameValue(nv) if nv.path.is_ident("rename") => {
+                    if field.ident.is_none() {
+                        return Err(Error::new_spanned(
+                            &meta,
+                            "`rename` is only supported on named fields",
+                        ));
+                    }
+                    set_once(&meta, &mut rename, haskell_field(&nv.lit)?)?
+                }
+                Meta::Path(path) if path.is_ident("skip") => skip = true,
+                Meta::Path(path) if path.is_ident("default") => {
+                    set_once(&meta, &mut default, None)?
+                }
+                Meta::NameValue(nv) if nv.path.is_ident("default") => {
+                    set_once(&meta, &mut default, Some(parse_lit(&nv.lit)?))?
+                }
+                Meta::NameValue(nv) if nv.path.is_ident("with") => {
+                    set_encoding(&meta, &mut encoding, Encoding::With(parse_lit(&nv.lit)?))?
+                }
+                Meta::NameValue(nv) if nv.path.is_ident("via") => {
+                    let via = match &nv.lit {
+                        Lit::Str(s) if s.value() == "borsh" => Encoding::Borsh,
+                        Lit::Str(s) if s.value() == "bincode" => Encoding::Bincode,
+                        lit => {
+                            return Err(Error::new_spanned(
+                                lit,
+                                "expected \"borsh\" or \"bincode\"",
+                            ))
+                        }
+                    };
+                    set_encoding(&meta, &mut encoding, via)?
+                }
+                _ => return Err(unknown_attribute(&meta)),
+            }
+        }
+        if skip || default.is_some() {
+            if encoding.is_some() {
+                return Err(Error::new_spanned(
+                    field,
+                    "skipped fields cannot use `with` or `via`",
+                ));
+            }
+            encoding = Some(Encoding::Skip(default.flatten()));
+        }
+        Ok(Field {
+            encoding: encoding.unwrap_or(Encoding::Default),
+            bound,
+            rename,
+        })
+    }
+}
+
+fn set_encoding(meta: &Meta, slot: &mut Option<Encoding>, value: Encoding) -> Result<()> {
+    if slot.is_some() {
+        return Err(Error::new_spanned(
+            meta,
+            "at most one of `with` and `via` can be specified",
+        ));
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
+/*******************************************************************************
+  Variant attributes
+*******************************************************************************/
+
+/// Attributes on an enum variant
+#[derive(Default)]
+pub struct Variant {
+    /// Name of the Haskell constructor (`#[haskell(rename = "..")]`)
+    pub rename: Option<String>,
+}
+
+impl Variant {
+    pub fn from_ast(variant: &syn::Variant) -> Result<Self> {
+        let mut attrs = Variant::default();
+        for meta in haskell_metas(&variant.attrs)? {
+            match &meta {
+                Meta::NameValue(nv) if nv.path.is_ident("rename") => {
+                    let name = haskell_constructor(&nv.lit)?;
+                    set_once(&meta, &mut attrs.rename, name)?
+                }
+                _ => return Err(unknown_attribute(&meta)),
+            }
+        }
+        Ok(attrs)
+    }
+}
+
+/*******************************************************************************
+  Export attributes
+*******************************************************************************/
+
+/// Arguments to `#[haskell_export(..)]`
+#[derive(Default)]
+pub struct Export {
+    /// Name of the exported symbol (`name = ".."`)
+    pub name: Option<String>,
+
+    /// Tag used for marshalling (`tag = ".."`)
+    pub tag: Option<Type>,
+
+    /// Return the result in a Rust-allocated buffer (`external`)
+    pub external: bool,
+
+    /// Do not catch panics, nor return a status code (`unchecked`)
+    pub unchecked: bool,
+}
+
+impl Export {
+    pub fn from_args(args: Vec<NestedMeta>) -> Result<Self> {
+        let mut export = Export::default();
+        for nested in args {
+            let meta = match nested {
+                NestedMeta::Meta(meta) => meta,
+                NestedMeta::Lit(lit) => {
+                    return Err(Error::new_spanned(lit, "expected haskell_export argument"))
+                }
+            };
+            match &meta {
+                Meta::NameValue(nv) if nv.path.is_ident("name") => {
+                    let name = match &nv.lit {
+                        Lit::Str(s) => s.value(),
+                        lit => return Err(Error::new_spanned(lit, "expected string literal")),
+                    };
+                    set_once(&meta, &mut export.name, name)?
+                }
+                Meta::NameValue(nv) if nv.path.is_ident("tag") => {
+                    set_once(&meta, &mut export.tag, parse_lit(&nv.lit)?)?
+                }
+                Meta::Path(path) if path.is_ident("external") => {
+                    if export.external {
+                        return Err(Error::new_spanned(
+                            &meta,
+                            "duplicate haskell_export argument `external`",
+                        ));
+                    }
+                    export.external = true
+                }
+                Meta::Path(path) if path.is_ident("unchecked") => {
+                    if export.unchecked {
+                        return Err(Error::new_spanned(
+                            &meta,
+                            "duplicate haskell_export argument `unchecked`",
+                        ));
+                    }
+                    export.unchecked = true
+                }
+                _ => {
+                    return Err(Error::new_spanned(
+                        &meta,
+                        format!("unknown haskell_export argument `{}`", attr_name(&meta)),
+                    ))
+                }
+            }
+        }
+        Ok(export)
+    }
+}
+
+/*******************************************************************************
+  Bounds
+*******************************************************************************/
+
+/// Custom bounds
+///
+/// These can be specified for all derives at once (`bound = ".."`), or for
+/// specific derives (`bound(to_haskell = "..", haskell_size = "..")`).
+#[derive(Default)]
+pub struct Bounds {
+    all: Option<Vec<WherePredicate>>,
+    per_derive: Vec<(Derive, Vec<WherePredicate>)>,
+}
+
+impl Bounds {
+    /// Custom bounds for the specified derive, if any
+    pub fn for_derive(&self, derive: Derive) -> Option<&[WherePredicate]> {
+        self.per_derive
+            .iter()
+            .find(|(d, _)| *d == derive)
+            .map(|(_, preds)| preds.as_slice())
+            .or(self.all.as_deref())
+    }
+
+    fn add(&mut self, meta: &Meta) -> Result<()> {
+        match meta {
+            Meta::NameValue(nv) => set_once(meta, &mut self.all, parse_predicates(&nv.lit)?),
+            Meta::List(list) => {
+                for nested in &list.nested {
+                    let derive = match nested {
+                        NestedMeta::Meta(Meta::NameValue(nv)) => Derive::ALL
+                            .iter()
+                            .find(|d| nv.path.is_ident(d.attr_key()))
+                            .map(|d| (*d, &nv.lit)),
+                        _ => None,
+                    };
+                    match derive {
+                        Some((derive, lit)) => {
+                            if self.per_derive.iter().any(|(d, _)| *d == derive) {
+                                return Err(Error::new_spanned(
+                                    nested,
+                                    format!("duplicate bound for `{}`", derive.attr_key()),
+                                ));
+                            }
+                            self.per_derive.push((derive, parse_predicates(lit)?))
+                        }
+                        None => {
+                            return Err(Error::new_spanned(
+                                nested,
+                                "expected one of `to_haskell`, `from_haskell`, \
+                                 `haskell_size`, `haskell_max_size` or `haskell_type`",
+                            ))
+                        }
+                    }
+                }
+                Ok(())
+            }
+            Meta::Path(_) => Err(Error::new_spanned(
+                meta,
+                "expected `bound = \"..\"` or `bound(..)`",
+            )),
+        }
+    }
+}
+
+fn parse_predicates(lit: &Lit) -> Result<Vec<WherePredicate>> {
+    match lit {
+        Lit::Str(s) => {
+            let parser = Punctuated::<WherePredicate, Token![,]>::parse_terminated;
+            let preds = parser
+                .parse_str(&s.value())
+                .map_err(|err| Error::new_spanned(lit, format!("invalid bound: {}", err)))?;
+            Ok(preds.into_iter().collect())
+        }
+        _ => Err(Error::new_spanned(lit, "expected string literal")),
+    }
+}
+
+/*******************************************************************************
+  Auxiliary
+*******************************************************************************/
+
+/// All items in all `#[haskell(..)]` attributes
+fn haskell_metas(attrs: &[Attribute]) -> Result<Vec<Meta>> {
+    let mut metas = Vec::new();
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("haskell")) {
+        match attr.parse_meta()? {
+            Meta::List(list) => {
+                for nested in list.nested {
+                    match nested {
+                        NestedMeta::Meta(meta) => metas.push(meta),
+                        NestedMeta::Lit(lit) => {
+                            return Err(Error::new_spanned(lit, "expected haskell attribute"))
+                        }
+                    }
+                }
+            }
+            meta => {
+                return Err(Error::new_spanned(
+                    meta,
+                    "expected attribute of the form #[haskell(..)]",
+                ))
+            }
+        }
+    }
+    Ok(metas)
+}
+
+fn parse_string(lit: &Lit) -> Result<String> {
+    match lit {
+        Lit::Str(s) => Ok(s.value()),
+        _ => Err(Error::new_spanned(lit, "expected string literal")),
+    }
+}
+
+/// Haskell type or constructor name
+fn haskell_constructor(lit: &Lit) -> Result<String> {
+    let name = parse_string(lit)?;
+    if name.starts_with(|c: char| c.is_uppercase()) && is_identifier_rest(&name) {
+        Ok(name)
+    } else {
+        Err(Error::new_spanned(
+            lit,
+            "Haskell type and constructor names must start with an uppercase letter, \
+             and consist of letters, digits, `_` and `'`",
+        ))
+    }
+}
+
+/// Reserved identifiers in Haskell, which cannot be used as field names
+///
+/// Keep in sync with `HASKELL_KEYWORDS` in `haskell_ffi::codegen::haskell`
+/// (this crate cannot depend on `haskell_ffi`).
+const HASKELL_KEYWORDS: [&str; 23] = [
+    "_", "case", "class", "data", "default", "deriving", "do", "else", "foreign", "if", "import",
+    "in", "infix", "infixl", "infixr", "instance", "let", "module", "newtype", "of", "then",
+    "type", "where",
+];
+
+/// Haskell record field name
+fn haskell_field(lit: &Lit) -> Result<String> {
+    let name = parse_string(lit)?;
+    if is_field_start(&name)
+        && is_identifier_rest(&name)
+        && !HASKELL_KEYWORDS.contains(&name.as_str())
+    {
+        Ok(name)
+    } else {
+        Err(Error::new_spanned(
+            lit,
+            "Haskell field names must start with a lowercase letter or `_`, \
+             consist of letters, digits, `_` and `'`, and not be a keyword",
+        ))
+    }
+}
+
+/// Prefix for Haskell record field names
+///
+/// The prefix is followed by the capitalized Rust field name, so the result is
+/// never a keyword.
+fn haskell_field_prefix(lit: &Lit) -> Result<String> {
+    let prefix = parse_string(lit)?;
+    if is_field_start(&prefix) && is_identifier_rest(&prefix) {
+        Ok(prefix)
+    } else {
+        Err(Error::new_spanned(
+            lit,
+            "Haskell field prefixes must start with a lowercase letter or `_`, \
+             and consist of letters, digits, `_` and `'`",
+        ))
+    }
+}
+
+fn is_field_start(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_lowercase() || c == '_')
+}
+
+/// Does the name consist of characters allowed in Haskell identifiers?
+fn is_identifier_rest(name: &str) -> bool {
+    name.chars()
+        .all(|c| c.is_alphanumeric() || c == '_' || c == '\'')
+}
+
+/// Module-qualified Haskell type, such as `Solana.Types.Pubkey`
+fn qualified_haskell_type(lit: &Lit) -> Result<String> {
+    let name = parse_string(lit)?;
+    let unqualified = name.rsplit('.').next().unwrap_or_default();
+    if unqualified.starts_with(|c: char| c.is_uppercase()) {
+        Ok(name)
+    } else {
+        Err(Error::new_spanned(
+            lit,
+            "expected a (qualified) Haskell type, such as \"Solana.Types.Pubkey\"",
+        ))
+    }
+}
+
+/// Name of the `RenameRule` variant for a naming convention
+fn rename_rule(lit: &Lit) -> Result<Ident> {
+    let rule = match parse_string(lit)?.as_str() {
+        "camelCase" => "CamelCase",
+        "snake_case" => "SnakeCase",
+        "lowercase" => "LowerCase",
+        _ => {
+            return Err(Error::new_spanned(
+                lit,
+                "expected \"camelCase\", \"snake_case\" or \"lowercase\"",
+            ))
+        }
+    };
+    Ok(Ident::new(rule, Span::call_site()))
+}
+
+/// Parse the contents of a string literal (`tag = "path::to::Tag"`)
+fn parse_lit<T: Parse>(lit: &Lit) -> Result<T> {
+    match lit {
+        Lit::Str(s) => s.parse(),
+        _ => Err(Error::new_spanned(lit, "expected string literal")),
+    }
+}
+
+fn set_once<T>(meta: &Meta, slot: &mut Option<T>, value: T) -> Result<()> {
+    if slot.is_some() {
+        return Err(Error::new_spanned(
+            meta,
+            format!("duplicate haskell attribute `{}`", attr_name(meta)),
+        ));
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
+fn unknown_attribute(meta: &Meta) -> Error {
+    Error::new_spanned(
+        meta,
+        format!("unknown haskell attribute `{}`", attr_name(meta)),
+    )
+}
+
+fn attr_name(meta: &Meta) -> String {
+    meta.path().to_token_stream().to_string()
+}
@@ -0,0 +1,330 @@
+//! The `#[haskell_export]` attribute
+
+use proc_macro2::{Ident, TokenStream};
+use quote::{format_ident, quote};
+use syn::{parse_quote, Error, FnArg, ItemFn, NestedMeta, Pat, Result, ReturnType, Type};
+
+use crate::attr::Export;
+
+pub fn expand(args: Vec<NestedMeta>, item: ItemFn) -> Result<TokenStream> {
+    let export = Export::from_args(args)?;
+    let sig = &item.sig;
+    let fn_name = &sig.ident;
+
+    if let Some(param) = sig.generics.params.first() {
+        return Err(Error::new_spanned(
+            param,
+            "haskell_export: cannot export generic functions",
+        ));
+    }
+    if let Some(asyncness) = &sig.asyncness {
+        return Err(Error::new_spanned(
+            asyncness,
+            "haskell_export: cannot export async functions",
+        ));
+    }
+    if let Some(variadic) = &sig.variadic {
+        return Err(Error::new_spanned(
+            variadic,
+            "haskell_export: cannot export variadic functions",
+        ));
+    }
+
+    let symbol = export.name.clone().unwrap_or_else(|| fn_name.to_string());
+    let shim_name = format_ident!("__haskell_export_{}", fn_name);
+    let tag: Type = export.tag.clone().unwrap_or_else(|| parse_quote!(()));
+
+    // Arguments are passed as a pointer to their encoding, and its length
+    let mut params = Vec::new();
+    let mut unmarshall = Vec::new();
+    let mut catch_unmarshall = Vec::new();
+    let mut call_args = Vec::new();
+    let mut describe_args = Vec::new();
+    for (ix, arg) in sig.inputs.iter().enumerate() {
+        let arg = match arg {
+            FnArg::Typed(arg) => arg,
+            FnArg::Receiver(receiver) => {
+                return Err(Error::new_spanned(
+                    receiver,
+                    "haskell_export: cannot export methods",
+                ))
+            }
+        };
+        let name = match &*arg.pat {
+            Pat::Ident(pat) => pat.ident.clone(),
+            _ => format_ident!("arg{}", ix),
+        };
+        // The parameters for `out` would clash with the output buffer
+        if name == "out" {
+            return Err(Error::new_spanned(
+                &arg.pat,
+                "haskell_export: the argument name `out` is reserved for the output buffer",
+            ));
+        }
+        let ptr = format_ident!("{}_ptr", name);
+        let len = format_ident!("{}_len", name);
+        let value = format_ident!("__{}", name);
+        params.push(quote!(#ptr: *const u8, #len: usize));
+
+        // Arguments taken by reference are decoded to a local value; `&str` and
+        // `&[T]` are decoded to `String` and `Vec<T>`, which have the same encoding
+        let (ty, by_ref): (Type, bool) = match &*arg.ty {
+            Type::Reference(reference) if reference.mutability.is_none() => {
+                match &*reference.elem {
+                    Type::Path(path) if path.qself.is_none() && path.path.is_ident("str") => {
+                        (parse_quote!(::std::string::String), true)
+                    }
+                    Type::Slice(slice) => {
+                        let elem = &slice.elem;
+                        (parse_quote!(::std::vec::Vec<#elem>), true)
+                    }
+                    elem => (elem.clone(), true),
+                }
+            }
+            ty => (ty.clone(), false),
+        };
+        match &ty {
+            Type::ImplTrait(_) => {
+                return Err(Error::new_spanned(
+                    &arg.ty,
+                    "haskell_export: cannot export functions with `impl Trait` arguments",
+                ))
+            }
+            Type::TraitObject(_) => {
+                return Err(Error::new_spanned(
+                    &arg.ty,
+                    "haskell_export: cannot export functions with `dyn Trait` arguments",
+                ))
+            }
+            Type::Reference(_) => {
+                return Err(Error::new_spanned(
+                    &arg.ty,
+                    "haskell_export: arguments can only be taken by shared reference, \
+                     or by value",
+                ))
+            }
+            _ => (),
+        }
+        unmarshall.push(quote! {
+            let #value: #ty = (&&::haskell_ffi::export::Unmarshaller::<#tag, #ty>::new())
+                .unmarshall(#ptr, #len, tag);
+        });
+        catch_unmarshall.push(quote! {
+            let #value: #ty = match (&&::haskell_ffi::export::Unmarshaller::<#tag, #ty>::new())
+                .catch_unmarshall(#ptr, #len, tag)
+            {
+                ::core::result::Result::Ok(value) => value,
+                ::core::result::Result::Err(status) => return status,
+            };
+        });
+        let arg_name = name.to_string();
+        describe_args.push(quote! {
+            ::haskell_ffi::schema::FunctionArg {
+                name: ::std::string::String::from(#arg_name),
+                ty: (&&::haskell_ffi::export::Describer::<#tag, #ty>::new()).describe(schema),
+                marshalling: (&&::haskell_ffi::export::Unmarshaller::<#tag, #ty>::new()).marshalling(),
+            }
+        });
+        call_args.push(if by_ref {
+            quote!(&#value)
+        } else {
+            quote!(#value)
+        });
+    }
+
+    let call = quote!(#fn_name(#(#call_args),*));
+    let shim = Shim {
+        symbol: &symbol,
+        name: &shim_name,
+        params: &params,
+        call: &call,
+        tag: &tag,
+        result_ty: result_type(&sig.output),
+        external: export.external,
+    };
+    let shim = if export.unchecked {
+        shim.unchecked(&unmarshall)
+    } else {
+        shim.checked(&catch_unmarshall)
+    };
+
+    // Description of the function, for the Haskell code generator
+    let checked = !export.unchecked;
+    let describe_result = match result_type(&sig.output) {
+        None => quote!(::core::option::Option::None),
+        Some(result_ty) => {
+            let marshalling = if export.external {
+                quote!(::haskell_ffi::schema::Marshalling::External)
+            } else {
+                quote!((&&&::haskell_ffi::export::Marshaller::<#tag, #result_ty>::new()).marshalling())
+            };
+            quote! {
+                ::core::option::Option::Some(::haskell_ffi::schema::FunctionResult {
+                    ty: (&&::haskell_ffi::export::Describer::<#tag, #result_ty>::new()).describe(schema),
+                    marshalling: #marshalling,
+                })
+            }
+        }
+    };
+
+    Ok(quote! {
+        #item
+
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        #shim
+
+        const _: () = {
+            fn describe(
+                schema: &mut ::haskell_ffi::schema::Schema,
+            ) -> ::haskell_ffi::schema::FunctionDecl {
+                #[allow(unused_imports)]
+                use ::haskell_ffi::export::{
+                    DescribeKnown as _, DescribeOpaque as _, MarshallFixed as _, MarshallMax as _,
+                    MarshallVar as _, UnmarshallFixed as _, UnmarshallVar as _,
+                };
+                ::haskell_ffi::schema::FunctionDecl {
+                    symbol: ::std::string::String::from(#symbol),
+                    args: ::std::vec![#(#describe_args),*],
+                    result: #describe_result,
+                    checked: #checked,
+                }
+            }
+
+            ::haskell_ffi::inventory::submit! {
+                ::haskell_ffi::export::HaskellExport { symbol: #symbol, describe }
+            }
+        };
+    })
+}
+
+/// Parts of the `extern "C"` wrapper
+struct Shim<'a> {
+    symbol: &'a str,
+    name: &'a Ident,
+    params: &'a [TokenStream],
+    call: &'a TokenStream,
+    tag: &'a Type,
+    result_ty: Option<&'a Type>,
+    external: bool,
+}
+
+impl Shim<'_> {
+    /// Wrapper that catches panics, and returns a status code
+    fn checked(&self, unmarshall: &[TokenStream]) -> TokenStream {
+        let Shim {
+            symbol,
+            name: shim_name,
+            params,
+            call,
+            tag,
+            ..
+        } = *self;
+        let status = quote!(::haskell_ffi::error::Status);
+        let call = quote! {
+            match #status::catch(|| ::core::result::Result::Ok(#call)) {
+                ::core::result::Result::Ok(result) => result,
+                ::core::result::Result::Err(status) => return status,
+            }
+        };
+        match self.result_ty {
+            None => quote! {
+                #[export_name = #symbol]
+                extern "C" fn #shim_name(#(#params),*) -> #status {
+                    #[allow(unused_imports)]
+                    use ::haskell_ffi::export::{UnmarshallFixed as _, UnmarshallVar as _};
+                    let tag: ::core::marker::PhantomData<#tag> = ::core::marker::PhantomData;
+                    #(#unmarshall)*
+                    #call;
+                    #status::Ok
+                }
+            },
+            Some(result_ty) if self.external => quote! {
+                #[export_name = #symbol]
+                extern "C" fn #shim_name(#(#params,)* out: *mut *mut ::std::vec::Vec<u8>) -> #status {
+                    #[allow(unused_imports)]
+                    use ::haskell_ffi::export::{UnmarshallFixed as _, UnmarshallVar as _};
+                    let tag: ::core::marker::PhantomData<#tag> = ::core::marker::PhantomData;
+                    #(#unmarshall)*
+                    let result: #result_ty = #call;
+                    ::haskell_ffi::to_haskell::catch_marshall_to_haskell_external(&result, out, tag)
+                }
+            },
+            Some(result_ty) => quote! {
+                #[export_name = #symbol]
+                extern "C" fn #shim_name(#(#params,)* out: *mut u8, out_len: *mut usize) -> #status {
+                    #[allow(unused_imports)]
+                    use ::haskell_ffi::export::{
+                        MarshallFixed as _, MarshallMax as _, MarshallVar as _, UnmarshallFixed as _,
+                        UnmarshallVar as _,
+                    };
+                    let tag: ::core::marker::PhantomData<#tag> = ::core::marker::PhantomData;
+                    #(#unmarshall)*
+                    let result: #result_ty = #call;
+                    (&&&::haskell_ffi::export::Marshaller::<#tag, #result_ty>::new())
+                        .catch_marshall(&result, out, out_len, tag)
+                }
+            },
+        }
+    }
+
+    /// Wrapper that panics if the function panics, or marshalling fails
+    fn unchecked(&self, unmarshall: &[TokenStream]) -> TokenStream {
+        let Shim {
+            symbol,
+            name: shim_name,
+            params,
+            call,
+            tag,
+            ..
+        } = *self;
+        match self.result_ty {
+            None => quote! {
+                #[export_name = #symbol]
+                extern "C" fn #shim_name(#(#params),*) {
+                    #[allow(unused_imports)]
+                    use ::haskell_ffi::export::{UnmarshallFixed as _, UnmarshallVar as _};
+                    let tag: ::core::marker::PhantomData<#tag> = ::core::marker::PhantomData;
+                    #(#unmarshall)*
+                    #call;
+                }
+            },
+            Some(result_ty) if self.external => quote! {
+                #[export_name = #symbol]
+                extern "C" fn #shim_name(#(#params),*) -> *mut ::std::vec::Vec<u8> {
+                    #[allow(unused_imports)]
+                    use ::haskell_ffi::export::{UnmarshallFixed as _, UnmarshallVar as _};
+                    let tag: ::core::marker::PhantomData<#tag> = ::core::marker::PhantomData;
+                    #(#unmarshall)*
+                    let result: #result_ty = #call;
+                    ::haskell_ffi::to_haskell::marshall_to_haskell_external(&result, tag)
+                }
+            },
+            Some(result_ty) => quote! {
+                #[export_name = #symbol]
+                extern "C" fn #shim_name(#(#params,)* out: *mut u8, out_len: *mut usize) {
+                    #[allow(unused_imports)]
+                    use ::haskell_ffi::export::{
+                        MarshallFixed as _, MarshallMax as _, MarshallVar as _, UnmarshallFixed as _,
+                        UnmarshallVar as _,
+                    };
+                    let tag: ::core::marker::PhantomData<#tag> = ::core::marker::PhantomData;
+                    #(#unmarshall)*
+                    let result: #result_ty = #call;
+                    (&&&::haskell_ffi::export::Marshaller::<#tag, #result_ty>::new())
+                        .marshall(&result, out, out_len, tag);
+                }
+            },
+        }
+    }
+}
+
+/// Result type of the function, unless it is `()`
+fn result_type(output: &ReturnType) -> Option<&Type> {
+    match output {
+        ReturnType::Default => None,
+        ReturnType::Type(_, ty) => match &**ty {
+            Type::Tuple(tuple) if tuple.elems.is_empty() => None,
+            ty => Some(ty),
+        },
+    }
+}
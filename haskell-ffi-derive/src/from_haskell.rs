@@ -0,0 +1,132 @@
+//! Deriving `FromHaskell`
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Error, Fields, Result, Type};
+
+use crate::attr::{self, Container, Encoding};
+use crate::generics::{Derive, InstanceGenerics};
+use crate::variants::variant_indices;
+
+pub fn derive(input: DeriveInput) -> Result<TokenStream> {
+    let name = &input.ident;
+
+    let container = Container::from_ast(&input)?;
+
+    // Add a bound `T: FromHaskell<Tag>` for the type parameters used in the fields.
+    let InstanceGenerics {
+        tag: tag_ty,
+        impl_generics,
+        ty_generics,
+        where_clause,
+    } = InstanceGenerics::new(&input, &container, Derive::FromHaskell)?;
+
+    let body = match &input.data {
+        Data::Struct(data) => {
+            // Transparent types are decoded as their field, without adding context
+            let type_name = (!container.transparent).then(|| name.to_string());
+            let value =
+                from_haskell_fields(&tag_ty, quote!(#name), type_name.as_deref(), &data.fields)?;
+            quote!(Ok(#value))
+        }
+        Data::Enum(data) => {
+            let type_name = name.to_string();
+            let ixs = variant_indices(name, data)?;
+            let mut arms = Vec::new();
+            for (variant, ix) in data.variants.iter().zip(ixs) {
+                let variant_name = &variant.ident;
+                let value = from_haskell_fields(
+                    &tag_ty,
+                    quote!(#name::#variant_name),
+                    Some(&format!("{}::{}", name, variant_name)),
+                    &variant.fields,
+                )?;
+                arms.push(quote!(#ix => Ok(#value),));
+            }
+            // With 256 variants every index is valid, and a catch-all would be unreachable
+            let invalid = (data.variants.len() <= u8::MAX as usize).then(
+                || quote!(_ => Err(::haskell_ffi::error::invalid_variant_index(#type_name, ix)),),
+            );
+            quote! {
+                let ix = <u8 as ::haskell_ffi::FromHaskell<#tag_ty>>::from_haskell(buf, tag)?;
+                match ix {
+                    #(#arms)*
+                    #invalid
+                }
+            }
+        }
+        Data::Union(_) => {
+            return Err(Error::new_spanned(
+                name,
+                "FromHaskell cannot be derived for unions",
+            ))
+        }
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::haskell_ffi::FromHaskell<#tag_ty> for #name #ty_generics #where_clause {
+            fn from_haskell(
+                buf: &mut &[u8],
+                tag: ::core::marker::PhantomData<#tag_ty>,
+            ) -> ::haskell_ffi::error::Result<Self> {
+                #body
+            }
+        }
+    })
+}
+
+/// Construct value, deserializing each field in turn
+///
+/// Struct expressions are evaluated in the order in which the fields are
+/// written, so this matches the Borsh encoding of structs. Errors are wrapped
+/// in a `FieldError`, so that they mention which field failed to decode.
+///
+/// The `constructor` is the path to the struct or enum variant; `type_name` is
+/// used in error messages (if `None`, errors are passed on unchanged).
+fn from_haskell_fields(
+    tag_ty: &Type,
+    constructor: TokenStream,
+    type_name: Option<&str>,
+    fields: &Fields,
+) -> Result<TokenStream> {
+    let mut recurse = Vec::new();
+    for (ix, f) in fields.iter().enumerate() {
+        let t = &f.ty;
+        let field_name = match &f.ident {
+            Some(ident) => ident.to_string(),
+            None => ix.to_string(),
+        };
+        let field_attrs = attr::Field::from_ast(f)?;
+        let skipped = field_attrs.is_skipped();
+        let decode = match field_attrs.encoding {
+            Encoding::Default => {
+                quote!(<#t as ::haskell_ffi::FromHaskell<#tag_ty>>::from_haskell(buf, tag))
+            }
+            Encoding::With(module) => quote!(#module::from_haskell(buf, tag)),
+            Encoding::Borsh => {
+                quote!(::haskell_ffi::use_borsh::borsh_from_haskell::<#tag_ty, #t>(buf, tag))
+            }
+            Encoding::Bincode => {
+                quote!(::haskell_ffi::bincode::bincode_from_haskell::<#tag_ty, #t>(buf, tag))
+            }
+            Encoding::Skip(None) => quote!(::core::default::Default::default()),
+            Encoding::Skip(Some(default)) => quote!(#default()),
+        };
+        let decode = match type_name {
+            _ if skipped => decode,
+            Some(type_name) => quote! {
+                #decode.map_err(|err| ::haskell_ffi::error::FieldError::wrap(#type_name, #field_name, err))?
+            },
+            None => quote!(#decode?),
+        };
+        recurse.push(match &f.ident {
+            Some(ident) => quote!(#ident: #decode),
+            None => decode,
+        });
+    }
+    Ok(match fields {
+        Fields::Named(_) => quote!(#constructor { #(#recurse),* }),
+        Fields::Unnamed(_) => quote!(#constructor ( #(#recurse),* )),
+        Fields::Unit => quote!(#constructor),
+    })
+}
@@ -0,0 +1,357 @@
+//! Dealing with the generics of the type we are deriving an instance for
+
+use std::collections::HashSet;
+
+use proc_macro2::{Span, TokenStream, TokenTree};
+use quote::ToTokens;
+use syn::{
+    parse_quote, Data, DeriveInput, GenericArgument, GenericParam, Generics, Ident, Path,
+    PathArguments, Result, ReturnType, Type, TypeParam, TypeParamBound, WherePredicate,
+};
+
+use crate::attr::{self, Container, Encoding};
+
+/*******************************************************************************
+  The derives
+*******************************************************************************/
+
+/// The trait we are deriving an instance for
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Derive {
+    ToHaskell,
+    FromHaskell,
+    HaskellSize,
+    HaskellMaxSize,
+    HaskellType,
+}
+
+impl Derive {
+    pub const ALL: [Derive; 5] = [
+        Derive::ToHaskell,
+        Derive::FromHaskell,
+        Derive::HaskellSize,
+        Derive::HaskellMaxSize,
+        Derive::HaskellType,
+    ];
+
+    /// Name used in `#[haskell(bound(..))]`
+    pub fn attr_key(self) -> &'static str {
+        match self {
+            Derive::ToHaskell => "to_haskell",
+            Derive::FromHaskell => "from_haskell",
+            Derive::HaskellSize => "haskell_size",
+            Derive::HaskellMaxSize => "haskell_max_size",
+            Derive::HaskellType => "haskell_type",
+        }
+    }
+
+    /// The trait, applied to the tag (for example, `HaskellSize<Tag>`)
+    fn trait_bound(self, tag: &Type) -> TypeParamBound {
+        match self {
+            Derive::ToHaskell => parse_quote!(::haskell_ffi::ToHaskell<#tag>),
+            Derive::FromHaskell => parse_quote!(::haskell_ffi::FromHaskell<#tag>),
+            Derive::HaskellSize => parse_quote!(::haskell_ffi::HaskellSize<#tag>),
+            Derive::HaskellMaxSize => parse_quote!(::haskell_ffi::HaskellMaxSize<#tag>),
+            Derive::HaskellType => parse_quote!(::haskell_ffi::HaskellType<#tag>),
+        }
+    }
+
+    /// Does the derived code rely on the instance for the type of this field?
+    fn uses_instance(self, encoding: &Encoding) -> bool {
+        match self {
+            Derive::ToHaskell | Derive::FromHaskell | Derive::HaskellType => {
+                matches!(encoding, Encoding::Default)
+            }
+            // The size of a field encoded with Borsh is given by its `HaskellSize`
+            Derive::HaskellSize | Derive::HaskellMaxSize => {
+                matches!(encoding, Encoding::Default | Encoding::Borsh)
+            }
+        }
+    }
+}
+
+/*******************************************************************************
+  Instance generics
+*******************************************************************************/
+
+/// Generics of the instance we are deriving
+pub struct InstanceGenerics {
+    /// The `Tag` argument of the instance
+    ///
+    /// This is either a fresh type parameter, or the concrete type specified
+    /// by the user with `#[haskell(tag = "..")]`.
+    pub tag: Type,
+    pub impl_generics: TokenStream,
+    pub ty_generics: TokenStream,
+    pub where_clause: TokenStream,
+}
+
+impl InstanceGenerics {
+    /// Compute the generics of the instance
+    ///
+    /// Unless the user specified custom bounds, we add a bound (such as
+    /// `T: HaskellSize<Tag>`) for every type parameter `T` that is used in a
+    /// field relying on the instance for its type. Skipped fields, fields
+    /// with a custom encoding and `PhantomData<T>` do not give rise to bounds.
+    pub fn new(input: &DeriveInput, container: &Container, derive: Derive) -> Result<Self> {
+        let (tag, tag_param): (Type, Option<TypeParam>) = match &container.tag {
+            Some(tag) => (tag.clone(), None),
+            None => {
+                let ident = fresh_tag(input);
+                (parse_quote!(#ident), Some(TypeParam::from(ident)))
+            }
+        };
+
+        let predicates = match container.bound.for_derive(derive) {
+            Some(custom) => custom.to_vec(),
+            None => inferred_bounds(input, &tag, derive)?,
+        };
+
+        let mut without_tag: Generics = input.generics.clone();
+        without_tag
+            .make_where_clause()
+            .predicates
+            .extend(predicates);
+
+        // Type parameters must come after lifetimes, but before const parameters
+        let mut including_tag: Generics = without_tag.clone();
+        if let Some(tag_param) = tag_param {
+            let ix = including_tag
+                .params
+                .iter()
+                .take_while(|param| matches!(param, GenericParam::Lifetime(_)))
+                .count();
+            including_tag
+                .params
+                .insert(ix, GenericParam::Type(tag_param));
+        }
+
+        let (impl_generics, _, _) = including_tag.split_for_impl();
+        let (_, ty_generics, where_clause) = without_tag.split_for_impl();
+
+        Ok(InstanceGenerics {
+            impl_generics: impl_generics.to_token_stream(),
+            ty_generics: ty_generics.to_token_stream(),
+            where_clause: where_clause.to_token_stream(),
+            tag,
+        })
+    }
+}
+
+/// Bounds for the type parameters used in the fields
+///
+/// Fields with a custom bound contribute that bound instead.
+fn inferred_bounds(input: &DeriveInput, tag: &Type, derive: Derive) -> Result<Vec<WherePredicate>> {
+    let fields: Vec<&syn::Field> = match &input.data {
+        Data::Struct(data) => data.fields.iter().collect(),
+        Data::Enum(data) => data.variants.iter().flat_map(|v| &v.fields).collect(),
+        Data::Union(_) => Vec::new(),
+    };
+
+    let params: HashSet<Ident> = input
+        .generics
+        .type_params()
+        .map(|param| param.ident.clone())
+        .collect();
+
+    let mut predicates = Vec::new();
+    let mut used: Vec<Ident> = Vec::new();
+    for f in fields {
+        let field_attrs = attr::Field::from_ast(f)?;
+        match field_attrs.bound.for_derive(derive) {
+            Some(custom) => predicates.extend(custom.iter().cloned()),
+            None if derive.uses_instance(&field_attrs.encoding) => {
+                let mut acc = HashSet::new();
+                params_in_type(&f.ty, &params, &mut acc);
+                // Preserve the order in which the parameters are declared
+                for param in input.generics.type_params() {
+                    if acc.contains(&param.ident) && !used.contains(&param.ident) {
+                        used.push(param.ident.clone());
+                    }
+                }
+            }
+            None => (),
+        }
+    }
+
+    let bound = derive.trait_bound(tag);
+    let mut inferred: Vec<WherePredicate> = input
+        .generics
+        .type_params()
+        .filter(|param| used.contains(&param.ident))
+        .map(|param| {
+            let ident = &param.ident;
+            parse_quote!(#ident: #bound)
+        })
+        .collect();
+    inferred.extend(predicates);
+    Ok(inferred)
+}
+
+/// Type parameters used in fields relying on the instance, in the order in
+/// which they are declared
+///
+/// Unlike `inferred_bounds`, this ignores custom bounds. For `HaskellType`,
+/// these are the parameters of the Haskell declaration; the others (such as
+/// the parameter of a skipped `PhantomData<T>`) do not affect the encoding.
+pub fn used_params(input: &DeriveInput, derive: Derive) -> Result<Vec<Ident>> {
+    let fields: Vec<&syn::Field> = match &input.data {
+        Data::Struct(data) => data.fields.iter().collect(),
+        Data::Enum(data) => data.variants.iter().flat_map(|v| &v.fields).collect(),
+        Data::Union(_) => Vec::new(),
+    };
+
+    let params: HashSet<Ident> = input
+        .generics
+        .type_params()
+        .map(|param| param.ident.clone())
+        .collect();
+
+    let mut acc = HashSet::new();
+    for f in fields {
+        if derive.uses_instance(&attr::Field::from_ast(f)?.encoding) {
+            params_in_type(&f.ty, &params, &mut acc);
+        }
+    }
+    Ok(input
+        .generics
+        .type_params()
+        .filter(|param| acc.contains(&param.ident))
+        .map(|param| param.ident.clone())
+        .collect())
+}
+
+/// Type parameters occurring in the type, other than inside `PhantomData`
+fn params_in_type(ty: &Type, params: &HashSet<Ident>, acc: &mut HashSet<Ident>) {
+    match ty {
+        Type::Path(path) => {
+            if let Some(qself) = &path.qself {
+                params_in_type(&qself.ty, params, acc);
+            }
+            params_in_path(&path.path, params, acc);
+        }
+        Type::Array(array) => params_in_type(&array.elem, params, acc),
+        Type::Slice(slice) => params_in_type(&slice.elem, params, acc),
+        Type::Reference(reference) => params_in_type(&reference.elem, params, acc),
+        Type::Ptr(ptr) => params_in_type(&ptr.elem, params, acc),
+        Type::Paren(paren) => params_in_type(&paren.elem, params, acc),
+        Type::Group(group) => params_in_type(&group.elem, params, acc),
+        Type::Tuple(tuple) => {
+            for elem in &tuple.elems {
+                params_in_type(elem, params, acc);
+            }
+        }
+        Type::BareFn(bare_fn) => {
+            for input in &bare_fn.inputs {
+                params_in_type(&input.ty, params, acc);
+            }
+            params_in_return_type(&bare_fn.output, params, acc);
+        }
+        Type::TraitObject(object) => params_in_bounds(object.bounds.iter(), params, acc),
+        Type::ImplTrait(object) => params_in_bounds(object.bounds.iter(), params, acc),
+        // We cannot see inside macros; be conservative
+        Type::Macro(mac) => {
+            let mut idents = HashSet::new();
+            collect_idents(mac.mac.tokens.clone(), &mut idents);
+            acc.extend(
+                params
+                    .iter()
+                    .filter(|p| idents.contains(&p.to_string()))
+                    .cloned(),
+            );
+        }
+        _ => (),
+    }
+}
+
+fn params_in_path(path: &Path, params: &HashSet<Ident>, acc: &mut HashSet<Ident>) {
+    if path.leading_colon.is_none() {
+        if let Some(first) = path.segments.first() {
+            if params.contains(&first.ident) {
+                acc.insert(first.ident.clone());
+            }
+        }
+    }
+    if let Some(last) = path.segments.last() {
+        if last.ident == "PhantomData" {
+            return;
+        }
+    }
+    for segment in &path.segments {
+        match &segment.arguments {
+            PathArguments::None => (),
+            PathArguments::AngleBracketed(args) => {
+                for arg in &args.args {
+                    match arg {
+                        GenericArgument::Type(ty) => params_in_type(ty, params, acc),
+                        GenericArgument::Binding(binding) => {
+                            params_in_type(&binding.ty, params, acc)
+                        }
+                        GenericArgument::Constraint(constraint) => {
+                            params_in_bounds(constraint.bounds.iter(), params, acc)
+                        }
+                        GenericArgument::Lifetime(_) | GenericArgument::Const(_) => (),
+                    }
+                }
+            }
+            PathArguments::Parenthesized(args) => {
+                for input in &args.inputs {
+                    params_in_type(input, params, acc);
+                }
+                params_in_return_type(&args.output, params, acc);
+            }
+        }
+    }
+}
+
+fn params_in_return_type(output: &ReturnType, params: &HashSet<Ident>, acc: &mut HashSet<Ident>) {
+    if let ReturnType::Type(_, ty) = output {
+        params_in_type(ty, params, acc);
+    }
+}
+
+fn params_in_bounds<'a>(
+    bounds: impl Iterator<Item = &'a TypeParamBound>,
+    params: &HashSet<Ident>,
+    acc: &mut HashSet<Ident>,
+) {
+    for bound in bounds {
+        if let TypeParamBound::Trait(bound) = bound {
+            params_in_path(&bound.path, params, acc);
+        }
+    }
+}
+
+/*******************************************************************************
+  The tag parameter
+*******************************************************************************/
+
+/// Name for the `Tag` parameter of the instance
+///
+/// We pick a name that does not occur anywhere in the type definition, so that
+/// it cannot clash with the user's type parameters, nor shadow any of the
+/// types used in the fields.
+fn fresh_tag(input: &DeriveInput) -> Ident {
+    let mut used: HashSet<String> = HashSet::new();
+    collect_idents(input.to_token_stream(), &mut used);
+
+    let mut candidate = "Tag".to_string();
+    let mut suffix = 0;
+    while used.contains(&candidate) {
+        suffix += 1;
+        candidate = format!("Tag{}", suffix);
+    }
+    Ident::new(&candidate, Span::call_site())
+}
+
+fn collect_idents(tokens: TokenStream, acc: &mut HashSet<String>) {
+    for token in tokens {
+        match token {
+            TokenTree::Ident(ident) => {
+                acc.insert(ident.to_string());
+            }
+            TokenTree::Group(group) => collect_idents(group.stream(), acc),
+            TokenTree::Punct(_) | TokenTree::Literal(_) => (),
+        }
+    }
+}
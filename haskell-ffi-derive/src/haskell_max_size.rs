@@ -0,0 +1,74 @@
+//! Deriving `HaskellMaxSize`
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Error, Fields, Result, Type};
+
+use crate::attr::{self, Container, Encoding};
+use crate::generics::{Derive, InstanceGenerics};
+use crate::haskell_size::NO_SIZE_BINCODE;
+use crate::variants::variant_indices;
+
+pub fn derive(input: DeriveInput) -> Result<TokenStream> {
+    let name = &input.ident;
+
+    let container = Container::from_ast(&input)?;
+
+    // Add a bound `T: HaskellMaxSize<Tag>` for the type parameters used in the fields.
+    let InstanceGenerics {
+        tag: tag_ty,
+        impl_generics,
+        ty_generics,
+        where_clause,
+    } = InstanceGenerics::new(&input, &container, Derive::HaskellMaxSize)?;
+
+    let max_size = match &input.data {
+        Data::Struct(data) => haskell_max_size_fields(&tag_ty, &data.fields)?,
+        Data::Enum(data) => {
+            // Check that the variant index fits in a `u8`
+            variant_indices(name, data)?;
+            let variants = data
+                .variants
+                .iter()
+                .map(|variant| haskell_max_size_fields(&tag_ty, &variant.fields))
+                .collect::<Result<Vec<_>>>()?;
+            quote! {
+                1 + 0usize #(.max(#variants))*
+            }
+        }
+        Data::Union(_) => {
+            return Err(Error::new_spanned(
+                name,
+                "HaskellMaxSize cannot be derived for unions",
+            ))
+        }
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::haskell_ffi::HaskellMaxSize<#tag_ty> for #name #ty_generics #where_clause {
+            fn haskell_max_size(tag: ::core::marker::PhantomData<#tag_ty>) -> usize {
+                #max_size
+            }
+        }
+    })
+}
+
+/// Sum up the maximum size of each field
+fn haskell_max_size_fields(tag_ty: &Type, fields: &Fields) -> Result<TokenStream> {
+    let mut recurse = Vec::new();
+    for f in fields {
+        let t = &f.ty;
+        recurse.push(match attr::Field::from_ast(f)?.encoding {
+            // See comments in `haskell_size` regarding `Borsh`
+            Encoding::Default | Encoding::Borsh => {
+                quote! { <#t as ::haskell_ffi::HaskellMaxSize<#tag_ty>>::haskell_max_size(tag) }
+            }
+            Encoding::With(module) => quote! { #module::haskell_max_size(tag) },
+            Encoding::Bincode => return Err(Error::new_spanned(f, NO_SIZE_BINCODE)),
+            Encoding::Skip(_) => continue,
+        });
+    }
+    Ok(quote! {
+        (0 #(+ #recurse)*)
+    })
+}
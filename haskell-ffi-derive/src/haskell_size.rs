@@ -0,0 +1,177 @@
+//! Deriving `HaskellSize`
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DataEnum, DeriveInput, Error, Expr, Fields, Ident, Lit, Result, Type};
+
+use crate::attr::{self, Container, Encoding};
+use crate::generics::{Derive, InstanceGenerics};
+use crate::variants::variant_indices;
+
+pub fn derive(input: DeriveInput) -> Result<TokenStream> {
+    // Used in the quasi-quotation below as `#name`.
+    let name = &input.ident;
+
+    let container = Container::from_ast(&input)?;
+
+    // Add a bound `T: HaskellSize<Tag>` for the type parameters used in the fields.
+    let InstanceGenerics {
+        tag: tag_ty,
+        impl_generics,
+        ty_generics,
+        where_clause,
+    } = InstanceGenerics::new(&input, &container, Derive::HaskellSize)?;
+
+    // Generate an expression to sum up the size of each field.
+    let sum = haskell_size_sum(&tag_ty, name, &input.data)?;
+
+    Ok(quote! {
+        impl #impl_generics ::haskell_ffi::HaskellSize<#tag_ty> for #name #ty_generics #where_clause {
+            fn haskell_size(tag: ::core::marker::PhantomData<#tag_ty>) -> usize {
+                #sum
+            }
+        }
+    })
+}
+
+/// Generate an expression to sum up the size of each field.
+fn haskell_size_sum(tag_ty: &Type, name: &Ident, data: &Data) -> Result<TokenStream> {
+    match data {
+        Data::Struct(ref data) => haskell_size_fields(tag_ty, &data.fields),
+        Data::Enum(ref data) => haskell_size_enum(tag_ty, name, data),
+        Data::Union(_) => Err(Error::new_spanned(
+            name,
+            "HaskellSize cannot be derived for unions",
+        )),
+    }
+}
+
+/// Auxiliary to `haskell_size_sum`
+fn haskell_size_fields(tag_ty: &Type, fields: &Fields) -> Result<TokenStream> {
+    let mut recurse = Vec::new();
+    for f in fields {
+        let t = &f.ty;
+        recurse.push(match attr::Field::from_ast(f)?.encoding {
+            // The Borsh encoding of types with a `HaskellSize` instance has the same size
+            Encoding::Default | Encoding::Borsh => {
+                quote! { <#t as ::haskell_ffi::HaskellSize<#tag_ty>> :: haskell_size(tag) }
+            }
+            Encoding::With(module) => quote! { #module::haskell_size(tag) },
+            Encoding::Bincode => return Err(Error::new_spanned(f, NO_SIZE_BINCODE)),
+            Encoding::Skip(_) => continue,
+        });
+    }
+    Ok(quote! {
+        0 #(+ #recurse)*
+    })
+}
+
+pub const NO_SIZE_BINCODE: &str =
+    "fields encoded with bincode are length-prefixed, and do not have a statically known size";
+
+/*******************************************************************************
+  Enums
+
+  An enum only has a statically known size if all variants have payloads of
+  the same size. We must be able to tell from the field types alone that this
+  is the case (the payloads have the same types, or consist of primitive types
+  of the same total size); otherwise we report an error, rather than deriving
+  an instance that panics at runtime.
+*******************************************************************************/
+
+fn haskell_size_enum(tag_ty: &Type, name: &Ident, data: &DataEnum) -> Result<TokenStream> {
+    variant_indices(name, data)?;
+
+    let first_variant = match data.variants.first() {
+        None => return Ok(quote!(1)),
+        Some(variant) => variant,
+    };
+    let first_size = haskell_size_fields(tag_ty, &first_variant.fields)?;
+    let msg = format!(
+        "HaskellSize: cannot tell that the variants of {} all have the same size; \
+         derive HaskellMaxSize instead",
+        name
+    );
+
+    let payloads: Vec<Vec<FieldShape>> = data
+        .variants
+        .iter()
+        .map(|variant| {
+            variant
+                .fields
+                .iter()
+                .filter_map(|f| field_shape(f).transpose())
+                .collect()
+        })
+        .collect::<Result<_>>()?;
+    if payloads.windows(2).all(|pair| pair[0] == pair[1]) {
+        return Ok(quote!(1 + #first_size));
+    }
+
+    let sizes: Vec<Option<usize>> = payloads
+        .iter()
+        .map(|shapes| shapes.iter().map(|shape| shape.size).sum())
+        .collect();
+    match sizes.iter().position(|sz| sz.is_none() || *sz != sizes[0]) {
+        Some(ix) => Err(Error::new_spanned(&data.variants[ix], msg)),
+        None => Ok(quote!(1 + #first_size)),
+    }
+}
+
+/// Syntactic description of a field, used to compare the sizes of variants
+#[derive(PartialEq)]
+struct FieldShape {
+    /// Fields with the same key have the same size
+    key: String,
+
+    /// Size of the field, if we can determine it syntactically
+    size: Option<usize>,
+}
+
+/// Returns `None` for skipped fields
+fn field_shape(f: &syn::Field) -> Result<Option<FieldShape>> {
+    let t = &f.ty;
+    let ty = quote!(#t).to_string();
+    Ok(Some(match attr::Field::from_ast(f)?.encoding {
+        Encoding::Default | Encoding::Borsh => FieldShape {
+            size: static_size(t),
+            key: ty,
+        },
+        Encoding::With(module) => FieldShape {
+            key: format!("{} with {}", ty, quote!(#module)),
+            size: None,
+        },
+        Encoding::Bincode => return Err(Error::new_spanned(f, NO_SIZE_BINCODE)),
+        Encoding::Skip(_) => return Ok(None),
+    }))
+}
+
+/// Size of the type, if we can determine it syntactically
+///
+/// This only covers the primitive types (and tuples and arrays of these).
+fn static_size(t: &Type) -> Option<usize> {
+    match t {
+        Type::Path(path) if path.qself.is_none() => {
+            let ident = path.path.get_ident()?;
+            let size = match ident.to_string().as_str() {
+                "u8" | "i8" | "bool" => 1,
+                "u16" | "i16" => 2,
+                "u32" | "i32" | "f32" => 4,
+                "u64" | "i64" | "f64" => 8,
+                "u128" | "i128" => 16,
+                _ => return None,
+            };
+            Some(size)
+        }
+        Type::Tuple(tuple) => tuple.elems.iter().map(static_size).sum(),
+        Type::Array(array) => match &array.len {
+            Expr::Lit(lit) => match &lit.lit {
+                Lit::Int(n) => Some(static_size(&array.elem)? * n.base10_parse::<usize>().ok()?),
+                _ => None,
+            },
+            _ => None,
+        },
+        Type::Paren(paren) => static_size(&paren.elem),
+        _ => None,
+    }
+}
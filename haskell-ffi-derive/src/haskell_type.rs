@@ -0,0 +1,286 @@
+//! Deriving `HaskellType`
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{parse_quote, Data, DeriveInput, Error, Fields, GenericParam, Result, Type};
+
+use crate::attr::{self, Container, Encoding};
+use crate::generics::{self, Derive, InstanceGenerics};
+use crate::variants::variant_indices;
+
+pub fn derive(input: DeriveInput) -> Result<TokenStream> {
+    let name = &input.ident;
+    let rust_name = name.to_string();
+
+    let container = Container::from_ast(&input)?;
+
+    // Name of the Haskell type
+    let name_str = container
+        .rename
+        .clone()
+        .unwrap_or_else(|| rust_name.clone());
+
+    // Add a bound `T: HaskellType<Tag>` for the type parameters used in the fields.
+    let InstanceGenerics {
+        tag: tag_ty,
+        impl_generics,
+        ty_generics,
+        where_clause,
+    } = InstanceGenerics::new(&input, &container, Derive::HaskellType)?;
+
+    // The declaration of a generic type is computed for the instantiation of
+    // all type parameters to `TypeVar`s. Only the parameters used in the
+    // described fields become parameters of the Haskell type; the others
+    // (such as `T` in a skipped `PhantomData<T>`) need not have an instance.
+    let used = generics::used_params(&input, Derive::HaskellType)?;
+    let mut type_params = Vec::new();
+    let mut var_args = Vec::new();
+    let mut unused_ix = used.len();
+    for param in &input.generics.params {
+        match param {
+            GenericParam::Lifetime(_) => var_args.push(quote!('static)),
+            GenericParam::Type(param) if used.contains(&param.ident) => {
+                let ix = type_params.len();
+                type_params.push(&param.ident);
+                var_args.push(quote!(::haskell_ffi::haskell_type::TypeVar<#ix>));
+            }
+            GenericParam::Type(_) => {
+                var_args.push(quote!(::haskell_ffi::haskell_type::TypeVar<#unused_ix>));
+                unused_ix += 1;
+            }
+            GenericParam::Const(param) => {
+                return Err(Error::new_spanned(
+                    param,
+                    "HaskellType cannot be derived for types with const parameters",
+                ))
+            }
+        }
+    }
+    let param_names = type_params.iter().map(|ident| ident.to_string());
+
+    if let Some(haskell_name) = &container.maps_to {
+        if input.generics.type_params().next().is_some() {
+            return Err(Error::new_spanned(
+                &input.generics,
+                "#[haskell(maps_to = \"..\")] is not supported for generic types",
+            ));
+        }
+        return Ok(quote! {
+            impl #impl_generics ::haskell_ffi::HaskellType<#tag_ty> for #name #ty_generics #where_clause {
+                fn haskell_type(_tag: ::core::marker::PhantomData<#tag_ty>) -> ::haskell_ffi::schema::TypeExpr {
+                    ::haskell_ffi::schema::TypeExpr::Opaque(::std::string::String::from(
+                        ::core::concat!(::core::module_path!(), "::", #rust_name)
+                    ))
+                }
+
+                fn haskell_decls(
+                    _tag: ::core::marker::PhantomData<#tag_ty>,
+                    schema: &mut ::haskell_ffi::schema::Schema,
+                ) {
+                    schema.map_type(
+                        ::core::concat!(::core::module_path!(), "::", #rust_name),
+                        #haskell_name,
+                    );
+                }
+            }
+        });
+    }
+
+    let body = match &input.data {
+        Data::Struct(data) if container.transparent => {
+            // `Container::from_ast` checked that there is exactly one such field
+            let mut ty = None;
+            for f in &data.fields {
+                if !attr::Field::from_ast(f)?.is_skipped() {
+                    ty = Some(field_type(&tag_ty, f)?);
+                }
+            }
+            quote!(::haskell_ffi::schema::TypeBody::Transparent(#ty))
+        }
+        Data::Struct(data) => {
+            let fields = fields_expr(&tag_ty, &container, &data.fields)?;
+            quote!(::haskell_ffi::schema::TypeBody::Struct(#fields))
+        }
+        Data::Enum(data) => {
+            variant_indices(name, data)?;
+            let mut variants = Vec::new();
+            for variant in &data.variants {
+                let variant_name = attr::Variant::from_ast(variant)?
+                    .rename
+                    .unwrap_or_else(|| variant.ident.to_string());
+                let fields = fields_expr(&tag_ty, &container, &variant.fields)?;
+                variants.push(quote! {
+                    ::haskell_ffi::schema::Variant {
+                        name: ::std::string::String::from(#variant_name),
+                        fields: #fields,
+                    }
+                });
+            }
+            quote!(::haskell_ffi::schema::TypeBody::Enum(
+                ::std::vec![#(#variants),*]
+            ))
+        }
+        Data::Union(_) => {
+            return Err(Error::new_spanned(
+                name,
+                "HaskellType cannot be derived for unions",
+            ))
+        }
+    };
+
+    // Declarations needed by the field types, and their sizes (if known)
+    let mut field_types: Vec<&Type> = Vec::new();
+    let mut sizes = vec![record_size(
+        &tag_ty,
+        &parse_quote!(Self),
+        quote!(Self::haskell_type(tag)),
+    )];
+    let all_fields: Vec<&syn::Field> = match &input.data {
+        Data::Struct(data) => data.fields.iter().collect(),
+        Data::Enum(data) => data.variants.iter().flat_map(|v| &v.fields).collect(),
+        Data::Union(_) => Vec::new(),
+    };
+    for f in all_fields {
+        match attr::Field::from_ast(f)?.encoding {
+            Encoding::Default => {
+                field_types.push(&f.ty);
+                sizes.push(record_size(&tag_ty, &f.ty, field_type(&tag_ty, f)?));
+            }
+            // The Borsh encoding of types with a `HaskellSize` instance has the same size
+            Encoding::Borsh => sizes.push(record_size(&tag_ty, &f.ty, field_type(&tag_ty, f)?)),
+            Encoding::With(_) | Encoding::Bincode | Encoding::Skip(_) => {}
+        }
+    }
+
+    Ok(quote! {
+        impl #impl_generics ::haskell_ffi::HaskellType<#tag_ty> for #name #ty_generics #where_clause {
+            fn haskell_type(tag: ::core::marker::PhantomData<#tag_ty>) -> ::haskell_ffi::schema::TypeExpr {
+                ::haskell_ffi::schema::TypeExpr::Named {
+                    name: ::std::string::String::from(#name_str),
+                    args: ::std::vec![
+                        #(<#type_params as ::haskell_ffi::HaskellType<#tag_ty>>::haskell_type(tag)),*
+                    ],
+                }
+            }
+
+            fn haskell_decls(
+                tag: ::core::marker::PhantomData<#tag_ty>,
+                schema: &mut ::haskell_ffi::schema::Schema,
+            ) {
+                #(<#type_params as ::haskell_ffi::HaskellType<#tag_ty>>::haskell_decls(tag, schema);)*
+                let rust_path = ::core::concat!(::core::module_path!(), "::", #rust_name);
+                if schema.reserve(#name_str, rust_path) {
+                    if let ::core::option::Option::Some(decl) =
+                        <#name<#(#var_args),*> as ::haskell_ffi::HaskellType<#tag_ty>>::haskell_decl(tag, schema)
+                    {
+                        schema.insert(decl);
+                    }
+                }
+            }
+
+            fn haskell_decl(
+                tag: ::core::marker::PhantomData<#tag_ty>,
+                schema: &mut ::haskell_ffi::schema::Schema,
+            ) -> ::core::option::Option<::haskell_ffi::schema::TypeDecl> {
+                #(<#field_types as ::haskell_ffi::HaskellType<#tag_ty>>::haskell_decls(tag, schema);)*
+                #(#sizes)*
+                ::core::option::Option::Some(::haskell_ffi::schema::TypeDecl {
+                    name: ::std::string::String::from(#name_str),
+                    rust_path: ::std::string::String::from(
+                        ::core::concat!(::core::module_path!(), "::", #rust_name)
+                    ),
+                    params: ::std::vec![#(::std::string::String::from(#param_names)),*],
+                    body: #body,
+                })
+            }
+        }
+    })
+}
+
+/// Statement recording the size of `ty` (described by `type_expr`) in the
+/// schema, if it has a `HaskellSize` instance
+///
+/// The size is only known if the instance applies for all tags (or for the
+/// tag of this instance); for generic types, the declaration is computed for
+/// the instantiation to `TypeVar`s, which have no size.
+fn record_size(tag_ty: &Type, ty: &Type, type_expr: TokenStream) -> TokenStream {
+    quote! {
+        schema.record_size(#type_expr, {
+            use ::haskell_ffi::haskell_size::{SizeKnown as _, SizeUnknown as _};
+            (&&::haskell_ffi::haskell_size::SizeProbe::<#tag_ty, #ty>::new()).size()
+        });
+    }
+}
+
+/// Expression of type `Fields` describing the (non-skipped) fields
+fn fields_expr(tag_ty: &Type, container: &Container, fields: &Fields) -> Result<TokenStream> {
+    let mut recurse = Vec::new();
+    for f in fields {
+        let field_attrs = attr::Field::from_ast(f)?;
+        if field_attrs.is_skipped() {
+            continue;
+        }
+        let ty = field_type(tag_ty, f)?;
+        recurse.push(match &f.ident {
+            Some(ident) => {
+                let name = match field_attrs.rename {
+                    Some(rename) => quote!(::std::string::String::from(#rename)),
+                    None => {
+                        let rust_name = ident.to_string();
+                        let rule = match &container.rename_all {
+                            Some(rule) => quote! {
+                                ::core::option::Option::Some(::haskell_ffi::naming::RenameRule::#rule)
+                            },
+                            None => quote!(::core::option::Option::None),
+                        };
+                        let prefix = match &container.field_prefix {
+                            Some(prefix) => quote!(::core::option::Option::Some(#prefix)),
+                            None => quote!(::core::option::Option::None),
+                        };
+                        quote!(::haskell_ffi::naming::field_name(#rust_name, #rule, #prefix))
+                    }
+                };
+                quote! {
+                    ::haskell_ffi::schema::NamedField {
+                        name: #name,
+                        ty: #ty,
+                    }
+                }
+            }
+            None => ty,
+        });
+    }
+    Ok(match fields {
+        Fields::Named(_) => quote!(::haskell_ffi::schema::Fields::Named(
+            ::std::vec![#(#recurse),*]
+        )),
+        Fields::Unnamed(_) => {
+            quote!(::haskell_ffi::schema::Fields::Unnamed(
+                ::std::vec![#(#recurse),*]
+            ))
+        }
+        Fields::Unit => quote!(::haskell_ffi::schema::Fields::Unit),
+    })
+}
+
+/// Expression of type `TypeExpr` describing the encoding of the field
+fn field_type(tag_ty: &Type, f: &syn::Field) -> Result<TokenStream> {
+    let t = &f.ty;
+    Ok(match attr::Field::from_ast(f)?.encoding {
+        Encoding::Default => quote!(<#t as ::haskell_ffi::HaskellType<#tag_ty>>::haskell_type(tag)),
+        Encoding::With(module) => quote!(#module::haskell_type(tag)),
+        Encoding::Borsh => quote! {
+            ::haskell_ffi::schema::TypeExpr::Borsh(
+                ::std::string::String::from(::core::any::type_name::<#t>())
+            )
+        },
+        Encoding::Bincode => quote! {
+            ::haskell_ffi::schema::TypeExpr::Bincode(
+                ::std::string::String::from(::core::any::type_name::<#t>())
+            )
+        },
+        Encoding::Skip(_) => {
+            return Err(Error::new_spanned(f, "skipped fields do not have a type"))
+        }
+    })
+}
@@ -1,85 +1,212 @@
-//! Macro for deriving `HaskellSize` instances for structs
+//! Macros for deriving `HaskellSize`, `HaskellMaxSize`, `ToHaskell`,
+//! `FromHaskell` and `HaskellType` instances
 //!
-//! Implementation is adapted from the `heapsize` example in the `syn` crate.
-//! The implementation is not identical, however: `haskell_size` does not take
-//! any value as input, but is entirely type-based.
+//! The implementation of `HaskellSize` is adapted from the `heapsize` example
+//! in the `syn` crate. The implementation is not identical, however:
+//! `haskell_size` does not take any value as input, but is entirely
+//! type-based.
+//!
+//! There is also an attribute macro `haskell_export`, which generates
+//! `extern "C"` wrappers for functions that are called from Haskell.
+//!
+//! The generated code refers to the `haskell_ffi` crate through absolute paths
+//! (`::haskell_ffi::...`), so that users do not need to bring any particular
+//! names into scope.
+//!
+//! # Attributes
+//!
+//! All derives support the following container attributes:
+//!
+//! - `#[haskell(tag = "path::to::Tag")]`: only derive the instance for this
+//!   specific tag, rather than for all tags. This is necessary when some of
+//!   the field types only have instances for that particular tag.
+//! - `#[haskell(bound = "T: ToHaskell<Tag>")]`: use the specified where-clause
+//!   predicates instead of the inferred bounds. Bounds can also be given for
+//!   each derive separately, as in
+//!   `#[haskell(bound(to_haskell = "..", from_haskell = ".."))]` (the other
+//!   keys are `haskell_size`, `haskell_max_size` and `haskell_type`). The tag
+//!   parameter of the instance is called `Tag`, unless that name is already
+//!   used in the type definition.
+//! - `#[haskell(transparent)]`: for structs with a single non-skipped field,
+//!   forward all instances to that field. This matches a Haskell `newtype`
+//!   that derives its instances via the underlying type. Decoding errors are
+//!   reported as-is, without mentioning the field.
+//!
+//! By default, we add a bound such as `T: ToHaskell<Tag>` for every type
+//! parameter `T` that occurs in a field relying on that instance. Skipped
+//! fields, fields using `with` or `via`, and occurrences inside `PhantomData`
+//! do not give rise to bounds.
+//!
+//! The derives also support the following field attributes:
+//!
+//! - `#[haskell(with = "module")]`: encode the field using the functions
+//!   `module::to_haskell` and `module::from_haskell` (and `module::haskell_size`
+//!   and `module::haskell_max_size` when deriving `HaskellSize` or
+//!   `HaskellMaxSize`).
+//! - `#[haskell(via = "borsh")]`: encode the field using its Borsh instances,
+//!   as if it was wrapped in `UseBorsh`.
+//! - `#[haskell(via = "bincode")]`: encode the field using `bincode`; the
+//!   result is length-prefixed (see `bincode_to_haskell`).
+//! - `#[haskell(skip)]`: do not send the field across the FFI boundary at all.
+//!   When decoding, the field is constructed using `Default::default()`. Skipped
+//!   fields do not contribute to `HaskellSize` or `HaskellMaxSize`.
+//! - `#[haskell(default = "path")]`: skip the field, and construct it using the
+//!   function `path` when decoding. `#[haskell(default)]` is equivalent to
+//!   `#[haskell(skip)]`.
+//! - `#[haskell(bound = "..")]`: use the specified predicates instead of the
+//!   bounds inferred from this field; as for containers, the
+//!   `bound(to_haskell = "..", ..)` form is also supported.
+//!
+//! For `HaskellType`, fields using `via = "borsh"` or `via = "bincode"` are
+//! described by the name of their Rust type.
+//!
+//! Finally, the names used in the generated Haskell code can be changed. These
+//! attributes never affect the wire format:
+//!
+//! - `#[haskell(rename = "..")]` on a type, enum variant or named field: the
+//!   name of the Haskell type, constructor or record field.
+//! - `#[haskell(rename_all = "..")]` on a type: the naming convention for
+//!   record fields, one of `"camelCase"` (the default), `"snake_case"` or
+//!   `"lowercase"`.
+//! - `#[haskell(field_prefix = "..")]` on a type: prefix for all record
+//!   fields, so that `max_retries` with prefix `cfg` becomes `cfgMaxRetries`.
+//! - `#[haskell(maps_to = "Module.Type")]` on a (non-generic) type: refer to
+//!   the type using an existing Haskell type, rather than generating a
+//!   declaration for it (see `haskell_ffi::codegen::type_map`).
+
+mod attr;
+mod export;
+mod from_haskell;
+mod generics;
+mod haskell_max_size;
+mod haskell_size;
+mod haskell_type;
+mod to_haskell;
+mod variants;
 
-use proc_macro2::TokenStream;
-use quote::quote;
-use syn::{
-    parse_macro_input, parse_quote, punctuated::Iter, Data, DeriveInput, Field, Fields,
-    GenericParam, Generics,
-};
+use syn::{parse_macro_input, AttributeArgs, DeriveInput, ItemFn};
 
 /// Derive `HaskellSize` instance
 ///
-/// NOTE: Only structs are currently supported.
-#[proc_macro_derive(HaskellSize)]
+/// Enums are only supported if all variants have payloads of the same size
+/// (for example, if none of the variants have any fields); for other enums,
+/// derive `HaskellMaxSize` instead.
+#[proc_macro_derive(HaskellSize, attributes(haskell))]
 pub fn haskell_size_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    // Parse the input tokens into a syntax tree.
     let input: DeriveInput = parse_macro_input!(input as DeriveInput);
+    haskell_size::derive(input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
 
-    // Used in the quasi-quotation below as `#name`.
-    let name = &input.ident;
-
-    // Add a bound `T: HaskellSize` to every type parameter T.
-    let without_tag: Generics = add_trait_bounds(input.generics);
-
-    // The instance itself must get an additional `Tag` argument
-    //
-    // NOTE: Things will go badly if one of the user's parameters is also named `Tag`.
-    let mut including_tag: Generics = without_tag.clone();
-    including_tag
-        .params
-        .push(GenericParam::Type(parse_quote!(Tag)));
-
-    let (including_tag_impl, _, _) = including_tag.split_for_impl();
-    let (_, without_tag_tys, without_tag_where) = without_tag.split_for_impl();
-
-    // Generate an expression to sum up the size of each field.
-    let sum = haskell_size_sum(&input.data);
-
-    let expanded = quote! {
-        impl #including_tag_impl HaskellSize<Tag> for #name #without_tag_tys #without_tag_where {
-            fn haskell_size(tag: PhantomData<Tag>) -> usize {
-                #sum
-            }
-        }
-    };
+/// Derive `HaskellMaxSize` instance
+///
+/// For structs this is the sum of the maximum sizes of the fields; for enums it
+/// is one byte for the variant index plus the maximum over all variants.
+#[proc_macro_derive(HaskellMaxSize, attributes(haskell))]
+pub fn haskell_max_size_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input: DeriveInput = parse_macro_input!(input as DeriveInput);
+    haskell_max_size::derive(input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
 
-    // Hand the output tokens back to the compiler.
-    proc_macro::TokenStream::from(expanded)
+/// Derive `ToHaskell` instance
+///
+/// Fields are encoded in declaration order, matching the Borsh encoding of
+/// structs. Enums are encoded as a `u8` variant index followed by the fields of
+/// the variant; this matches the encoding of sum types in the Haskell `borsh`
+/// library.
+#[proc_macro_derive(ToHaskell, attributes(haskell))]
+pub fn to_haskell_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input: DeriveInput = parse_macro_input!(input as DeriveInput);
+    to_haskell::derive(input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
 }
 
-/// Add a bound `T: HaskellSize<Tag>` to every type parameter T.
-fn add_trait_bounds(mut generics: Generics) -> Generics {
-    for param in &mut generics.params {
-        if let GenericParam::Type(ref mut type_param) = *param {
-            type_param.bounds.push(parse_quote!(HaskellSize<Tag>));
-        }
-    }
-    generics
+/// Derive `FromHaskell` instance
+///
+/// Fields are decoded in declaration order. If a field fails to decode, the
+/// error will mention the name of the struct (or enum variant) and of the
+/// field.
+#[proc_macro_derive(FromHaskell, attributes(haskell))]
+pub fn from_haskell_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input: DeriveInput = parse_macro_input!(input as DeriveInput);
+    from_haskell::derive(input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
 }
 
-/// Generate an expression to sum up the size of each field.
-fn haskell_size_sum(data: &Data) -> TokenStream {
-    match data {
-        Data::Struct(ref data) => match &data.fields {
-            Fields::Named(fields) => haskell_size_fields(fields.named.iter()),
-            Fields::Unnamed(fields) => haskell_size_fields(fields.unnamed.iter()),
-            Fields::Unit => quote!(0),
-        },
-        Data::Enum(_) | Data::Union(_) => unimplemented!(),
-    }
+/// Derive `HaskellType` instance
+///
+/// The instance describes the same encoding as the derived `ToHaskell` and
+/// `FromHaskell` instances. For generic types, the declaration is computed by
+/// instantiating all type parameters to `TypeVar`s; const parameters are not
+/// supported. Type parameters that do not give rise to bounds (for example,
+/// `T` in a skipped `PhantomData<T>`) are left out of the Haskell type.
+/// Fields using `with = "module"` require a function `module::haskell_type`.
+#[proc_macro_derive(HaskellType, attributes(haskell))]
+pub fn haskell_type_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input: DeriveInput = parse_macro_input!(input as DeriveInput);
+    haskell_type::derive(input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
 }
 
-/// Auxiliary to `haskell_size_sum`
-fn haskell_size_fields(fields: Iter<Field>) -> TokenStream {
-    let recurse = fields.map(|f| {
-        let t = &f.ty;
-        quote! { <#t> :: haskell_size(tag) }
-    });
-    quote! {
-        0 #(+ #recurse)*
-    }
+/// Export function to Haskell
+///
+/// This generates an `extern "C"` wrapper around the function, which decodes
+/// the arguments and encodes the result. Each argument `x` is passed as a
+/// pointer `x_ptr` to its encoding, together with the length `x_len` of the
+/// encoding. Unless the function returns `()`, the wrapper takes two additional
+/// arguments `out` and `out_len`: a buffer for the result, and a pointer to the
+/// size of that buffer. Arguments can therefore not be called `out`.
+///
+/// Arguments can also be taken by shared reference, in which case they are
+/// decoded to a local value and passed by reference. `&str` and `&[T]` are
+/// decoded as `String` and `Vec<T>`; other references must be to sized types.
+/// Mutable references, `impl Trait` and `dyn Trait` are not supported.
+///
+/// Arguments with a `HaskellSize` instance use `marshall_from_haskell_fixed`;
+/// other arguments use `marshall_from_haskell_var`. Similarly, the result is
+/// encoded using `marshall_to_haskell_fixed` or `marshall_to_haskell_max` if
+/// the result type has a `HaskellSize` or `HaskellMaxSize` instance, and using
+/// `marshall_to_haskell_var` otherwise; only in the last case is `*out_len`
+/// updated to the required size.
+///
+/// The wrapper uses the `catch_marshall_*` variants of these functions, and
+/// catches panics in the function itself; it returns a
+/// `haskell_ffi::error::Status`, which is `Ok` (0) if the call succeeded. If
+/// it did not, the reason is recorded as the last error on the current thread
+/// (see `haskell_ffi::error::last_error`).
+///
+/// Functions with a result in a Rust-allocated buffer (see `external`) take an
+/// additional argument `out`, to which the buffer is written.
+///
+/// The function is also registered with `haskell_ffi::export::exports`, so
+/// that Haskell foreign imports can be generated for it (see
+/// `haskell_ffi::codegen::foreign`).
+///
+/// Supported arguments:
+///
+/// - `name = ".."`: name of the exported symbol (defaults to the name of the
+///   function).
+/// - `tag = "path::to::Tag"`: the tag to use for marshalling (defaults to `()`).
+/// - `external`: return the result in a Rust-allocated buffer, using
+///   `marshall_to_haskell_external`, rather than taking `out` and `out_len`.
+/// - `unchecked`: do not catch panics, and do not return a status code. The
+///   wrapper returns nothing (or the Rust-allocated buffer, for `external`),
+///   and aborts the process if the function panics, or if the arguments
+///   cannot be decoded.
+#[proc_macro_attribute]
+pub fn haskell_export(
+    args: proc_macro::TokenStream,
+    input: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    let args: AttributeArgs = parse_macro_input!(args as AttributeArgs);
+    let input: ItemFn = parse_macro_input!(input as ItemFn);
+    export::expand(args, input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
 }
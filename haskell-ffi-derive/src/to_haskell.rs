@@ -0,0 +1,130 @@
+//! Deriving `ToHaskell`
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{Data, DeriveInput, Error, Fields, Index, Result, Type};
+
+use crate::attr::{self, Container, Encoding};
+use crate::generics::{Derive, InstanceGenerics};
+use crate::variants::variant_indices;
+
+pub fn derive(input: DeriveInput) -> Result<TokenStream> {
+    let name = &input.ident;
+
+    let container = Container::from_ast(&input)?;
+
+    // Add a bound `T: ToHaskell<Tag>` for the type parameters used in the fields.
+    let InstanceGenerics {
+        tag: tag_ty,
+        impl_generics,
+        ty_generics,
+        where_clause,
+    } = InstanceGenerics::new(&input, &container, Derive::ToHaskell)?;
+
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let values = data
+                .fields
+                .iter()
+                .enumerate()
+                .map(|(ix, f)| match &f.ident {
+                    Some(ident) => quote!(&self.#ident),
+                    None => {
+                        let ix = Index::from(ix);
+                        quote!(&self.#ix)
+                    }
+                });
+            let fields = to_haskell_fields(&tag_ty, &data.fields, values)?;
+            quote! {
+                #fields
+                Ok(())
+            }
+        }
+        Data::Enum(data) => {
+            let ixs = variant_indices(name, data)?;
+            let mut arms = Vec::new();
+            for (variant, ix) in data.variants.iter().zip(ixs) {
+                let variant_name = &variant.ident;
+                let bindings: Vec<_> = (0..variant.fields.len())
+                    .map(|i| format_ident!("__field{}", i))
+                    .collect();
+                let pattern = match &variant.fields {
+                    Fields::Named(fields) => {
+                        let field_names = fields.named.iter().map(|f| &f.ident);
+                        quote!(#name::#variant_name { #(#field_names: ref #bindings),* })
+                    }
+                    Fields::Unnamed(_) => quote!(#name::#variant_name ( #(ref #bindings),* )),
+                    Fields::Unit => quote!(#name::#variant_name),
+                };
+                let fields = to_haskell_fields(
+                    &tag_ty,
+                    &variant.fields,
+                    bindings.iter().map(|b| quote!(#b)),
+                )?;
+                arms.push(quote! {
+                    #pattern => {
+                        <u8 as ::haskell_ffi::ToHaskell<#tag_ty>>::to_haskell(&#ix, writer, tag)?;
+                        #fields
+                        Ok(())
+                    }
+                });
+            }
+            quote! {
+                match *self {
+                    #(#arms)*
+                }
+            }
+        }
+        Data::Union(_) => {
+            return Err(Error::new_spanned(
+                name,
+                "ToHaskell cannot be derived for unions",
+            ))
+        }
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::haskell_ffi::ToHaskell<#tag_ty> for #name #ty_generics #where_clause {
+            fn to_haskell<W: ::std::io::Write>(
+                &self,
+                writer: &mut W,
+                tag: ::core::marker::PhantomData<#tag_ty>,
+            ) -> ::haskell_ffi::error::Result<()> {
+                #body
+            }
+        }
+    })
+}
+
+/// Serialize each field in turn
+///
+/// This matches the Borsh encoding of structs: the fields are simply
+/// concatenated, in declaration order. The `values` are expressions for
+/// references to each field.
+fn to_haskell_fields(
+    tag_ty: &Type,
+    fields: &Fields,
+    values: impl Iterator<Item = TokenStream>,
+) -> Result<TokenStream> {
+    let mut recurse = Vec::new();
+    for (f, value) in fields.iter().zip(values) {
+        let t = &f.ty;
+        let encode = match attr::Field::from_ast(f)?.encoding {
+            Encoding::Default => {
+                quote!(<#t as ::haskell_ffi::ToHaskell<#tag_ty>>::to_haskell(#value, writer, tag))
+            }
+            Encoding::With(module) => quote!(#module::to_haskell(#value, writer, tag)),
+            Encoding::Borsh => {
+                quote!(::haskell_ffi::use_borsh::borsh_to_haskell(#value, writer, tag))
+            }
+            Encoding::Bincode => {
+                quote!(::haskell_ffi::bincode::bincode_to_haskell(#value, writer, tag))
+            }
+            Encoding::Skip(_) => continue,
+        };
+        recurse.push(quote!(#encode?;));
+    }
+    Ok(quote! {
+        #(#recurse)*
+    })
+}
@@ -0,0 +1,18 @@
+//! Enum variants
+
+use syn::{DataEnum, Error, Ident, Result};
+
+/// Variant indices
+///
+/// Following Borsh, enums are encoded as a `u8` variant index (the position
+/// of the variant in the declaration, ignoring any explicit discriminants),
+/// followed by the fields of the variant.
+pub fn variant_indices(name: &Ident, data: &DataEnum) -> Result<Vec<u8>> {
+    (0..data.variants.len())
+        .map(|ix| {
+            u8::try_from(ix).map_err(|_| {
+                Error::new_spanned(name, "enums with more than 256 variants are not supported")
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,324 @@
+//! Generate the Haskell side of the bindings for a Rust crate
+//!
+//! The input is either a schema dump written using `SchemaDump::write_to`, or
+//! the source code of the crate (see `haskell_ffi::build`). The output is the Haskell
+//! module declaring the types, the Haskell module with the foreign imports,
+//! and the C header.
+//!
+//! With `--check`, nothing is written; instead, the generator fails if any of
+//! the files in the output directory are missing or out of date.
+//!
+//! The `diff` subcommand compares two schemas (see `haskell_ffi::compat`), and
+//! fails if there are any breaking changes to the wire format. The `docs`
+//! subcommand writes a Markdown description of the wire format (see
+//! `haskell_ffi::codegen::docs`).
+
+use std::{
+    env,
+    fmt::{self, Display},
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use haskell_ffi::{
+    build,
+    codegen::{self, docs::WireDocs, type_map::TypeMap, Config, GeneratedFile},
+    compat,
+    error::{Error, Result},
+    schema::SchemaDump,
+};
+
+const USAGE: &str = "\
+Usage: haskell-ffi-gen generate [OPTIONS] (--dump <FILE> | --source <DIR>)
+       haskell-ffi-gen diff <OLD> <NEW>
+       haskell-ffi-gen docs [--out <FILE>] [--title <TITLE>] (--dump <FILE> | --source <DIR>)
+
+Generate the Haskell modules and C header for the schema.
+
+Options:
+  --dump <FILE>           Schema dump (JSON) written by haskell_ffi::schema::SchemaDump
+  --source <DIR>          Source directory of the Rust crate
+  --out <DIR>             Haskell source directory [default: .]
+  --header-dir <DIR>      Directory for the C header [default: the Haskell source directory]
+  --types-module <NAME>   Haskell module for the types [default: Bindings.Types]
+  --ffi-module <NAME>     Haskell module for the foreign imports [default: Bindings.FFI]
+  --header <FILE>         File name of the C header [default: bindings.h]
+  --map <RUST>=<HASKELL>  Refer to the Rust type using an existing (qualified) Haskell type
+  --import <IMPORT>       Add an import to the generated Haskell modules
+  --check                 Do not write any files; fail if they are out of date
+
+Compare two schemas, each a schema dump (JSON) or the source directory of the
+Rust crate. Fails if there are any breaking changes to the wire format.
+
+Describe the wire format of the schema in Markdown, written to --out (or to
+standard output).
+";
+
+/*******************************************************************************
+  Errors
+*******************************************************************************/
+
+/// Invalid command line
+#[derive(Debug)]
+struct GenError {
+    message: String,
+}
+
+impl GenError {
+    fn new(message: impl Into<String>) -> Self {
+        GenError {
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for GenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for GenError {}
+
+impl From<GenError> for Error {
+    fn from(err: GenError) -> Self {
+        Error::custom(err)
+    }
+}
+
+/*******************************************************************************
+  Command line
+*******************************************************************************/
+
+enum Input {
+    Dump(PathBuf),
+    Source(PathBuf),
+}
+
+struct GenerateArgs {
+    input: Input,
+    out: PathBuf,
+    header_dir: Option<PathBuf>,
+    config: Config,
+    check: bool,
+}
+
+fn parse_generate_args(args: &[String]) -> Result<GenerateArgs> {
+    let mut input = None;
+    let mut out = PathBuf::from(".");
+    let mut header_dir = None;
+    let mut config = Config {
+        types_module: "Bindings.Types".to_string(),
+        ffi_module: "Bindings.FFI".to_string(),
+        header: "bindings.h".to_string(),
+        type_map: TypeMap::new(),
+    };
+    let mut check = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| GenError::new(format!("missing value for {}", arg)))
+        };
+        match arg.as_str() {
+            "--dump" => input = Some(Input::Dump(PathBuf::from(value()?))),
+            "--source" => input = Some(Input::Source(PathBuf::from(value()?))),
+            "--out" => out = PathBuf::from(value()?),
+            "--header-dir" => header_dir = Some(PathBuf::from(value()?)),
+            "--types-module" => config.types_module = value()?,
+            "--ffi-module" => config.ffi_module = value()?,
+            "--header" => config.header = value()?,
+            "--map" => {
+                let mapping = value()?;
+                let (rust_path, haskell_name) = mapping.split_once('=').ok_or_else(|| {
+                    GenError::new(format!("expected <RUST>=<HASKELL>, got {}", mapping))
+                })?;
+                config.type_map.map(rust_path, haskell_name);
+            }
+            "--import" => {
+                config.type_map.import(&value()?);
+            }
+            "--check" => check = true,
+            _ => return Err(GenError::new(format!("unknown argument {}", arg)).into()),
+        }
+    }
+
+    let input = input.ok_or_else(|| GenError::new("either --dump or --source is required"))?;
+    Ok(GenerateArgs {
+        input,
+        out,
+        header_dir,
+        config,
+        check,
+    })
+}
+
+/*******************************************************************************
+  Generating
+*******************************************************************************/
+
+impl Input {
+    /// Dump files are read as is, directories are parsed as source code
+    fn from_path(path: &str) -> Self {
+        let path = PathBuf::from(path);
+        if path.is_dir() {
+            Input::Source(path)
+        } else {
+            Input::Dump(path)
+        }
+    }
+
+    fn read(&self) -> Result<SchemaDump> {
+        match self {
+            Input::Dump(path) => SchemaDump::read_from(path),
+            Input::Source(dir) => build::parse_crate(dir),
+        }
+    }
+}
+
+/// Files to generate, with their path relative to the current directory
+fn generated_files(args: &GenerateArgs) -> Result<Vec<GeneratedFile>> {
+    let dump = args.input.read()?;
+    let mut files = Vec::new();
+    for file in codegen::generate_haskell(&dump, &args.config)? {
+        files.push(GeneratedFile {
+            path: args.out.join(file.path),
+            contents: file.contents,
+        });
+    }
+    let header = codegen::generate_header(&dump, &args.config)?;
+    files.push(GeneratedFile {
+        path: args
+            .header_dir
+            .as_ref()
+            .unwrap_or(&args.out)
+            .join(header.path),
+        contents: header.contents,
+    });
+    Ok(files)
+}
+
+fn write_files(files: &[GeneratedFile]) -> Result<()> {
+    for file in files {
+        if let Some(parent) = file.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&file.path, &file.contents)?;
+        println!("wrote {}", file.path.display());
+    }
+    Ok(())
+}
+
+/// Files that are missing or differ from what we would generate
+fn stale_files(files: &[GeneratedFile]) -> Vec<&Path> {
+    files
+        .iter()
+        .filter(|file| fs::read_to_string(&file.path).ok().as_deref() != Some(&*file.contents))
+        .map(|file| file.path.as_path())
+        .collect()
+}
+
+fn generate(args: &[String]) -> Result<ExitCode> {
+    let args = parse_generate_args(args)?;
+    let files = generated_files(&args)?;
+    if !args.check {
+        write_files(&files)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let stale = stale_files(&files);
+    if stale.is_empty() {
+        return Ok(ExitCode::SUCCESS);
+    }
+    for path in stale {
+        eprintln!("out of date: {}", path.display());
+    }
+    eprintln!("run haskell-ffi-gen generate without --check to update");
+    Ok(ExitCode::FAILURE)
+}
+
+/*******************************************************************************
+  Comparing schemas
+*******************************************************************************/
+
+fn diff(args: &[String]) -> Result<ExitCode> {
+    let (old, new) = match args {
+        [old, new] => (Input::from_path(old), Input::from_path(new)),
+        _ => return Err(GenError::new("diff expects two schemas").into()),
+    };
+    let diff = compat::diff(&old.read()?, &new.read()?);
+    print!("{}", diff);
+    Ok(if diff.is_compatible() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+/*******************************************************************************
+  Documenting the wire format
+*******************************************************************************/
+
+fn docs(args: &[String]) -> Result<ExitCode> {
+    let mut input = None;
+    let mut out = None;
+    let mut title = "Bindings".to_string();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| GenError::new(format!("missing value for {}", arg)))
+        };
+        match arg.as_str() {
+            "--dump" => input = Some(Input::Dump(PathBuf::from(value()?))),
+            "--source" => input = Some(Input::Source(PathBuf::from(value()?))),
+            "--out" => out = Some(PathBuf::from(value()?)),
+            "--title" => title = value()?,
+            _ => return Err(GenError::new(format!("unknown argument {}", arg)).into()),
+        }
+    }
+
+    let input = input.ok_or_else(|| GenError::new("either --dump or --source is required"))?;
+    let docs = WireDocs::from_dump(&title, input.read()?);
+    match out {
+        Some(path) => {
+            docs.write_to(&path)?;
+            println!("wrote {}", path.display());
+        }
+        None => print!("{}", docs.render()?),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/*******************************************************************************
+  Main
+*******************************************************************************/
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(|arg| arg.as_str()) {
+        Some("generate") => generate(&args[1..]),
+        Some("diff") => diff(&args[1..]),
+        Some("docs") => docs(&args[1..]),
+        Some("--help") | Some("-h") => {
+            print!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        _ => {
+            eprint!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match result {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("haskell-ffi-gen: {}", err);
+            ExitCode::from(2)
+        }
+    }
+}
@@ -0,0 +1,191 @@
+//! Running the `haskell-ffi-gen` binary
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use haskell_ffi::{schema::SchemaDump, HaskellType};
+
+mod v1 {
+    use super::*;
+
+    #[derive(HaskellType)]
+    #[allow(dead_code)]
+    pub struct Account {
+        pub owner: String,
+        pub balance: u64,
+    }
+}
+
+mod v2 {
+    use super::*;
+
+    #[derive(HaskellType)]
+    #[allow(dead_code)]
+    pub struct Account {
+        pub owner: String,
+        pub balance: u128,
+    }
+}
+
+/*******************************************************************************
+  Auxiliary
+*******************************************************************************/
+
+/// Empty directory for the test
+fn temp_dir(test: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("haskell-ffi-gen-{}-{}", test, std::process::id()));
+    if dir.exists() {
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Write a schema dump with the declarations for `Account`
+fn write_dump(path: &Path, v2: bool) {
+    let mut dump = SchemaDump::new();
+    if v2 {
+        dump.add::<(), v2::Account>();
+    } else {
+        dump.add::<(), v1::Account>();
+    }
+    dump.add_schema_check().write_to(path).unwrap();
+}
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_haskell-ffi-gen"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+/*******************************************************************************
+  Sanity checks
+*******************************************************************************/
+
+#[test]
+fn generate_check() {
+    let dir = temp_dir("check");
+    let dump = dir.join("schema.json");
+    let out = dir.join("src");
+    write_dump(&dump, false);
+
+    let (dump_arg, out_arg) = (dump.to_str().unwrap(), out.to_str().unwrap());
+    let generate = ["generate", "--dump", dump_arg, "--out", out_arg];
+    let check = ["generate", "--dump", dump_arg, "--out", out_arg, "--check"];
+
+    // Nothing generated yet
+    let output = run(&check);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("out of date"));
+
+    assert!(run(&generate).status.success());
+    for file in ["Bindings/Types.hs", "Bindings/FFI.hs", "bindings.h"] {
+        assert!(out.join(file).is_file(), "missing {}", file);
+    }
+    assert!(run(&check).status.success());
+
+    // Modified file
+    let types = out.join("Bindings/Types.hs");
+    let mut contents = fs::read_to_string(&types).unwrap();
+    contents.push_str("-- edited by hand\n");
+    fs::write(&types, contents).unwrap();
+    let output = run(&check);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains(&format!("out of date: {}", types.display())));
+
+    // Stale schema
+    assert!(run(&generate).status.success());
+    write_dump(&dump, true);
+    assert_eq!(run(&check).status.code(), Some(1));
+
+    // Missing file
+    assert!(run(&generate).status.success());
+    fs::remove_file(out.join("bindings.h")).unwrap();
+    assert_eq!(run(&check).status.code(), Some(1));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn diff() {
+    let dir = temp_dir("diff");
+    let old = dir.join("old.json");
+    let new = dir.join("new.json");
+    write_dump(&old, false);
+    write_dump(&new, true);
+    let (old, new) = (old.to_str().unwrap(), new.to_str().unwrap());
+
+    assert!(run(&["diff", old, old]).status.success());
+
+    let output = run(&["diff", old, new]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout)
+        .contains("field balance changed type from u64 to u128"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn invalid_arguments() {
+    let output = run(&[]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).starts_with("Usage:"));
+
+    let output = run(&["generate", "--dump"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        stderr(&output),
+        "haskell-ffi-gen: missing value for --dump\n"
+    );
+
+    let output = run(&["generate", "--out", "src"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        stderr(&output),
+        "haskell-ffi-gen: either --dump or --source is required\n"
+    );
+
+    let output = run(&["generate", "--verbose"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        stderr(&output),
+        "haskell-ffi-gen: unknown argument --verbose\n"
+    );
+
+    let output = run(&["generate", "--dump", "schema.json", "--map", "Pubkey"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        stderr(&output),
+        "haskell-ffi-gen: expected <RUST>=<HASKELL>, got Pubkey\n"
+    );
+}
+
+#[test]
+fn unsupported_source() {
+    let dir = temp_dir("source");
+    fs::write(
+        dir.join("lib.rs"),
+        r#"
+        #[derive(HaskellType)]
+        pub struct Slot {
+            #[haskell(with = "narrow")]
+            slot: u64,
+        }
+        "#,
+    )
+    .unwrap();
+
+    let output = run(&["generate", "--source", dir.to_str().unwrap(), "--check"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("Slot.slot: fields using #[haskell(with = \"..\")]"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -1,7 +1,4 @@
-use std::{
-    io::{ErrorKind, Write},
-    marker::PhantomData,
-};
+use std::{io::Write, marker::PhantomData};
 
 use crate::error::Result;
 
@@ -18,7 +15,7 @@ where
             borsh::BorshSerialize::serialize(&vec, writer)?;
             Ok(())
         }
-        Err(e) => Err(Box::new(std::io::Error::new(ErrorKind::InvalidData, e))),
+        Err(e) => Err(e.into()),
     }
 }
 
@@ -32,6 +29,6 @@ where
     let vec: Vec<u8> = borsh::BorshDeserialize::deserialize(buf)?;
     match bincode::deserialize(vec.as_ref()) {
         Ok(x) => Ok(x),
-        Err(e) => Err(Box::new(std::io::Error::new(ErrorKind::InvalidData, e))),
+        Err(e) => Err(e.into()),
     }
 }
@@ -0,0 +1,270 @@
+//! Generating the Haskell bindings from a build script
+//!
+//! Build scripts run before the crate is compiled, so the schema is read from
+//! the source code of the crate (see `source` for the limitations). A typical
+//! `build.rs` looks like
+//!
+//! ```ignore
+//! fn main() {
+//!     haskell_ffi::build::Bindings::new()
+//!         .types_module("Example.Types")
+//!         .ffi_module("Example.FFI")
+//!         .header("example.h")
+//!         .haskell_dir("../example-hs/src")
+//!         .header_dir("../example-hs/cbits")
+//!         .map_type("solana_program::pubkey::Pubkey", "Solana.Types.Pubkey")
+//!         .generate()
+//!         .unwrap();
+//! }
+//! ```
+//!
+//! This requires the `build` feature.
+
+mod source;
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    codegen::{self, type_map::TypeMap, Config, GeneratedFile},
+    error::Result,
+};
+
+pub use source::parse_crate;
+
+/*******************************************************************************
+  Configuration
+*******************************************************************************/
+
+/// Bindings to generate
+pub struct Bindings {
+    config: Config,
+    source_dir: Option<PathBuf>,
+    haskell_dir: PathBuf,
+    header_dir: Option<PathBuf>,
+    library: Option<String>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bindings {
+    pub fn new() -> Self {
+        Bindings {
+            config: Config {
+                types_module: "Bindings.Types".to_string(),
+                ffi_module: "Bindings.FFI".to_string(),
+                header: "bindings.h".to_string(),
+                type_map: TypeMap::new(),
+            },
+            source_dir: None,
+            haskell_dir: PathBuf::from("haskell/src"),
+            header_dir: None,
+            library: None,
+        }
+    }
+
+    /// Haskell module for the types (defaults to `Bindings.Types`)
+    pub fn types_module(&mut self, module_name: &str) -> &mut Self {
+        self.config.types_module = module_name.to_string();
+        self
+    }
+
+    /// Haskell module for the foreign imports (defaults to `Bindings.FFI`)
+    pub fn ffi_module(&mut self, module_name: &str) -> &mut Self {
+        self.config.ffi_module = module_name.to_string();
+        self
+    }
+
+    /// File name of the C header (defaults to `bindings.h`)
+    pub fn header(&mut self, file_name: &str) -> &mut Self {
+        self.config.header = file_name.to_string();
+        self
+    }
+
+    /// Refer to the Rust type using an existing Haskell type
+    ///
+    /// See `codegen::type_map::TypeMap::map`.
+    pub fn map_type(&mut self, rust_path: &str, haskell_name: &str) -> &mut Self {
+        self.config.type_map.map(rust_path, haskell_name);
+        self
+    }
+
+    /// Add an import to the generated Haskell modules
+    pub fn import(&mut self, import: &str) -> &mut Self {
+        self.config.type_map.import(import);
+        self
+    }
+
+    /// Source directory of the crate (defaults to `src` in the crate root)
+    pub fn source_dir(&mut self, dir: impl Into<PathBuf>) -> &mut Self {
+        self.source_dir = Some(dir.into());
+        self
+    }
+
+    /// Haskell source directory (defaults to `haskell/src`)
+    ///
+    /// Relative paths are relative to the crate root.
+    pub fn haskell_dir(&mut self, dir: impl Into<PathBuf>) -> &mut Self {
+        self.haskell_dir = dir.into();
+        self
+    }
+
+    /// Directory for the C header (defaults to the Haskell source directory)
+    ///
+    /// Relative paths are relative to the crate root.
+    pub fn header_dir(&mut self, dir: impl Into<PathBuf>) -> &mut Self {
+        self.header_dir = Some(dir.into());
+        self
+    }
+
+    /// Name of the library the Haskell package links against
+    ///
+    /// Defaults to the name of the crate. Only used for the Cabal stanza.
+    pub fn library(&mut self, name: &str) -> &mut Self {
+        self.library = Some(name.to_string());
+        self
+    }
+
+    /// Generate the bindings, and write them to the configured directories
+    ///
+    /// Files are only written if their contents changed, so that the Haskell
+    /// package is not rebuilt unnecessarily. When any files were written, the
+    /// suggested Cabal stanza is shown as a build warning.
+    pub fn generate(&self) -> Result<()> {
+        let crate_dir = crate_dir();
+        let source_dir = crate_dir.join(self.source_dir.as_deref().unwrap_or(Path::new("src")));
+        println!("cargo:rerun-if-changed={}", source_dir.display());
+
+        let mut updated = false;
+        for file in self.generated_files(&source_dir)? {
+            let path = crate_dir.join(&file.path);
+            if fs::read_to_string(&path).ok().as_deref() == Some(&*file.contents) {
+                continue;
+            }
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, &file.contents)?;
+            updated = true;
+        }
+
+        if updated {
+            for line in self.cabal_stanza().lines() {
+                println!("cargo:warning={}", line);
+            }
+        }
+        Ok(())
+    }
+
+    /// Files to generate, with paths relative to the crate root
+    pub fn generated_files(&self, source_dir: &Path) -> Result<Vec<GeneratedFile>> {
+        let dump = parse_crate(source_dir)?;
+        let mut files = Vec::new();
+        for file in codegen::generate_haskell(&dump, &self.config)? {
+            files.push(GeneratedFile {
+                path: self.haskell_dir.join(file.path),
+                contents: file.contents,
+            });
+        }
+        let header = codegen::generate_header(&dump, &self.config)?;
+        files.push(GeneratedFile {
+            path: self.header_path().join(header.path),
+            contents: header.contents,
+        });
+        Ok(files)
+    }
+
+    /// Suggested stanza for the `.cabal` file of the Haskell package
+    ///
+    /// The header directory is shown relative to the crate root; it may need
+    /// to be adjusted to be relative to the Haskell package instead.
+    pub fn cabal_stanza(&self) -> String {
+        let library = match &self.library {
+            Some(library) => library.clone(),
+            None => env::var("CARGO_PKG_NAME")
+                .unwrap_or_default()
+                .replace('-', "_"),
+        };
+        format!(
+            "  -- Generated bindings ({types}, {ffi})\n  \
+             extra-libraries:  {library}\n  \
+             include-dirs:     {include}\n  \
+             install-includes: {header}",
+            types = self.config.types_module,
+            ffi = self.config.ffi_module,
+            library = library,
+            include = self.header_path().display(),
+            header = self.config.header,
+        )
+    }
+
+    fn header_path(&self) -> &Path {
+        self.header_dir.as_deref().unwrap_or(&self.haskell_dir)
+    }
+}
+
+/// Root directory of the crate whose build script is running
+fn crate_dir() -> PathBuf {
+    env::var_os("CARGO_MANIFEST_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_default()
+}
+
+/*******************************************************************************
+  Sanity checks
+*******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cabal_stanza() {
+        let mut bindings = Bindings::new();
+        bindings
+            .types_module("Example.Types")
+            .ffi_module("Example.FFI")
+            .header("example.h")
+            .haskell_dir("example-hs/src")
+            .header_dir("example-hs/cbits")
+            .library("example");
+        assert_eq!(
+            bindings.cabal_stanza(),
+            "  -- Generated bindings (Example.Types, Example.FFI)
+  extra-libraries:  example
+  include-dirs:     example-hs/cbits
+  install-includes: example.h"
+        );
+    }
+
+    #[test]
+    fn generated_files() -> Result<()> {
+        let mut bindings = Bindings::new();
+        bindings
+            .types_module("Example.Types")
+            .ffi_module("Example.FFI")
+            .header("example.h")
+            .haskell_dir("example-hs/src");
+        let source_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/build");
+        let paths: Vec<PathBuf> = bindings
+            .generated_files(&source_dir)?
+            .into_iter()
+            .map(|file| file.path)
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                PathBuf::from("example-hs/src/Example/Types.hs"),
+                PathBuf::from("example-hs/src/Example/FFI.hs"),
+                PathBuf::from("example-hs/src/example.h"),
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,1028 @@
+//! Reading the schema from the source code of a Rust crate
+//!
+//! This is used by build scripts, which run before the crate is compiled, and
+//! by `haskell-ffi-gen` as an alternative to a schema dump. We look for types
+//! with `#[derive(HaskellType)]` and functions with `#[haskell_export]`, and
+//! interpret their definitions the same way the derives do. Since we only see
+//! the syntax, we make the following assumptions:
+//!
+//! - Types are identified by their name; a type without a `HaskellType` derive
+//!   in the crate is opaque (unless it is one of the standard types), and must
+//!   be mapped onto a Haskell type (see `codegen::type_map`).
+//! - Fields using `#[haskell(with = "module")]` are not supported, since their
+//!   description is only known to `module::haskell_type`; such crates must
+//!   use a schema dump instead.
+//! - The marshalling of arguments and results is determined by the standard
+//!   `HaskellSize` and `HaskellMaxSize` instances, and by the derives in the
+//!   crate. Hand-written instances are not taken into account.
+//! - Tags are ignored.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    codegen::CodegenError,
+    error::{Error, Result},
+    naming::{self, RenameRule},
+    schema::{
+        Fields, FunctionArg, FunctionDecl, FunctionResult, Marshalling, NamedField, Primitive,
+        SchemaDump, TypeBody, TypeDecl, TypeExpr, Variant,
+    },
+};
+use quote::ToTokens;
+use syn::{
+    Attribute, Data, DeriveInput, Expr, FnArg, GenericArgument, Item, ItemFn, Lit, Meta,
+    NestedMeta, Pat, PathArguments, ReturnType, Type,
+};
+
+/// Read the schema from all source files in `src_dir`
+pub fn parse_crate(src_dir: &Path) -> Result<SchemaDump> {
+    let mut files = Vec::new();
+    collect_files(src_dir, &mut files)?;
+    files.sort();
+
+    let mut items = Items::default();
+    for file in &files {
+        let source = fs::read_to_string(file)?;
+        let parsed = syn::parse_file(&source)
+            .map_err(|err| CodegenError::new(format!("{}: {}", file.display(), err)))?;
+        let module = module_for_file(src_dir, file);
+        items.collect(&module, parsed.items);
+    }
+    items.into_dump()
+}
+
+/// Read the schema from a single source file
+#[cfg(test)]
+fn parse_source(source: &str) -> Result<SchemaDump> {
+    let parsed = syn::parse_file(source).map_err(|err| CodegenError::new(err.to_string()))?;
+    let mut items = Items::default();
+    items.collect("crate", parsed.items);
+    items.into_dump()
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Rust module path for a source file (`src/foo/bar.rs` becomes `crate::foo::bar`)
+fn module_for_file(src_dir: &Path, file: &Path) -> String {
+    let mut module = vec!["crate".to_string()];
+    if let Ok(relative) = file.strip_prefix(src_dir) {
+        let relative = relative.with_extension("");
+        for component in relative.iter() {
+            let component = component.to_string_lossy();
+            if !matches!(&*component, "lib" | "main" | "mod") {
+                module.push(component.into_owned());
+            }
+        }
+    }
+    module.join("::")
+}
+
+/*******************************************************************************
+  Collecting items
+*******************************************************************************/
+
+#[derive(Default)]
+struct Items {
+    /// Types deriving `HaskellType`, with the module they are defined in
+    types: Vec<(String, DeriveInput)>,
+
+    /// Exported functions
+    functions: Vec<ItemFn>,
+
+    /// Names of types deriving `HaskellSize` and `HaskellMaxSize`
+    fixed_size: HashSet<String>,
+    max_size: HashSet<String>,
+}
+
+impl Items {
+    fn collect(&mut self, module: &str, items: Vec<Item>) {
+        for item in items {
+            let input = match item {
+                Item::Struct(item) => DeriveInput::from(item),
+                Item::Enum(item) => DeriveInput::from(item),
+                Item::Fn(item) if item.attrs.iter().any(is_export) => {
+                    self.functions.push(item);
+                    continue;
+                }
+                Item::Mod(item) => {
+                    if let Some((_, items)) = item.content {
+                        self.collect(&format!("{}::{}", module, item.ident), items);
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            let derives = derives(&input.attrs);
+            let name = input.ident.to_string();
+            if derives.contains("HaskellSize") {
+                self.fixed_size.insert(name.clone());
+            }
+            if derives.contains("HaskellMaxSize") {
+                self.max_size.insert(name);
+            }
+            if derives.contains("HaskellType") {
+                self.types.push((module.to_string(), input));
+            }
+        }
+    }
+
+    fn into_dump(self) -> Result<SchemaDump> {
+        let known: HashMap<String, KnownType> = self
+            .types
+            .iter()
+            .map(|(_, input)| {
+                let known = KnownType {
+                    haskell_name: rename(&input.attrs).unwrap_or_else(|| input.ident.to_string()),
+                    params: input.generics.type_params().count(),
+                    used_params: used_params(input),
+                };
+                (input.ident.to_string(), known)
+            })
+            .collect();
+        let mapped: HashMap<String, String> = self
+            .types
+            .iter()
+            .filter(|(_, input)| maps_to(&input.attrs).is_some())
+            .map(|(module, input)| {
+                let rust_path = format!("{}::{}", module, input.ident);
+                (input.ident.to_string(), rust_path)
+            })
+            .collect();
+        let ctx = Context {
+            known: &known,
+            mapped: &mapped,
+            fixed_size: &self.fixed_size,
+            max_size: &self.max_size,
+        };
+
+        let mut dump = SchemaDump::new();
+        for (module, input) in &self.types {
+            let name = &known[&input.ident.to_string()].haskell_name;
+            let rust_path = format!("{}::{}", module, input.ident);
+            if let Some(haskell_name) = maps_to(&input.attrs) {
+                dump.schema.map_type(&rust_path, &haskell_name);
+                continue;
+            }
+            if dump.schema.reserve(name, &rust_path) {
+                dump.schema
+                    .insert(ctx.type_decl(input, name.clone(), rust_path)?);
+            }
+        }
+        for function in &self.functions {
+            dump.functions.push(ctx.function_decl(function)?);
+        }
+        dump.functions.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        dump.add_schema_check();
+        Ok(dump)
+    }
+}
+
+/// Names of the derived traits
+fn derives(attrs: &[Attribute]) -> HashSet<String> {
+    let mut derives = HashSet::new();
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("derive")) {
+        if let Ok(Meta::List(list)) = attr.parse_meta() {
+            for nested in list.nested {
+                if let NestedMeta::Meta(meta) = nested {
+                    if let Some(ident) = meta.path().segments.last() {
+                        derives.insert(ident.ident.to_string());
+                    }
+                }
+            }
+        }
+    }
+    derives
+}
+
+fn is_export(attr: &Attribute) -> bool {
+    attr.path
+        .segments
+        .last()
+        .is_some_and(|segment| segment.ident == "haskell_export")
+}
+
+/// Contents of the `#[haskell(..)]` attributes
+fn haskell_metas(attrs: &[Attribute]) -> Vec<Meta> {
+    let mut metas = Vec::new();
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("haskell")) {
+        if let Ok(Meta::List(list)) = attr.parse_meta() {
+            for nested in list.nested {
+                if let NestedMeta::Meta(meta) = nested {
+                    metas.push(meta);
+                }
+            }
+        }
+    }
+    metas
+}
+
+fn string_value(meta: &Meta) -> Option<String> {
+    match meta {
+        Meta::NameValue(nv) => match &nv.lit {
+            Lit::Str(s) => Some(s.value()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Indices of the type parameters used in fields with the default encoding
+///
+/// As in the derive, the other type parameters (such as `T` in a skipped
+/// `PhantomData<T>`) are left out of the Haskell type.
+fn used_params(input: &DeriveInput) -> Vec<usize> {
+    let fields: Vec<&syn::Field> = match &input.data {
+        Data::Struct(data) => data.fields.iter().collect(),
+        Data::Enum(data) => data.variants.iter().flat_map(|v| &v.fields).collect(),
+        Data::Union(_) => Vec::new(),
+    };
+    let mut names = HashSet::new();
+    for field in fields {
+        if let FieldEncoding::Default = field_encoding(&field.attrs) {
+            names_in_type(&field.ty, &mut names);
+        }
+    }
+    input
+        .generics
+        .type_params()
+        .enumerate()
+        .filter(|(_, param)| names.contains(&param.ident.to_string()))
+        .map(|(ix, _)| ix)
+        .collect()
+}
+
+/// Names that might refer to type parameters, other than inside `PhantomData`
+fn names_in_type(ty: &Type, acc: &mut HashSet<String>) {
+    match ty {
+        Type::Reference(reference) => names_in_type(&reference.elem, acc),
+        Type::Paren(paren) => names_in_type(&paren.elem, acc),
+        Type::Group(group) => names_in_type(&group.elem, acc),
+        Type::Array(array) => names_in_type(&array.elem, acc),
+        Type::Slice(slice) => names_in_type(&slice.elem, acc),
+        Type::Tuple(tuple) => tuple.elems.iter().for_each(|ty| names_in_type(ty, acc)),
+        Type::Path(path) => {
+            if let Some(qself) = &path.qself {
+                names_in_type(&qself.ty, acc);
+            }
+            if let Some(first) = path.path.segments.first() {
+                acc.insert(first.ident.to_string());
+            }
+            if path
+                .path
+                .segments
+                .last()
+                .is_some_and(|segment| segment.ident == "PhantomData")
+            {
+                return;
+            }
+            for segment in &path.path.segments {
+                for arg in type_args(&segment.arguments) {
+                    names_in_type(arg, acc);
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+/*******************************************************************************
+  Interpreting items
+*******************************************************************************/
+
+/// Type deriving `HaskellType`
+struct KnownType {
+    haskell_name: String,
+
+    /// Number of type parameters of the Rust type
+    params: usize,
+
+    /// Indices of the type parameters that are parameters of the Haskell type
+    /// (see `used_params`)
+    used_params: Vec<usize>,
+}
+
+struct Context<'a> {
+    /// Types deriving `HaskellType`, by their Rust name
+    known: &'a HashMap<String, KnownType>,
+
+    /// Types mapped onto existing Haskell types, with their Rust path
+    mapped: &'a HashMap<String, String>,
+    fixed_size: &'a HashSet<String>,
+    max_size: &'a HashSet<String>,
+}
+
+/// How a field is encoded
+enum FieldEncoding {
+    Default,
+    Borsh,
+    Bincode,
+    With,
+    Skip,
+}
+
+/// Haskell name of a type, variant or field (`#[haskell(rename = "..")]`)
+fn rename(attrs: &[Attribute]) -> Option<String> {
+    haskell_metas(attrs)
+        .iter()
+        .filter(|meta| meta.path().is_ident("rename"))
+        .find_map(string_value)
+}
+
+/// Existing Haskell type for a type (`#[haskell(maps_to = "..")]`)
+fn maps_to(attrs: &[Attribute]) -> Option<String> {
+    haskell_metas(attrs)
+        .iter()
+        .filter(|meta| meta.path().is_ident("maps_to"))
+        .find_map(string_value)
+}
+
+/// Naming of record fields (`rename_all` and `field_prefix`)
+#[derive(Default)]
+struct FieldNaming {
+    rename_all: Option<RenameRule>,
+    prefix: Option<String>,
+}
+
+impl FieldNaming {
+    fn from_attrs(attrs: &[Attribute]) -> Self {
+        let mut naming = FieldNaming::default();
+        for meta in haskell_metas(attrs) {
+            if meta.path().is_ident("rename_all") {
+                naming.rename_all =
+                    string_value(&meta).and_then(|rule| RenameRule::from_name(&rule));
+            } else if meta.path().is_ident("field_prefix") {
+                naming.prefix = string_value(&meta);
+            }
+        }
+        naming
+    }
+}
+
+fn field_encoding(attrs: &[Attribute]) -> FieldEncoding {
+    let mut encoding = FieldEncoding::Default;
+    for meta in haskell_metas(attrs) {
+        if meta.path().is_ident("skip") || meta.path().is_ident("default") {
+            return FieldEncoding::Skip;
+        } else if meta.path().is_ident("with") {
+            encoding = FieldEncoding::With;
+        } else if meta.path().is_ident("via") {
+            encoding = match string_value(&meta).as_deref() {
+                Some("bincode") => FieldEncoding::Bincode,
+                _ => FieldEncoding::Borsh,
+            };
+        }
+    }
+    encoding
+}
+
+impl Context<'_> {
+    fn type_decl(&self, input: &DeriveInput, name: String, rust_path: String) -> Result<TypeDecl> {
+        let naming = FieldNaming::from_attrs(&input.attrs);
+        let all_params: Vec<_> = input.generics.type_params().collect();
+        let params: Vec<String> = used_params(input)
+            .into_iter()
+            .map(|ix| all_params[ix].ident.to_string())
+            .collect();
+        let transparent = haskell_metas(&input.attrs)
+            .iter()
+            .any(|meta| meta.path().is_ident("transparent"));
+
+        let body = match &input.data {
+            Data::Struct(data) if transparent => {
+                let fields = self.fields(input, &data.fields, &naming, &params)?;
+                match fields.types().as_slice() {
+                    [ty] => TypeBody::Transparent((*ty).clone()),
+                    _ => {
+                        return Err(Error::custom(CodegenError::new(format!(
+                            "{}: #[haskell(transparent)] requires exactly one non-skipped field",
+                            input.ident
+                        ))))
+                    }
+                }
+            }
+            Data::Struct(data) => {
+                TypeBody::Struct(self.fields(input, &data.fields, &naming, &params)?)
+            }
+            Data::Enum(data) => TypeBody::Enum(
+                data.variants
+                    .iter()
+                    .map(|variant| {
+                        Ok(Variant {
+                            name: rename(&variant.attrs)
+                                .unwrap_or_else(|| variant.ident.to_string()),
+                            fields: self.fields(input, &variant.fields, &naming, &params)?,
+                        })
+                    })
+                    .collect::<Result<_>>()?,
+            ),
+            Data::Union(_) => {
+                return Err(Error::custom(CodegenError::new(format!(
+                    "{}: HaskellType cannot be derived for unions",
+                    input.ident
+                ))))
+            }
+        };
+
+        Ok(TypeDecl {
+            name,
+            rust_path,
+            params,
+            body,
+        })
+    }
+
+    fn fields(
+        &self,
+        input: &DeriveInput,
+        fields: &syn::Fields,
+        naming: &FieldNaming,
+        params: &[String],
+    ) -> Result<Fields> {
+        let mut named = Vec::new();
+        let mut unnamed = Vec::new();
+        for (ix, field) in fields.iter().enumerate() {
+            let ty = match field_encoding(&field.attrs) {
+                FieldEncoding::Skip => continue,
+                FieldEncoding::Default => self.type_expr(&field.ty, params),
+                FieldEncoding::Borsh => TypeExpr::Borsh(type_string(&field.ty)),
+                FieldEncoding::Bincode => TypeExpr::Bincode(type_string(&field.ty)),
+                FieldEncoding::With => {
+                    let field_name = match &field.ident {
+                        Some(ident) => ident.to_string(),
+                        None => ix.to_string(),
+                    };
+                    return Err(Error::custom(CodegenError::new(format!(
+                        "{}.{}: fields using #[haskell(with = \"..\")] are not supported \
+                         when reading the schema from the source code; use a schema dump",
+                        input.ident, field_name
+                    ))));
+                }
+            };
+            match &field.ident {
+                Some(ident) => named.push(NamedField {
+                    name: rename(&field.attrs).unwrap_or_else(|| {
+                        let prefix = naming.prefix.as_deref();
+                        naming::field_name(&ident.to_string(), naming.rename_all, prefix)
+                    }),
+                    ty,
+                }),
+                None => unnamed.push(ty),
+            }
+        }
+        Ok(match fields {
+            syn::Fields::Named(_) => Fields::Named(named),
+            syn::Fields::Unnamed(_) => Fields::Unnamed(unnamed),
+            syn::Fields::Unit => Fields::Unit,
+        })
+    }
+
+    fn function_decl(&self, item: &ItemFn) -> Result<FunctionDecl> {
+        let mut symbol = item.sig.ident.to_string();
+        let mut external = false;
+        let mut checked = true;
+        for attr in item.attrs.iter().filter(|attr| is_export(attr)) {
+            if let Ok(Meta::List(list)) = attr.parse_meta() {
+                for nested in list.nested {
+                    match nested {
+                        NestedMeta::Meta(meta) if meta.path().is_ident("name") => {
+                            if let Some(name) = string_value(&meta) {
+                                symbol = name;
+                            }
+                        }
+                        NestedMeta::Meta(Meta::Path(path)) if path.is_ident("external") => {
+                            external = true;
+                        }
+                        NestedMeta::Meta(Meta::Path(path)) if path.is_ident("unchecked") => {
+                            checked = false;
+                        }
+                        _ => (),
+                    }
+                }
+            }
+        }
+
+        let mut args = Vec::new();
+        for (ix, arg) in item.sig.inputs.iter().enumerate() {
+            let arg = match arg {
+                FnArg::Typed(arg) => arg,
+                FnArg::Receiver(_) => {
+                    return Err(Error::custom(CodegenError::new(format!(
+                        "{}: cannot export methods",
+                        item.sig.ident
+                    ))))
+                }
+            };
+            let name = match &*arg.pat {
+                Pat::Ident(pat) => pat.ident.to_string(),
+                _ => format!("arg{}", ix),
+            };
+            let ty = strip_reference(&arg.ty);
+            let marshalling = if self.is_fixed_size(ty) {
+                Marshalling::Fixed
+            } else {
+                Marshalling::Var
+            };
+            args.push(FunctionArg {
+                name,
+                ty: self.type_expr(ty, &[]),
+                marshalling,
+            });
+        }
+
+        let result = match &item.sig.output {
+            ReturnType::Type(_, ty) if !is_unit(ty) => {
+                let marshalling = if external {
+                    Marshalling::External
+                } else if self.is_fixed_size(ty) {
+                    Marshalling::Fixed
+                } else if self.is_max_size(ty) {
+                    Marshalling::Max
+                } else {
+                    Marshalling::Var
+                };
+                Some(FunctionResult {
+                    ty: self.type_expr(ty, &[]),
+                    marshalling,
+                })
+            }
+            _ => None,
+        };
+
+        Ok(FunctionDecl {
+            symbol,
+            args,
+            result,
+            checked,
+        })
+    }
+
+    fn type_expr(&self, ty: &Type, params: &[String]) -> TypeExpr {
+        let opaque = || TypeExpr::Opaque(type_string(ty));
+        match ty {
+            Type::Reference(reference) => self.type_expr(&reference.elem, params),
+            Type::Paren(paren) => self.type_expr(&paren.elem, params),
+            Type::Group(group) => self.type_expr(&group.elem, params),
+            Type::Tuple(tuple) if tuple.elems.is_empty() => TypeExpr::Primitive(Primitive::Unit),
+            Type::Tuple(tuple) => TypeExpr::Tuple(
+                tuple
+                    .elems
+                    .iter()
+                    .map(|ty| self.type_expr(ty, params))
+                    .collect(),
+            ),
+            // Exported functions decode `&[T]` as `Vec<T>`
+            Type::Slice(slice) => TypeExpr::Vec(Box::new(self.type_expr(&slice.elem, params))),
+            Type::Array(array) => match array_len(&array.len) {
+                Some(n) => TypeExpr::Array(Box::new(self.type_expr(&array.elem, params)), n),
+                None => opaque(),
+            },
+            Type::Path(path) if path.qself.is_none() => {
+                let segment = match path.path.segments.last() {
+                    Some(segment) => segment,
+                    None => return opaque(),
+                };
+                let name = segment.ident.to_string();
+                let args = type_args(&segment.arguments);
+                let arg = |ix: usize| Box::new(self.type_expr(args[ix], params));
+
+                if path.path.segments.len() == 1 {
+                    if let Some(ix) = params.iter().position(|param| *param == name) {
+                        return TypeExpr::Var(ix);
+                    }
+                }
+                if let Some(prim) = primitive(&name) {
+                    return TypeExpr::Primitive(prim);
+                }
+                match (name.as_str(), args.len()) {
+                    ("Vec", 1) => TypeExpr::Vec(arg(0)),
+                    ("Option", 1) => TypeExpr::Option(arg(0)),
+                    ("Result", 2) => TypeExpr::Result(arg(0), arg(1)),
+                    ("HashMap", 2) => TypeExpr::HashMap(arg(0), arg(1)),
+                    ("HashSet", 1) => TypeExpr::HashSet(arg(0)),
+                    ("UseBorsh", 1) => TypeExpr::Borsh(type_string(args[0])),
+                    _ if args.is_empty() && self.mapped.contains_key(&name) => {
+                        TypeExpr::Opaque(self.mapped[&name].clone())
+                    }
+                    _ => match self.known.get(&name) {
+                        Some(known) if known.params == args.len() => TypeExpr::Named {
+                            name: known.haskell_name.clone(),
+                            args: known
+                                .used_params
+                                .iter()
+                                .map(|ix| self.type_expr(args[*ix], params))
+                                .collect(),
+                        },
+                        _ => opaque(),
+                    },
+                }
+            }
+            _ => opaque(),
+        }
+    }
+
+    /// Does the type have a `HaskellSize` instance?
+    fn is_fixed_size(&self, ty: &Type) -> bool {
+        match ty {
+            Type::Paren(paren) => self.is_fixed_size(&paren.elem),
+            Type::Group(group) => self.is_fixed_size(&group.elem),
+            Type::Tuple(tuple) => tuple.elems.iter().all(|ty| self.is_fixed_size(ty)),
+            Type::Array(array) => self.is_fixed_size(&array.elem),
+            Type::Path(path) => match path.path.segments.last() {
+                Some(segment) => {
+                    let name = segment.ident.to_string();
+                    match primitive(&name) {
+                        Some(Primitive::String) => false,
+                        Some(_) => true,
+                        None => self.fixed_size.contains(&name),
+                    }
+                }
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Does the type have a `HaskellMaxSize` instance?
+    fn is_max_size(&self, ty: &Type) -> bool {
+        match ty {
+            Type::Paren(paren) => self.is_max_size(&paren.elem),
+            Type::Group(group) => self.is_max_size(&group.elem),
+            Type::Array(array) => self.is_max_size(&array.elem),
+            Type::Path(path) => match path.path.segments.last() {
+                Some(segment) => {
+                    let name = segment.ident.to_string();
+                    let args = type_args(&segment.arguments);
+                    match (name.as_str(), args.len()) {
+                        ("Option", 1) | ("Result", 2) => args.iter().all(|ty| self.is_max_size(ty)),
+                        _ => self.is_fixed_size(ty) || self.max_size.contains(&name),
+                    }
+                }
+                None => false,
+            },
+            ty => self.is_fixed_size(ty),
+        }
+    }
+}
+
+fn primitive(name: &str) -> Option<Primitive> {
+    Some(match name {
+        "u8" => Primitive::U8,
+        "u16" => Primitive::U16,
+        "u32" => Primitive::U32,
+        "u64" => Primitive::U64,
+        "u128" => Primitive::U128,
+        "i8" => Primitive::I8,
+        "i16" => Primitive::I16,
+        "i32" => Primitive::I32,
+        "i64" => Primitive::I64,
+        "i128" => Primitive::I128,
+        "f32" => Primitive::F32,
+        "f64" => Primitive::F64,
+        "bool" => Primitive::Bool,
+        // Exported functions decode `&str` as `String`
+        "String" | "str" => Primitive::String,
+        _ => return None,
+    })
+}
+
+fn type_args(arguments: &PathArguments) -> Vec<&Type> {
+    match arguments {
+        PathArguments::AngleBracketed(args) => args
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn array_len(expr: &Expr) -> Option<usize> {
+    match expr {
+        Expr::Lit(lit) => match &lit.lit {
+            Lit::Int(n) => n.base10_parse().ok(),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn strip_reference(ty: &Type) -> &Type {
+    match ty {
+        Type::Reference(reference) if reference.mutability.is_none() => &reference.elem,
+        ty => ty,
+    }
+}
+
+fn is_unit(ty: &Type) -> bool {
+    matches!(ty, Type::Tuple(tuple) if tuple.elems.is_empty())
+}
+
+/// Rust type as written in the source (without spaces)
+fn type_string(ty: &Type) -> String {
+    ty.to_token_stream().to_string().replace(' ', "")
+}
+
+/*******************************************************************************
+  Tests
+*******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        export::exports, fingerprint::SignatureFingerprint, FromHaskell, HaskellSize, HaskellType,
+        ToHaskell,
+    };
+
+    const SOURCE: &str = r#"
+        use haskell_ffi::{haskell_export, HaskellSize, HaskellType, ToHaskell};
+
+        #[derive(HaskellType, HaskellSize, ToHaskell, FromHaskell)]
+        #[haskell(rename = "Point2D", field_prefix = "point")]
+        pub struct Point {
+            x: i32,
+            #[haskell(rename = "ordinate")]
+            y: i32,
+        }
+
+        #[derive(HaskellType, ToHaskell)]
+        pub enum Shape<T> {
+            Empty,
+            Polygon(Vec<Point>),
+            Circle { center: Point, radius: T },
+        }
+
+        #[derive(HaskellType)]
+        #[haskell(transparent)]
+        pub struct Label(#[haskell(skip)] u8, String);
+
+        #[derive(HaskellType, ToHaskell)]
+        #[haskell(maps_to = "Example.Color.Color")]
+        pub struct Color(u8, u8, u8);
+
+        #[derive(HaskellType, ToHaskell)]
+        pub struct Style {
+            color: Color,
+            #[haskell(via = "borsh")]
+            owner: Pubkey,
+        }
+
+        #[haskell_export(name = "shape_area")]
+        fn area(shape: &Shape<f64>) -> Option<f64> {
+            None
+        }
+
+        #[haskell_export]
+        fn origin() -> Point {
+            Point { x: 0, y: 0 }
+        }
+
+        #[haskell_export(external, unchecked)]
+        fn label(raw: Vec<u8>) -> Label {
+            unimplemented!()
+        }
+    "#;
+
+    #[test]
+    fn types() -> Result<()> {
+        let dump = parse_source(SOURCE)?;
+        let names: Vec<&str> = dump
+            .schema
+            .decls()
+            .iter()
+            .map(|d| d.name.as_str())
+            .collect();
+        assert_eq!(names, ["Point2D", "Shape", "Label", "Style"]);
+
+        let point = dump.schema.get("Point2D").unwrap();
+        assert_eq!(
+            point.body,
+            TypeBody::Struct(Fields::Named(vec![
+                NamedField {
+                    name: "pointX".to_string(),
+                    ty: TypeExpr::Primitive(Primitive::I32),
+                },
+                NamedField {
+                    name: "ordinate".to_string(),
+                    ty: TypeExpr::Primitive(Primitive::I32),
+                },
+            ]))
+        );
+
+        let shape = dump.schema.get("Shape").unwrap();
+        assert_eq!(shape.params, ["T"]);
+        assert_eq!(
+            shape.body,
+            TypeBody::Enum(vec![
+                Variant {
+                    name: "Empty".to_string(),
+                    fields: Fields::Unit,
+                },
+                Variant {
+                    name: "Polygon".to_string(),
+                    fields: Fields::Unnamed(vec![TypeExpr::Vec(Box::new(TypeExpr::Named {
+                        name: "Point2D".to_string(),
+                        args: vec![],
+                    }))]),
+                },
+                Variant {
+                    name: "Circle".to_string(),
+                    fields: Fields::Named(vec![
+                        NamedField {
+                            name: "center".to_string(),
+                            ty: TypeExpr::Named {
+                                name: "Point2D".to_string(),
+                                args: vec![],
+                            },
+                        },
+                        NamedField {
+                            name: "radius".to_string(),
+                            ty: TypeExpr::Var(0),
+                        },
+                    ]),
+                },
+            ])
+        );
+
+        let label = dump.schema.get("Label").unwrap();
+        assert_eq!(
+            label.body,
+            TypeBody::Transparent(TypeExpr::Primitive(Primitive::String))
+        );
+
+        // Mapped types are not declared, but referred to by their Rust path
+        assert_eq!(
+            dump.schema
+                .mapped_types()
+                .get("crate::Color")
+                .map(String::as_str),
+            Some("Example.Color.Color")
+        );
+        let style = dump.schema.get("Style").unwrap();
+        assert_eq!(
+            style.body,
+            TypeBody::Struct(Fields::Named(vec![
+                NamedField {
+                    name: "color".to_string(),
+                    ty: TypeExpr::Opaque("crate::Color".to_string()),
+                },
+                NamedField {
+                    name: "owner".to_string(),
+                    ty: TypeExpr::Borsh("Pubkey".to_string()),
+                },
+            ]))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn phantom_params() -> Result<()> {
+        let dump = parse_source(
+            r#"
+            #[derive(HaskellType)]
+            pub struct Tagged<M, T> {
+                value: T,
+                #[haskell(skip)]
+                marker: PhantomData<M>,
+            }
+
+            #[derive(HaskellType)]
+            pub struct Wrapper(Tagged<Marker, u8>);
+            "#,
+        )?;
+        assert_eq!(dump.schema.get("Tagged").unwrap().params, ["T"]);
+        assert_eq!(
+            dump.schema.get("Wrapper").unwrap().body,
+            TypeBody::Struct(Fields::Unnamed(vec![TypeExpr::Named {
+                name: "Tagged".to_string(),
+                args: vec![TypeExpr::Primitive(Primitive::U8)],
+            }]))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn borrowed_args() -> Result<()> {
+        let dump = parse_source(
+            r#"
+            #[haskell_export]
+            fn join(sep: &str, words: &[String]) -> String {
+                words.join(sep)
+            }
+            "#,
+        )?;
+        let join = dump.functions.iter().find(|f| f.symbol == "join").unwrap();
+        let string = TypeExpr::Primitive(Primitive::String);
+        assert_eq!(join.args[0].ty, string);
+        assert_eq!(join.args[1].ty, TypeExpr::Vec(Box::new(string)));
+        assert_eq!(join.args[1].marshalling, Marshalling::Var);
+        Ok(())
+    }
+
+    #[test]
+    fn with_field() {
+        let err = parse_source(
+            r#"
+            #[derive(HaskellType)]
+            pub struct Slot {
+                #[haskell(with = "narrow")]
+                slot: u64,
+            }
+            "#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Slot.slot: fields using #[haskell(with = \"..\")] are not supported when \
+             reading the schema from the source code; use a schema dump"
+        );
+    }
+
+    /// Same wire format as `Point` in `SOURCE`
+    #[derive(HaskellType, HaskellSize, ToHaskell, FromHaskell)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    #[crate::haskell_export(name = "source_test_origin")]
+    fn origin() -> Point {
+        Point { x: 0, y: 0 }
+    }
+
+    #[test]
+    fn fingerprints() -> Result<()> {
+        let dump = parse_source(SOURCE)?;
+        let origin = dump
+            .functions
+            .iter()
+            .find(|f| f.symbol == "origin")
+            .unwrap();
+        let parsed = SignatureFingerprint::new(&dump.schema, origin);
+
+        let export = exports()
+            .into_iter()
+            .find(|export| export.symbol == "source_test_origin")
+            .unwrap();
+        let derived = SignatureFingerprint::of_export(export);
+        assert_eq!(parsed.signature, derived.signature);
+        assert_eq!(parsed.types[0].1, derived.types[0].1);
+        Ok(())
+    }
+
+    #[test]
+    fn functions() -> Result<()> {
+        let dump = parse_source(SOURCE)?;
+        let summary: Vec<(&str, Vec<Marshalling>, Option<Marshalling>)> = dump
+            .functions
+            .iter()
+            .map(|f| {
+                (
+                    f.symbol.as_str(),
+                    f.args.iter().map(|arg| arg.marshalling).collect(),
+                    f.result.as_ref().map(|result| result.marshalling),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (
+                    "haskell_ffi_check_schema",
+                    vec![Marshalling::Var],
+                    Some(Marshalling::Var)
+                ),
+                ("label", vec![Marshalling::Var], Some(Marshalling::External)),
+                ("origin", vec![], Some(Marshalling::Fixed)),
+                ("shape_area", vec![Marshalling::Var], Some(Marshalling::Max)),
+            ]
+        );
+        assert_eq!(
+            dump.functions[3].args[0].ty,
+            TypeExpr::Named {
+                name: "Shape".to_string(),
+                args: vec![TypeExpr::Primitive(Primitive::F64)],
+            }
+        );
+        let checked: Vec<bool> = dump.functions.iter().map(|f| f.checked).collect();
+        assert_eq!(checked, [true, false, true, true]);
+        Ok(())
+    }
+}
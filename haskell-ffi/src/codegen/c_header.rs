@@ -0,0 +1,266 @@
+//! Generating a C header for the exported functions
+//!
+//! The header declares the functions used to access Rust-allocated buffers
+//! (`haskell_ffi_external_ptr` and friends) and the last error
+//! (`haskell_ffi_last_error_ptr` and friends), as well as every function
+//! exported using `#[haskell_export]`. It is needed by the `capi` foreign
+//! imports generated by `codegen::foreign`, but can also be used by other C
+//! consumers of the library:
+//!
+//! ```ignore
+//! let mut header = CHeader::new("example.h");
+//! header.add_exports();
+//! header.write_to(Path::new("cbits"))?;
+//! ```
+
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    error::Result,
+    export::{exports, HaskellExport},
+    schema::{FunctionDecl, Marshalling, Schema},
+};
+
+/*******************************************************************************
+  Headers
+*******************************************************************************/
+
+/// C header declaring a set of exported functions
+pub struct CHeader {
+    file_name: String,
+    functions: Vec<FunctionDecl>,
+}
+
+impl CHeader {
+    /// New header, to be written to a file with the specified name
+    pub fn new(file_name: &str) -> Self {
+        CHeader {
+            file_name: file_name.to_string(),
+            functions: Vec::new(),
+        }
+    }
+
+    /// Add all functions exported using `#[haskell_export]`
+    pub fn add_exports(&mut self) -> &mut Self {
+        for export in exports() {
+            self.add_export(export);
+        }
+        self
+    }
+
+    pub fn add_export(&mut self, export: &HaskellExport) -> &mut Self {
+        // The C types of the arguments do not depend on their Rust types
+        let decl = (export.describe)(&mut Schema::new());
+        self.add_function(decl)
+    }
+
+    pub fn add_function(&mut self, decl: FunctionDecl) -> &mut Self {
+        self.functions.push(decl);
+        self
+    }
+
+    pub fn file_name(&self) -> &str {
+        &self.file_name
+    }
+
+    pub fn render(&self) -> Result<String> {
+        render_header(&self.file_name, &self.functions)
+    }
+
+    /// Write the header to `dir`
+    ///
+    /// Returns the path to the file.
+    pub fn write_to(&self, dir: &Path) -> Result<PathBuf> {
+        let path = dir.join(&self.file_name);
+        fs::create_dir_all(dir)?;
+        fs::write(&path, self.render()?)?;
+        Ok(path)
+    }
+}
+
+/// Render a C header declaring the specified functions
+pub fn render_header(file_name: &str, functions: &[FunctionDecl]) -> Result<String> {
+    let guard = include_guard(file_name);
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "/* Generated by haskell-ffi from the exported Rust functions. Do not edit. */"
+    )?;
+    writeln!(out)?;
+    writeln!(out, "#ifndef {}", guard)?;
+    writeln!(out, "#define {}", guard)?;
+    writeln!(out)?;
+    writeln!(out, "#include <stddef.h>")?;
+    writeln!(out, "#include <stdint.h>")?;
+    writeln!(out)?;
+    writeln!(out, "#ifdef __cplusplus")?;
+    writeln!(out, "extern \"C\" {{")?;
+    writeln!(out, "#endif")?;
+    writeln!(out)?;
+    writeln!(out, "/* Rust-allocated buffer (opaque) */")?;
+    writeln!(out, "typedef struct ExternalBuffer ExternalBuffer;")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "/* Status returned by exported functions (haskell_ffi::error::Status) */"
+    )?;
+    writeln!(out, "typedef enum haskell_ffi_status {{")?;
+    writeln!(out, "  HASKELL_FFI_OK = 0,")?;
+    writeln!(out, "  HASKELL_FFI_ERROR = 1,")?;
+    writeln!(out, "  HASKELL_FFI_PANIC = 2")?;
+    writeln!(out, "}} haskell_ffi_status;")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "const uint8_t *haskell_ffi_external_ptr(ExternalBuffer *vec);"
+    )?;
+    writeln!(out, "size_t haskell_ffi_external_len(ExternalBuffer *vec);")?;
+    writeln!(out, "void haskell_ffi_external_free(ExternalBuffer *vec);")?;
+    writeln!(out)?;
+    writeln!(out, "/* Last error on the current thread */")?;
+    writeln!(out, "haskell_ffi_status haskell_ffi_last_error_kind(void);")?;
+    writeln!(out, "const uint8_t *haskell_ffi_last_error_ptr(void);")?;
+    writeln!(out, "size_t haskell_ffi_last_error_len(void);")?;
+    writeln!(out, "void haskell_ffi_last_error_clear(void);")?;
+    for function in functions {
+        writeln!(out)?;
+        writeln!(out, "{};", render_prototype(function))?;
+    }
+    writeln!(out)?;
+    writeln!(out, "#ifdef __cplusplus")?;
+    writeln!(out, "}}")?;
+    writeln!(out, "#endif")?;
+    writeln!(out)?;
+    writeln!(out, "#endif /* {} */", guard)?;
+    Ok(out)
+}
+
+/// Prototype of the `extern "C"` wrapper generated by `#[haskell_export]`
+fn render_prototype(decl: &FunctionDecl) -> String {
+    let mut params = Vec::new();
+    for arg in &decl.args {
+        params.push(format!("const uint8_t *{}_ptr", arg.name));
+        params.push(format!("size_t {}_len", arg.name));
+    }
+    let mut result = if decl.checked {
+        "haskell_ffi_status "
+    } else {
+        "void "
+    };
+    match decl.result.as_ref().map(|result| result.marshalling) {
+        None => (),
+        Some(Marshalling::External) if decl.checked => {
+            params.push("ExternalBuffer **out".to_string());
+        }
+        Some(Marshalling::External) => result = "ExternalBuffer *",
+        Some(_) => {
+            params.push("uint8_t *out".to_string());
+            params.push("size_t *out_len".to_string());
+        }
+    }
+    if params.is_empty() {
+        params.push("void".to_string());
+    }
+    format!("{}{}({})", result, decl.symbol, params.join(", "))
+}
+
+/// Include guard for the header (`example.h` becomes `EXAMPLE_H`)
+fn include_guard(file_name: &str) -> String {
+    let mut guard: String = file_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if guard.starts_with(|c: char| c.is_ascii_digit()) {
+        guard.insert(0, '_');
+    }
+    guard
+}
+
+/*******************************************************************************
+  Tests
+*******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[crate::haskell_export(name = "c_header_test_scale")]
+    fn scale(factor: u32, values: Vec<u32>) -> Vec<u32> {
+        values.into_iter().map(|v| v * factor).collect()
+    }
+
+    #[crate::haskell_export(name = "c_header_test_range", external)]
+    fn range(n: u8) -> Vec<u8> {
+        (0..n).collect()
+    }
+
+    #[crate::haskell_export(name = "c_header_test_reset", unchecked)]
+    fn reset() {}
+
+    #[test]
+    fn render() -> Result<()> {
+        let mut header = CHeader::new("example-ffi.h");
+        for export in exports() {
+            if export.symbol.starts_with("c_header_test_") {
+                header.add_export(export);
+            }
+        }
+        let expected = r#"/* Generated by haskell-ffi from the exported Rust functions. Do not edit. */
+
+#ifndef EXAMPLE_FFI_H
+#define EXAMPLE_FFI_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Rust-allocated buffer (opaque) */
+typedef struct ExternalBuffer ExternalBuffer;
+
+/* Status returned by exported functions (haskell_ffi::error::Status) */
+typedef enum haskell_ffi_status {
+  HASKELL_FFI_OK = 0,
+  HASKELL_FFI_ERROR = 1,
+  HASKELL_FFI_PANIC = 2
+} haskell_ffi_status;
+
+const uint8_t *haskell_ffi_external_ptr(ExternalBuffer *vec);
+size_t haskell_ffi_external_len(ExternalBuffer *vec);
+void haskell_ffi_external_free(ExternalBuffer *vec);
+
+/* Last error on the current thread */
+haskell_ffi_status haskell_ffi_last_error_kind(void);
+const uint8_t *haskell_ffi_last_error_ptr(void);
+size_t haskell_ffi_last_error_len(void);
+void haskell_ffi_last_error_clear(void);
+
+haskell_ffi_status c_header_test_range(const uint8_t *n_ptr, size_t n_len, ExternalBuffer **out);
+
+void c_header_test_reset(void);
+
+haskell_ffi_status c_header_test_scale(const uint8_t *factor_ptr, size_t factor_len, const uint8_t *values_ptr, size_t values_len, uint8_t *out, size_t *out_len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* EXAMPLE_FFI_H */
+"#;
+        assert_eq!(header.render()?, expected);
+        Ok(())
+    }
+}
@@ -0,0 +1,552 @@
+//! Generating documentation of the wire format
+//!
+//! The generated Markdown describes the byte layout of every type in the
+//! schema: the offset and size of every field (as far as they are statically
+//! known), how variable-sized values are length-prefixed, and the index of
+//! every enum variant. It is intended as a shared reference when reading
+//! hexdumps of values sent across the FFI boundary:
+//!
+//! ```ignore
+//! let mut docs = WireDocs::new("Example");
+//! docs.add::<ExampleTag, Config>().add_exports();
+//! docs.write_to(Path::new("docs/wire-format.md"))?;
+//! ```
+//!
+//! Sizes are those of the `HaskellSize` instances, as recorded in the schema by
+//! the derived `HaskellType` instances (see `Schema::size`); types without a
+//! `HaskellSize` instance are variable-sized. The size of a field using `with`
+//! is not recorded, but can often be inferred from the size of the type.
+//! Schemas obtained from the source code (see `build`) do not record sizes, so
+//! every type is documented as variable-sized.
+
+use std::{fmt::Write as _, fs, path::Path};
+
+use crate::{
+    error::Result,
+    haskell_type::HaskellType,
+    schema::{Fields, FunctionDecl, Primitive, Schema, SchemaDump, TypeBody, TypeDecl, TypeExpr},
+};
+
+/*******************************************************************************
+  Documents
+*******************************************************************************/
+
+/// Description of the wire format of a set of types and functions
+pub struct WireDocs {
+    title: String,
+    dump: SchemaDump,
+}
+
+impl WireDocs {
+    pub fn new(title: &str) -> Self {
+        WireDocs {
+            title: title.to_string(),
+            dump: SchemaDump::new(),
+        }
+    }
+
+    /// Add `T`, and all types it depends on
+    pub fn add<Tag, T: HaskellType<Tag> + ?Sized>(&mut self) -> &mut Self {
+        self.dump.add::<Tag, T>();
+        self
+    }
+
+    /// Add all functions exported using `#[haskell_export]`
+    pub fn add_exports(&mut self) -> &mut Self {
+        self.dump.add_exports();
+        self
+    }
+
+    /// Document the types and functions in an existing dump
+    pub fn from_dump(title: &str, dump: SchemaDump) -> Self {
+        WireDocs {
+            title: title.to_string(),
+            dump,
+        }
+    }
+
+    pub fn render(&self) -> Result<String> {
+        render_markdown(&self.title, &self.dump)
+    }
+
+    pub fn write_to(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.render()?)?;
+        Ok(())
+    }
+}
+
+/// Render Markdown documentation for all types and functions in the dump
+pub fn render_markdown(title: &str, dump: &SchemaDump) -> Result<String> {
+    let mut out = String::new();
+    writeln!(out, "# Wire format: {}", title)?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "Generated by haskell-ffi from the Rust type definitions. All values are \
+         encoded using Borsh: integers and floats are little-endian, and length \
+         prefixes are `u32`. Offsets and sizes are in bytes."
+    )?;
+
+    if !dump.schema.decls().is_empty() {
+        writeln!(out)?;
+        writeln!(out, "## Types")?;
+    }
+    for decl in dump.schema.decls() {
+        writeln!(out)?;
+        render_decl(&dump.schema, decl, &mut out)?;
+    }
+
+    if !dump.functions.is_empty() {
+        writeln!(out)?;
+        writeln!(out, "## Functions")?;
+        writeln!(out)?;
+        render_functions(&dump.functions, &mut out)?;
+    }
+    Ok(out)
+}
+
+/*******************************************************************************
+  Types
+*******************************************************************************/
+
+fn render_decl(schema: &Schema, decl: &TypeDecl, out: &mut String) -> Result<()> {
+    let head = decl_head(decl);
+    writeln!(out, "### `{}`", head)?;
+    writeln!(out)?;
+
+    let decl_size = schema.size(&TypeExpr::Named {
+        name: decl.name.clone(),
+        args: (0..decl.params.len()).map(TypeExpr::Var).collect(),
+    });
+    let size = match decl_size {
+        Some(size) => format!("fixed size of {} bytes", size),
+        None => "variable size".to_string(),
+    };
+    match &decl.body {
+        TypeBody::Transparent(ty) => {
+            writeln!(
+                out,
+                "Rust type `{}`, {}. Encoded as `{}` (transparent).",
+                decl.rust_path,
+                size,
+                type_name(ty, &decl.params)
+            )?;
+        }
+        TypeBody::Struct(fields) => {
+            writeln!(out, "Rust struct `{}`, {}.", decl.rust_path, size)?;
+            if !fields.types().is_empty() {
+                writeln!(out)?;
+                render_fields(schema, decl, fields, 0, decl_size, out)?;
+            }
+        }
+        TypeBody::Enum(variants) => {
+            writeln!(
+                out,
+                "Rust enum `{}`, {}. Encoded as a 1-byte variant index, followed by the \
+                 fields of the variant.",
+                decl.rust_path, size
+            )?;
+            writeln!(out)?;
+            writeln!(out, "| Index | Variant | Fields |")?;
+            writeln!(out, "|------:|---------|--------|")?;
+            for (ix, variant) in variants.iter().enumerate() {
+                let fields: Vec<String> = field_labels(&variant.fields)
+                    .iter()
+                    .zip(variant.fields.types())
+                    .map(|(label, ty)| format!("{}: `{}`", label, type_name(ty, &decl.params)))
+                    .collect();
+                let fields = if fields.is_empty() {
+                    "(none)".to_string()
+                } else {
+                    fields.join(", ")
+                };
+                writeln!(out, "| {} | `{}` | {} |", ix, variant.name, fields)?;
+            }
+            for (ix, variant) in variants.iter().enumerate() {
+                if variant.fields.types().is_empty() {
+                    continue;
+                }
+                writeln!(out)?;
+                writeln!(out, "Variant `{}` (index {}):", variant.name, ix)?;
+                writeln!(out)?;
+                let payload_size = decl_size.map(|size| size - 1);
+                render_fields(schema, decl, &variant.fields, 1, payload_size, out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Table of fields, with their offset (if statically known)
+///
+/// `total` is the size of all fields together, if known.
+fn render_fields(
+    schema: &Schema,
+    decl: &TypeDecl,
+    fields: &Fields,
+    start: usize,
+    total: Option<usize>,
+    out: &mut String,
+) -> Result<()> {
+    writeln!(out, "| Offset | Size | Field | Type | Encoding |")?;
+    writeln!(out, "|-------:|-----:|-------|------|----------|")?;
+    let mut offset = Some(start);
+    for ((label, ty), size) in field_labels(fields)
+        .iter()
+        .zip(fields.types())
+        .zip(field_sizes(schema, fields, total))
+    {
+        writeln!(
+            out,
+            "| {} | {} | {} | `{}` | {} |",
+            offset.map_or("?".to_string(), |offset| offset.to_string()),
+            size.map_or("variable".to_string(), |size| size.to_string()),
+            label,
+            type_name(ty, &decl.params),
+            encoding(schema, ty, &decl.params)
+        )?;
+        offset = offset.zip(size).map(|(offset, size)| offset + size);
+    }
+    Ok(())
+}
+
+/// Sizes of the fields
+///
+/// If the size of only one field is unknown, it is inferred from the `total`.
+fn field_sizes(schema: &Schema, fields: &Fields, total: Option<usize>) -> Vec<Option<usize>> {
+    let mut sizes: Vec<Option<usize>> = fields
+        .types()
+        .into_iter()
+        .map(|ty| schema.size(ty))
+        .collect();
+    let unknown: Vec<usize> = (0..sizes.len()).filter(|ix| sizes[*ix].is_none()).collect();
+    if let (Some(total), [ix]) = (total, unknown.as_slice()) {
+        let known: usize = sizes.iter().flatten().sum();
+        sizes[*ix] = total.checked_sub(known);
+    }
+    sizes
+}
+
+/// How values of the type are encoded
+fn encoding(schema: &Schema, ty: &TypeExpr, params: &[String]) -> String {
+    match ty {
+        TypeExpr::Primitive(Primitive::Bool) => "1 byte, `0` or `1`".to_string(),
+        TypeExpr::Primitive(Primitive::Unit) => "nothing".to_string(),
+        TypeExpr::Primitive(Primitive::String) => {
+            "length prefix (number of bytes), then UTF-8".to_string()
+        }
+        TypeExpr::Primitive(_) => String::new(),
+        TypeExpr::Vec(_) => "length prefix, then the elements".to_string(),
+        TypeExpr::Option(_) => {
+            "1-byte tag (`0` = `None`, `1` = `Some`), then the value".to_string()
+        }
+        TypeExpr::Result(_, _) => {
+            "1-byte tag (`0` = `Err`, `1` = `Ok`), then the value".to_string()
+        }
+        TypeExpr::Tuple(_) => "the components, in order".to_string(),
+        TypeExpr::Array(_, n) => format!("{} elements, no length prefix", n),
+        TypeExpr::HashMap(_, _) => {
+            "length prefix, then the entries (key, value) sorted by key".to_string()
+        }
+        TypeExpr::HashSet(_) => "length prefix, then the elements in sorted order".to_string(),
+        TypeExpr::Named { name, .. } => match schema.get(name) {
+            Some(decl) => {
+                let head = decl_head(decl);
+                format!("see [`{}`](#{})", head, anchor(&head))
+            }
+            None => format!("see `{}`", name),
+        },
+        TypeExpr::Var(n) => match params.get(*n) {
+            Some(param) => format!("depends on `{}`", param),
+            None => String::new(),
+        },
+        TypeExpr::Borsh(rust_type) => format!("Borsh encoding of `{}`", rust_type),
+        TypeExpr::Bincode(_) => "length prefix, then the bincode encoding".to_string(),
+        TypeExpr::Opaque(rust_type) => format!("see the `ToHaskell` instance of `{}`", rust_type),
+    }
+}
+
+/// Name of the declared type, applied to its type parameters
+fn decl_head(decl: &TypeDecl) -> String {
+    type_name(
+        &TypeExpr::Named {
+            name: decl.name.clone(),
+            args: (0..decl.params.len()).map(TypeExpr::Var).collect(),
+        },
+        &decl.params,
+    )
+}
+
+/// Anchor of the heading for the declaration
+///
+/// This follows the GitHub rules: the heading is lowercased, punctuation is
+/// removed, and spaces become hyphens (`Pair<A, B>` becomes `paira-b`).
+fn anchor(head: &str) -> String {
+    head.to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Names of the fields (or their position, for unnamed fields)
+fn field_labels(fields: &Fields) -> Vec<String> {
+    match fields {
+        Fields::Named(fields) => fields
+            .iter()
+            .map(|field| format!("`{}`", field.name))
+            .collect(),
+        Fields::Unnamed(fields) => (0..fields.len()).map(|ix| ix.to_string()).collect(),
+        Fields::Unit => Vec::new(),
+    }
+}
+
+/// Rust syntax for the type, using the names of the type parameters
+fn type_name(ty: &TypeExpr, params: &[String]) -> String {
+    substitute(ty, params).to_string()
+}
+
+fn substitute(ty: &TypeExpr, params: &[String]) -> TypeExpr {
+    let go = |t: &TypeExpr| Box::new(substitute(t, params));
+    match ty {
+        TypeExpr::Var(n) => match params.get(*n) {
+            Some(param) => TypeExpr::Named {
+                name: param.clone(),
+                args: Vec::new(),
+            },
+            None => ty.clone(),
+        },
+        TypeExpr::Vec(t) => TypeExpr::Vec(go(t)),
+        TypeExpr::Option(t) => TypeExpr::Option(go(t)),
+        TypeExpr::Result(ok, err) => TypeExpr::Result(go(ok), go(err)),
+        TypeExpr::Tuple(ts) => TypeExpr::Tuple(ts.iter().map(|t| substitute(t, params)).collect()),
+        TypeExpr::Array(t, n) => TypeExpr::Array(go(t), *n),
+        TypeExpr::HashMap(k, v) => TypeExpr::HashMap(go(k), go(v)),
+        TypeExpr::HashSet(t) => TypeExpr::HashSet(go(t)),
+        TypeExpr::Named { name, args } => TypeExpr::Named {
+            name: name.clone(),
+            args: args.iter().map(|t| substitute(t, params)).collect(),
+        },
+        TypeExpr::Primitive(_)
+        | TypeExpr::Borsh(_)
+        | TypeExpr::Bincode(_)
+        | TypeExpr::Opaque(_) => ty.clone(),
+    }
+}
+
+/*******************************************************************************
+  Functions
+*******************************************************************************/
+
+fn render_functions(functions: &[FunctionDecl], out: &mut String) -> Result<()> {
+    writeln!(
+        out,
+        "Every argument is passed as a pointer to its encoding, along with the \
+         length of the encoding. The result is written to a buffer provided by \
+         the caller, or returned in a Rust-allocated buffer (`External`)."
+    )?;
+    writeln!(out)?;
+    writeln!(out, "| Symbol | Arguments | Result |")?;
+    writeln!(out, "|--------|-----------|--------|")?;
+    for function in functions {
+        let args: Vec<String> = function
+            .args
+            .iter()
+            .map(|arg| format!("{}: `{}`", arg.name, arg.ty))
+            .collect();
+        let result = match &function.result {
+            None => "(none)".to_string(),
+            Some(result) => format!("`{}` ({:?})", result.ty, result.marshalling),
+        };
+        writeln!(
+            out,
+            "| `{}` | {} | {} |",
+            function.symbol,
+            if args.is_empty() {
+                "(none)".to_string()
+            } else {
+                args.join(", ")
+            },
+            result
+        )?;
+    }
+    Ok(())
+}
+
+/*******************************************************************************
+  Sanity checks
+*******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use std::marker::PhantomData;
+
+    use super::*;
+    use crate::{HaskellSize, HaskellType};
+
+    enum ExampleTag {}
+
+    #[derive(HaskellType, HaskellSize)]
+    #[allow(dead_code)]
+    struct Header {
+        version: u8,
+        flags: u16,
+        key: [u8; 32],
+        amount: (u64, bool),
+    }
+
+    #[derive(HaskellType, HaskellSize)]
+    #[allow(dead_code)]
+    enum Direction {
+        Up(u32),
+        Down(u32),
+    }
+
+    #[derive(HaskellType)]
+    #[allow(dead_code)]
+    enum Message<T> {
+        Ping,
+        Data { header: Header, payload: Vec<T> },
+    }
+
+    /// Encode `u64` as `u32`
+    mod narrow {
+        use super::*;
+
+        pub fn haskell_type<Tag>(_tag: PhantomData<Tag>) -> TypeExpr {
+            TypeExpr::Primitive(Primitive::U32)
+        }
+
+        pub fn haskell_size<Tag>(_tag: PhantomData<Tag>) -> usize {
+            4
+        }
+    }
+
+    #[derive(HaskellType, HaskellSize)]
+    #[allow(dead_code)]
+    struct Account {
+        #[haskell(via = "borsh")]
+        lamports: u64,
+        #[haskell(with = "narrow")]
+        slot: u64,
+        owner: Header,
+    }
+
+    #[test]
+    fn sizes() {
+        let tag = PhantomData::<ExampleTag>;
+        let mut schema = Schema::new();
+        schema
+            .add::<ExampleTag, Header>()
+            .add::<ExampleTag, Direction>()
+            .add::<ExampleTag, Message<u8>>()
+            .add::<ExampleTag, Account>();
+        assert_eq!(
+            schema.size(&Header::haskell_type(tag)),
+            Some(Header::haskell_size(tag))
+        );
+        assert_eq!(
+            schema.size(&Direction::haskell_type(tag)),
+            Some(Direction::haskell_size(tag))
+        );
+        assert_eq!(schema.size(&Message::<u8>::haskell_type(tag)), None);
+        assert_eq!(
+            schema.size(&Account::haskell_type(tag)),
+            Some(Account::haskell_size(tag))
+        );
+
+        // The size of the field using `with` is inferred
+        let account = schema.get("Account").unwrap();
+        let TypeBody::Struct(fields) = &account.body else {
+            panic!("expected struct")
+        };
+        assert_eq!(
+            field_sizes(&schema, fields, Some(Account::haskell_size(tag))),
+            vec![Some(8), Some(4), Some(44)]
+        );
+    }
+
+    #[test]
+    fn anchors() {
+        let mut schema = Schema::new();
+        schema.add::<ExampleTag, Message<u8>>();
+        assert_eq!(anchor("Pair<A, B>"), "paira-b");
+        assert_eq!(
+            encoding(
+                &schema,
+                &Message::<u8>::haskell_type(PhantomData::<ExampleTag>),
+                &[]
+            ),
+            "see [`Message<T>`](#messaget)"
+        );
+    }
+
+    #[test]
+    fn render() -> Result<()> {
+        let mut docs = WireDocs::new("Example");
+        docs.add::<ExampleTag, Message<u8>>()
+            .add::<ExampleTag, Direction>();
+        let expected = "# Wire format: Example
+
+Generated by haskell-ffi from the Rust type definitions. All values are encoded using Borsh: integers and floats are little-endian, and length prefixes are `u32`. Offsets and sizes are in bytes.
+
+## Types
+
+### `Header`
+
+Rust struct `haskell_ffi::codegen::docs::tests::Header`, fixed size of 44 bytes.
+
+| Offset | Size | Field | Type | Encoding |
+|-------:|-----:|-------|------|----------|
+| 0 | 1 | `version` | `u8` |  |
+| 1 | 2 | `flags` | `u16` |  |
+| 3 | 32 | `key` | `[u8; 32]` | 32 elements, no length prefix |
+| 35 | 9 | `amount` | `(u64, bool)` | the components, in order |
+
+### `Message<T>`
+
+Rust enum `haskell_ffi::codegen::docs::tests::Message`, variable size. Encoded as a 1-byte variant index, followed by the fields of the variant.
+
+| Index | Variant | Fields |
+|------:|---------|--------|
+| 0 | `Ping` | (none) |
+| 1 | `Data` | `header`: `Header`, `payload`: `Vec<T>` |
+
+Variant `Data` (index 1):
+
+| Offset | Size | Field | Type | Encoding |
+|-------:|-----:|-------|------|----------|
+| 1 | 44 | `header` | `Header` | see [`Header`](#header) |
+| 45 | variable | `payload` | `Vec<T>` | length prefix, then the elements |
+
+### `Direction`
+
+Rust enum `haskell_ffi::codegen::docs::tests::Direction`, fixed size of 5 bytes. Encoded as a 1-byte variant index, followed by the fields of the variant.
+
+| Index | Variant | Fields |
+|------:|---------|--------|
+| 0 | `Up` | 0: `u32` |
+| 1 | `Down` | 0: `u32` |
+
+Variant `Up` (index 0):
+
+| Offset | Size | Field | Type | Encoding |
+|-------:|-----:|-------|------|----------|
+| 1 | 4 | 0 | `u32` |  |
+
+Variant `Down` (index 1):
+
+| Offset | Size | Field | Type | Encoding |
+|-------:|-----:|-------|------|----------|
+| 1 | 4 | 0 | `u32` |  |
+";
+        assert_eq!(docs.render()?, expected);
+        Ok(())
+    }
+}
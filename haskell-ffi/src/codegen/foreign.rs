@@ -0,0 +1,728 @@
+//! Generating Haskell foreign imports for exported functions
+//!
+//! For every function exported using `#[haskell_export]` we generate a
+//! `foreign import capi` declaration of the raw symbol, and a typed wrapper
+//! that marshalls the arguments and result using the helpers from the Haskell
+//! `foreign-rust` package:
+//!
+//! ```ignore
+//! let mut module = ForeignModule::new("Example.FFI", "example.h");
+//! module.import("Example.Types").add_exports();
+//! module.write_to(Path::new("src"))?;
+//! ```
+//!
+//! The types of the arguments and results are added to the module's
+//! `schema`; their declarations can be generated using `HaskellModule`.
+//! Types without a `HaskellType` instance must be mapped onto an existing
+//! Haskell type (see `codegen::type_map`); an unqualified mapping refers to a
+//! type declared in one of the imported modules. The header can be generated
+//! using `codegen::c_header`.
+//!
+//! The wrappers throw a `RustFFIException` (declared in the generated module)
+//! if the Rust function returns a status code other than `Ok`, that is, if it
+//! panicked or could not marshall its arguments or result (see
+//! `error::Status`). The exception contains the message of the error recorded
+//! by the Rust side. Since that error is recorded per OS thread, the wrappers
+//! make the call in a bound thread (when the threaded RTS is used).
+//!
+//! If the functions include `haskell_ffi_check_schema`, the module also
+//! contains the fingerprints of the other functions, and a function
+//! `checkSchema` that compares them to those of the Rust library (see
+//! `fingerprint`).
+
+use std::{
+    collections::BTreeSet,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    codegen::{
+        haskell::{field_name, module_path, render_type, Imports},
+        type_map::TypeMap,
+    },
+    error::Result,
+    export::{exports, HaskellExport},
+    fingerprint::{SignatureFingerprint, CHECK_SCHEMA_SYMBOL},
+    schema::{FunctionDecl, Marshalling, Schema},
+};
+
+/*******************************************************************************
+  Modules
+*******************************************************************************/
+
+/// Haskell module containing foreign imports for a set of exported functions
+pub struct ForeignModule {
+    module_name: String,
+    header: String,
+    imports: Vec<String>,
+    functions: Vec<FunctionDecl>,
+    schema: Schema,
+    type_map: TypeMap,
+}
+
+impl ForeignModule {
+    /// New module, importing the functions from the specified C header
+    pub fn new(module_name: &str, header: &str) -> Self {
+        ForeignModule {
+            module_name: module_name.to_string(),
+            header: header.to_string(),
+            imports: Vec::new(),
+            functions: Vec::new(),
+            schema: Schema::new(),
+            type_map: TypeMap::new(),
+        }
+    }
+
+    /// Import a Haskell module (typically the one declaring the types)
+    pub fn import(&mut self, module_name: &str) -> &mut Self {
+        self.imports.push(module_name.to_string());
+        self
+    }
+
+    /// Refer to the Rust type using an existing Haskell type (see `TypeMap::map`)
+    pub fn map_type(&mut self, rust_path: &str, haskell_name: &str) -> &mut Self {
+        self.type_map.map(rust_path, haskell_name);
+        self
+    }
+
+    /// Add all functions exported using `#[haskell_export]`
+    pub fn add_exports(&mut self) -> &mut Self {
+        for export in exports() {
+            self.add_export(export);
+        }
+        self
+    }
+
+    pub fn add_export(&mut self, export: &HaskellExport) -> &mut Self {
+        let decl = (export.describe)(&mut self.schema);
+        self.add_function(decl)
+    }
+
+    pub fn add_function(&mut self, decl: FunctionDecl) -> &mut Self {
+        self.functions.push(decl);
+        self
+    }
+
+    pub fn functions(&self) -> &[FunctionDecl] {
+        &self.functions
+    }
+
+    /// Declarations of the types used by the functions
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    pub fn render(&self) -> Result<String> {
+        render_foreign_module(
+            &self.module_name,
+            &self.header,
+            &self.imports,
+            &self.functions,
+            &self.schema,
+            &self.type_map.resolve(&self.schema)?,
+        )
+    }
+
+    /// Write the module to the corresponding file in `dir`
+    ///
+    /// Returns the path to the file.
+    pub fn write_to(&self, dir: &Path) -> Result<PathBuf> {
+        let path = module_path(dir, &self.module_name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, self.render()?)?;
+        Ok(path)
+    }
+}
+
+/// Render a Haskell module with foreign imports for the specified functions
+///
+/// The schema should describe the types of the functions, and the type map
+/// should be resolved against it (see `TypeMap::resolve`).
+pub fn render_foreign_module(
+    module_name: &str,
+    header: &str,
+    module_imports: &[String],
+    functions: &[FunctionDecl],
+    schema: &Schema,
+    types: &TypeMap,
+) -> Result<String> {
+    let mut imports = Imports::default();
+    for import in types.imports() {
+        imports.import(import);
+    }
+    imports.pragma("CApiFFI");
+    imports.import("Foreign.C.Types");
+    imports.import("Foreign.Ptr");
+
+    let mut decls = Vec::new();
+    for function in functions {
+        decls.push(render_function(header, function, types, &mut imports)?);
+    }
+    let checked = functions.iter().any(|function| function.checked);
+    if checked {
+        decls.push(render_check_status(header, &mut imports)?);
+    }
+    let check_schema = functions
+        .iter()
+        .any(|function| function.symbol == CHECK_SCHEMA_SYMBOL);
+    if check_schema {
+        decls.push(render_schema_check(functions, schema, &mut imports)?);
+    }
+
+    let mut out = String::new();
+    for pragma in imports.pragmas() {
+        writeln!(out, "{{-# LANGUAGE {} #-}}", pragma)?;
+    }
+    writeln!(out)?;
+    writeln!(
+        out,
+        "-- | Generated by haskell-ffi from the exported Rust functions. Do not edit."
+    )?;
+    writeln!(out, "module {} (", module_name)?;
+    for (ix, function) in functions.iter().enumerate() {
+        let sep = if ix == 0 { " " } else { "," };
+        writeln!(out, "  {} {}", sep, function_name(&function.symbol))?;
+    }
+    if checked {
+        writeln!(out, "  , RustFFIException (..)")?;
+        writeln!(out, "  , RustFFIErrorKind (..)")?;
+    }
+    if check_schema {
+        writeln!(out, "  , expectedSchema")?;
+        writeln!(out, "  , checkSchema")?;
+    }
+    writeln!(out, "  ) where")?;
+    writeln!(out)?;
+    let mut all_imports: BTreeSet<String> = module_imports.iter().cloned().collect();
+    all_imports.extend(imports.imports().map(|import| import.to_string()));
+    for import in all_imports {
+        writeln!(out, "import {}", import)?;
+    }
+    for decl in decls {
+        writeln!(out)?;
+        out.push_str(&decl);
+    }
+    Ok(out)
+}
+
+/*******************************************************************************
+  Functions
+*******************************************************************************/
+
+fn render_function(
+    header: &str,
+    decl: &FunctionDecl,
+    types: &TypeMap,
+    imports: &mut Imports,
+) -> Result<String> {
+    let name = function_name(&decl.symbol);
+    let c_name = format!("c_{}", decl.symbol);
+
+    // Foreign import of the raw symbol
+    let mut c_args = vec!["Ptr CUChar -> CULong"; decl.args.len()];
+    let mut c_result = if decl.checked { "IO CInt" } else { "IO ()" };
+    match decl.result.as_ref().map(|result| result.marshalling) {
+        None => (),
+        Some(Marshalling::External) => {
+            imports.import("Foreign.Rust.Marshall.External");
+            if decl.checked {
+                c_args.push("Ptr (Ptr ExternalBuffer)");
+            } else {
+                c_result = "IO (Ptr ExternalBuffer)";
+            }
+        }
+        Some(_) => c_args.push("Ptr CUChar -> Ptr CULong"),
+    }
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "foreign import capi unsafe \"{} {}\"",
+        header, decl.symbol
+    )?;
+    writeln!(out, "  {}", c_name)?;
+    for (ix, arg) in c_args.iter().enumerate() {
+        let sep = if ix == 0 { "::" } else { "->" };
+        writeln!(out, "    {} {}", sep, arg)?;
+    }
+    let sep = if c_args.is_empty() { "::" } else { "->" };
+    writeln!(out, "    {} {}", sep, c_result)?;
+    writeln!(out)?;
+
+    // Typed wrapper
+    let mut sig = Vec::new();
+    for arg in &decl.args {
+        sig.push(render_type(&arg.ty, &[], types, imports)?);
+    }
+    sig.push(match &decl.result {
+        None => "IO ()".to_string(),
+        Some(result) => {
+            let ty = render_type(&result.ty, &[], types, imports)?;
+            if is_atomic(&ty) {
+                format!("IO {}", ty)
+            } else {
+                format!("IO ({})", ty)
+            }
+        }
+    });
+    writeln!(out, "{} :: {}", name, sig.join(" -> "))?;
+
+    let arg_names: Vec<String> = decl.args.iter().map(|arg| field_name(&arg.name)).collect();
+    let head = std::iter::once(name)
+        .chain(arg_names.iter().cloned())
+        .collect::<Vec<_>>()
+        .join(" ");
+    writeln!(out, "{} =", head)?;
+
+    // Marshall the arguments and allocate the output buffer, then make the call
+    let mut lambdas = Vec::new();
+    let mut call = vec![c_name];
+    if !arg_names.is_empty() {
+        imports.import("Foreign.Rust.Marshall.Variable");
+    }
+    for arg in &arg_names {
+        lambdas.push(format!("toBorshVar {} $ \\{}Ptr {}Len ->", arg, arg, arg));
+        call.push(format!("{}Ptr", arg));
+        call.push(format!("{}Len", arg));
+    }
+    let mut call = call.join(" ");
+    let check = format!(" >>= checkStatus {:?}", decl.symbol);
+    let mut body = Vec::new();
+    match decl.result.as_ref().map(|result| result.marshalling) {
+        None => (),
+        Some(Marshalling::External) if decl.checked => {
+            imports.import("Foreign.Marshal.Alloc (alloca)");
+            imports.import("Foreign.Storable (peek)");
+            lambdas.push("alloca $ \\out -> do".to_string());
+            call.push_str(" out");
+            body.push("peek out >>= fromExternalBorsh".to_string());
+        }
+        Some(Marshalling::External) => call.push_str(" >>= fromExternalBorsh"),
+        Some(Marshalling::Var) => {
+            imports.import("Foreign.Rust.Marshall.Variable");
+            lambdas.push("withBorshVarBuffer $ \\out outLen ->".to_string());
+            call.push_str(" out outLen");
+        }
+        Some(marshalling) => {
+            imports.import("Foreign.Marshal.Utils (with)");
+            imports.import("Foreign.Rust.Marshall.Fixed");
+            let helper = if marshalling == Marshalling::Fixed {
+                "withBorshFixedBuffer"
+            } else {
+                "withBorshMaxBuffer"
+            };
+            lambdas.push(format!("{} $ \\out len ->", helper));
+            lambdas.push("with len $ \\outLen ->".to_string());
+            call.push_str(" out outLen");
+        }
+    }
+    if decl.checked {
+        // The last error is recorded per OS thread (see `error::last_error`)
+        lambdas.insert(0, "inBoundThread $".to_string());
+        call.push_str(&check);
+    }
+    body.insert(0, call);
+    for lambda in &lambdas {
+        writeln!(out, "    {}", lambda)?;
+    }
+    let indent = if lambdas.is_empty() { "    " } else { "      " };
+    for line in body {
+        writeln!(out, "{}{}", indent, line)?;
+    }
+    Ok(out)
+}
+
+/// Exception for failed calls, and the function throwing it
+///
+/// The exception contains the last error recorded by the Rust side (see
+/// `error::last_error`). Since that is recorded per OS thread, the wrappers
+/// make the call and retrieve the error in a bound thread.
+fn render_check_status(header: &str, imports: &mut Imports) -> Result<String> {
+    imports.import("Control.Concurrent (rtsSupportsBoundThreads, runInBoundThread)");
+    imports.import("Control.Exception (Exception, throwIO)");
+    imports.import("qualified GHC.Foreign as GHC");
+    imports.import("GHC.IO.Encoding (utf8)");
+
+    let mut out = String::new();
+    out.push_str(RUST_FFI_EXCEPTION);
+    for (symbol, ty) in [
+        ("haskell_ffi_last_error_kind", "IO CInt"),
+        ("haskell_ffi_last_error_ptr", "IO (Ptr CUChar)"),
+        ("haskell_ffi_last_error_len", "IO CULong"),
+        ("haskell_ffi_last_error_clear", "IO ()"),
+    ] {
+        writeln!(out)?;
+        writeln!(out, "foreign import capi unsafe \"{} {}\"", header, symbol)?;
+        writeln!(out, "  c_{} :: {}", symbol, ty)?;
+    }
+    writeln!(out)?;
+    out.push_str(CHECK_STATUS);
+    Ok(out)
+}
+
+const RUST_FFI_EXCEPTION: &str = r#"-- | Failure of a Rust function
+data RustFFIException = RustFFIException
+  { rustFFISymbol :: String
+  , rustFFIErrorKind :: RustFFIErrorKind
+  , rustFFIMessage :: String
+  }
+  deriving (Show)
+
+instance Exception RustFFIException
+
+-- | Why the Rust function failed
+data RustFFIErrorKind
+  = RustFFIInvalidEncoding
+  | RustFFIPanic
+  | RustFFINoErrorRecorded
+  deriving (Show, Eq)
+"#;
+
+const CHECK_STATUS: &str = r#"-- | Run the call and 'checkStatus' on the same OS thread
+--
+-- The Rust side records the last error per OS thread, and an unbound thread
+-- may migrate between OS threads in the threaded RTS.
+inBoundThread :: IO a -> IO a
+inBoundThread action
+  | rtsSupportsBoundThreads = runInBoundThread action
+  | otherwise = action
+
+-- | Throw a 'RustFFIException' if the Rust function did not complete successfully
+checkStatus :: String -> CInt -> IO ()
+checkStatus _ 0 = return ()
+checkStatus symbol _ = do
+    kind <- c_haskell_ffi_last_error_kind
+    ptr <- c_haskell_ffi_last_error_ptr
+    len <- c_haskell_ffi_last_error_len
+    message <- GHC.peekCStringLen utf8 (castPtr ptr, fromIntegral len)
+    c_haskell_ffi_last_error_clear
+    throwIO $ RustFFIException symbol (errorKind kind) message
+  where
+    errorKind 0 = RustFFINoErrorRecorded
+    errorKind 2 = RustFFIPanic
+    errorKind _ = RustFFIInvalidEncoding
+"#;
+
+/// Fingerprints of the functions, and the function checking them
+fn render_schema_check(
+    functions: &[FunctionDecl],
+    schema: &Schema,
+    imports: &mut Imports,
+) -> Result<String> {
+    imports.pragma("OverloadedStrings");
+    imports.import("Data.Text (Text)");
+    imports.import("Data.Word");
+    imports.import("qualified Data.Text as Text");
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "-- | Fingerprints of the functions when this module was generated"
+    )?;
+    writeln!(out, "expectedSchema :: [(Text, Word64, [(Text, Word64)])]")?;
+    writeln!(out, "expectedSchema =")?;
+    let signatures: Vec<SignatureFingerprint> = functions
+        .iter()
+        .filter(|function| function.symbol != CHECK_SCHEMA_SYMBOL)
+        .map(|function| SignatureFingerprint::new(schema, function))
+        .collect();
+    if signatures.is_empty() {
+        writeln!(out, "    []")?;
+    }
+    for (ix, signature) in signatures.iter().enumerate() {
+        let sep = if ix == 0 { '[' } else { ',' };
+        let types: Vec<String> = signature
+            .types
+            .iter()
+            .map(|(name, fingerprint)| format!("({:?}, {})", name, fingerprint))
+            .collect();
+        writeln!(
+            out,
+            "  {} ({:?}, {}, [{}])",
+            sep,
+            signature.symbol,
+            signature.signature,
+            types.join(", ")
+        )?;
+    }
+    if !signatures.is_empty() {
+        writeln!(out, "  ]")?;
+    }
+    writeln!(out)?;
+    writeln!(
+        out,
+        "-- | Check that the Rust library agrees with this module about the wire format"
+    )?;
+    writeln!(out, "--")?;
+    writeln!(
+        out,
+        "-- Should be called once, before calling any of the other functions."
+    )?;
+    writeln!(out, "checkSchema :: IO ()")?;
+    writeln!(out, "checkSchema = do")?;
+    writeln!(
+        out,
+        "    mismatch <- {} expectedSchema",
+        function_name(CHECK_SCHEMA_SYMBOL)
+    )?;
+    writeln!(out, "    case mismatch of")?;
+    writeln!(out, "      Nothing -> return ()")?;
+    writeln!(out, "      Just err -> fail (Text.unpack err)")?;
+    Ok(out)
+}
+
+/// Haskell function name for an exported symbol (`add_one` becomes `addOne`)
+fn function_name(symbol: &str) -> String {
+    field_name(symbol)
+}
+
+/// Does the rendered type need parentheses when applied to `IO`?
+fn is_atomic(rendered: &str) -> bool {
+    !rendered.contains(' ') || rendered.starts_with('(') || rendered.starts_with('[')
+}
+
+/*******************************************************************************
+  Tests
+*******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FromHaskell, HaskellSize, HaskellType, ToHaskell};
+
+    #[derive(Debug, HaskellType, HaskellSize, ToHaskell, FromHaskell)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    /// Rust type without a `HaskellType` instance
+    #[derive(ToHaskell)]
+    struct Summary(String);
+
+    #[crate::haskell_export(name = "foreign_test_translate")]
+    fn translate(point: Point, dx: i32) -> Point {
+        Point {
+            x: point.x + dx,
+            y: point.y,
+        }
+    }
+
+    #[crate::haskell_export(name = "foreign_test_summarize")]
+    fn summarize(points: Vec<Point>) -> Summary {
+        Summary(format!("{} points", points.len()))
+    }
+
+    #[crate::haskell_export(name = "foreign_test_bytes", external)]
+    fn bytes(n: u8) -> Vec<u8> {
+        vec![n; n as usize]
+    }
+
+    #[crate::haskell_export(name = "foreign_test_reset", unchecked)]
+    fn reset() {}
+
+    fn foreign_test_exports() -> Vec<&'static HaskellExport> {
+        exports()
+            .into_iter()
+            .filter(|export| export.symbol.starts_with("foreign_test_"))
+            .collect()
+    }
+
+    #[test]
+    fn registry() {
+        let symbols: Vec<&str> = foreign_test_exports()
+            .iter()
+            .map(|export| export.symbol)
+            .collect();
+        assert_eq!(
+            symbols,
+            [
+                "foreign_test_bytes",
+                "foreign_test_reset",
+                "foreign_test_summarize",
+                "foreign_test_translate"
+            ]
+        );
+    }
+
+    #[test]
+    fn render() -> Result<()> {
+        let mut module = ForeignModule::new("Example.FFI", "example.h");
+        module
+            .import("Example.Types")
+            .map_type(std::any::type_name::<Summary>(), "Summary");
+        for export in foreign_test_exports() {
+            module.add_export(export);
+        }
+        let names: Vec<&str> = module
+            .schema()
+            .decls()
+            .iter()
+            .map(|d| d.name.as_str())
+            .collect();
+        assert_eq!(names, ["Point"]);
+
+        let expected = r#"{-# LANGUAGE CApiFFI #-}
+
+-- | Generated by haskell-ffi from the exported Rust functions. Do not edit.
+module Example.FFI (
+    foreignTestBytes
+  , foreignTestReset
+  , foreignTestSummarize
+  , foreignTestTranslate
+  , RustFFIException (..)
+  , RustFFIErrorKind (..)
+  ) where
+
+import Control.Concurrent (rtsSupportsBoundThreads, runInBoundThread)
+import Control.Exception (Exception, throwIO)
+import Data.Int
+import Data.Word
+import Example.Types
+import Foreign.C.Types
+import Foreign.Marshal.Alloc (alloca)
+import Foreign.Marshal.Utils (with)
+import Foreign.Ptr
+import Foreign.Rust.Marshall.External
+import Foreign.Rust.Marshall.Fixed
+import Foreign.Rust.Marshall.Variable
+import Foreign.Storable (peek)
+import GHC.IO.Encoding (utf8)
+import qualified GHC.Foreign as GHC
+
+foreign import capi unsafe "example.h foreign_test_bytes"
+  c_foreign_test_bytes
+    :: Ptr CUChar -> CULong
+    -> Ptr (Ptr ExternalBuffer)
+    -> IO CInt
+
+foreignTestBytes :: Word8 -> IO [Word8]
+foreignTestBytes n =
+    inBoundThread $
+    toBorshVar n $ \nPtr nLen ->
+    alloca $ \out -> do
+      c_foreign_test_bytes nPtr nLen out >>= checkStatus "foreign_test_bytes"
+      peek out >>= fromExternalBorsh
+
+foreign import capi unsafe "example.h foreign_test_reset"
+  c_foreign_test_reset
+    :: IO ()
+
+foreignTestReset :: IO ()
+foreignTestReset =
+    c_foreign_test_reset
+
+foreign import capi unsafe "example.h foreign_test_summarize"
+  c_foreign_test_summarize
+    :: Ptr CUChar -> CULong
+    -> Ptr CUChar -> Ptr CULong
+    -> IO CInt
+
+foreignTestSummarize :: [Point] -> IO Summary
+foreignTestSummarize points =
+    inBoundThread $
+    toBorshVar points $ \pointsPtr pointsLen ->
+    withBorshVarBuffer $ \out outLen ->
+      c_foreign_test_summarize pointsPtr pointsLen out outLen >>= checkStatus "foreign_test_summarize"
+
+foreign import capi unsafe "example.h foreign_test_translate"
+  c_foreign_test_translate
+    :: Ptr CUChar -> CULong
+    -> Ptr CUChar -> CULong
+    -> Ptr CUChar -> Ptr CULong
+    -> IO CInt
+
+foreignTestTranslate :: Point -> Int32 -> IO Point
+foreignTestTranslate point dx =
+    inBoundThread $
+    toBorshVar point $ \pointPtr pointLen ->
+    toBorshVar dx $ \dxPtr dxLen ->
+    withBorshFixedBuffer $ \out len ->
+    with len $ \outLen ->
+      c_foreign_test_translate pointPtr pointLen dxPtr dxLen out outLen >>= checkStatus "foreign_test_translate"
+
+-- | Failure of a Rust function
+data RustFFIException = RustFFIException
+  { rustFFISymbol :: String
+  , rustFFIErrorKind :: RustFFIErrorKind
+  , rustFFIMessage :: String
+  }
+  deriving (Show)
+
+instance Exception RustFFIException
+
+-- | Why the Rust function failed
+data RustFFIErrorKind
+  = RustFFIInvalidEncoding
+  | RustFFIPanic
+  | RustFFINoErrorRecorded
+  deriving (Show, Eq)
+
+foreign import capi unsafe "example.h haskell_ffi_last_error_kind"
+  c_haskell_ffi_last_error_kind :: IO CInt
+
+foreign import capi unsafe "example.h haskell_ffi_last_error_ptr"
+  c_haskell_ffi_last_error_ptr :: IO (Ptr CUChar)
+
+foreign import capi unsafe "example.h haskell_ffi_last_error_len"
+  c_haskell_ffi_last_error_len :: IO CULong
+
+foreign import capi unsafe "example.h haskell_ffi_last_error_clear"
+  c_haskell_ffi_last_error_clear :: IO ()
+
+-- | Run the call and 'checkStatus' on the same OS thread
+--
+-- The Rust side records the last error per OS thread, and an unbound thread
+-- may migrate between OS threads in the threaded RTS.
+inBoundThread :: IO a -> IO a
+inBoundThread action
+  | rtsSupportsBoundThreads = runInBoundThread action
+  | otherwise = action
+
+-- | Throw a 'RustFFIException' if the Rust function did not complete successfully
+checkStatus :: String -> CInt -> IO ()
+checkStatus _ 0 = return ()
+checkStatus symbol _ = do
+    kind <- c_haskell_ffi_last_error_kind
+    ptr <- c_haskell_ffi_last_error_ptr
+    len <- c_haskell_ffi_last_error_len
+    message <- GHC.peekCStringLen utf8 (castPtr ptr, fromIntegral len)
+    c_haskell_ffi_last_error_clear
+    throwIO $ RustFFIException symbol (errorKind kind) message
+  where
+    errorKind 0 = RustFFINoErrorRecorded
+    errorKind 2 = RustFFIPanic
+    errorKind _ = RustFFIInvalidEncoding
+"#;
+        assert_eq!(module.render()?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn schema_check() -> Result<()> {
+        let mut module = ForeignModule::new("Example.FFI", "example.h");
+        for export in exports() {
+            if export.symbol == CHECK_SCHEMA_SYMBOL || export.symbol == "foreign_test_translate" {
+                module.add_export(export);
+            }
+        }
+        let translate = &module.functions()[0];
+        let fingerprint = SignatureFingerprint::new(module.schema(), translate);
+
+        let rendered = module.render()?;
+        assert!(rendered.contains("  , expectedSchema\n  , checkSchema\n  ) where"));
+        assert!(rendered.contains(&format!(
+            "expectedSchema =
+  [ (\"foreign_test_translate\", {}, [(\"Point\", {})])
+  ]
+",
+            fingerprint.signature, fingerprint.types[0].1
+        )));
+        assert!(rendered.contains("    mismatch <- haskellFfiCheckSchema expectedSchema\n"));
+        Ok(())
+    }
+}
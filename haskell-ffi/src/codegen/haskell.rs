@@ -0,0 +1,787 @@
+//! Generating Haskell data type declarations
+//!
+//! The generated declarations mirror the Rust types, and derive their Borsh
+//! instances generically, so that the encoding matches the `ToHaskell` and
+//! `FromHaskell` instances on the Rust side:
+//!
+//! ```ignore
+//! let mut module = HaskellModule::new("Example.Types");
+//! module.add::<ExampleTag, Config>();
+//! module.write_to(Path::new("src"))?;
+//! ```
+//!
+//! The mapping of types is as follows:
+//!
+//! | Rust                         | Haskell                           |
+//! |------------------------------|-----------------------------------|
+//! | `u8` .. `u64`, `i8` .. `i64` | `Word8` .. `Word64`, `Int8` .. `Int64` |
+//! | `u128`, `i128`               | `Word128`, `Int128` (`wide-word`) |
+//! | `f32`, `f64`                 | `Float`, `Double`                 |
+//! | `bool`, `()`                 | `Bool`, `()`                      |
+//! | `String`                     | `Text`                            |
+//! | `Vec<T>`                     | `[T]`                             |
+//! | `Option<T>`                  | `Maybe T`                         |
+//! | `Result<T, E>`               | `Either E T`                      |
+//! | `(A, B, ..)`                 | `(A, B, ..)`                      |
+//! | `[T; N]`                     | `FixedSizeArray N T`              |
+//! | `HashMap<K, V>`              | `Map K V`                         |
+//! | `HashSet<T>`                 | `Set T`                           |
+//! | `via = "bincode"`            | `ByteString`                      |
+//!
+//! Types can also be mapped onto existing Haskell types, in which case no
+//! declaration is generated for them (see `codegen::type_map`). Types without
+//! a `HaskellType` instance, and types encoded using Borsh, must be mapped.
+
+use std::{
+    collections::BTreeSet,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    codegen::{
+        type_map::{split_qualified, TypeMap},
+        CodegenError,
+    },
+    error::{Error, Result},
+    haskell_type::HaskellType,
+    naming,
+    schema::{Fields, Primitive, Schema, TypeBody, TypeDecl, TypeExpr},
+};
+
+/*******************************************************************************
+  Modules
+*******************************************************************************/
+
+/// Haskell module containing declarations for a set of Rust types
+pub struct HaskellModule {
+    module_name: String,
+    schema: Schema,
+    type_map: TypeMap,
+}
+
+impl HaskellModule {
+    pub fn new(module_name: &str) -> Self {
+        HaskellModule {
+            module_name: module_name.to_string(),
+            schema: Schema::new(),
+            type_map: TypeMap::new(),
+        }
+    }
+
+    /// Add declarations for `T`, and all types it depends on
+    pub fn add<Tag, T: HaskellType<Tag> + ?Sized>(&mut self) -> &mut Self {
+        self.schema.add::<Tag, T>();
+        self
+    }
+
+    /// Refer to the Rust type using an existing Haskell type (see `TypeMap::map`)
+    pub fn map_type(&mut self, rust_path: &str, haskell_name: &str) -> &mut Self {
+        self.type_map.map(rust_path, haskell_name);
+        self
+    }
+
+    /// Add an import line (see `TypeMap::import`)
+    pub fn import(&mut self, import: &str) -> &mut Self {
+        self.type_map.import(import);
+        self
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    pub fn render(&self) -> Result<String> {
+        render_module(&self.module_name, &self.schema, &self.type_map)
+    }
+
+    /// Write the module to the corresponding file in `dir`
+    ///
+    /// For example, module `Example.Types` is written to
+    /// `dir/Example/Types.hs`. Returns the path to the file.
+    pub fn write_to(&self, dir: &Path) -> Result<PathBuf> {
+        let path = module_path(dir, &self.module_name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, self.render()?)?;
+        Ok(path)
+    }
+}
+
+/// Path to the file containing the specified module
+pub fn module_path(dir: &Path, module_name: &str) -> PathBuf {
+    let mut path = dir.to_path_buf();
+    path.extend(module_name.split('.'));
+    path.set_extension("hs");
+    path
+}
+
+/// Render a Haskell module declaring all types in the schema
+///
+/// Types that are mapped onto existing Haskell types are not declared.
+pub fn render_module(module_name: &str, schema: &Schema, type_map: &TypeMap) -> Result<String> {
+    if let Some((a, b)) = schema.conflicts().first() {
+        return Err(Error::custom(CodegenError::new(format!(
+            "Rust types {} and {} map to the same Haskell type",
+            a, b
+        ))));
+    }
+
+    let types = type_map.resolve(schema)?;
+    let declared: Vec<&TypeDecl> = schema
+        .decls()
+        .iter()
+        .filter(|decl| types.get_decl(&decl.name).is_none())
+        .collect();
+
+    let mut imports = Imports::default();
+    for import in types.imports() {
+        imports.import(import);
+    }
+    let mut decls = Vec::new();
+    for decl in &declared {
+        decls.push(render_decl(decl, &types, &mut imports)?);
+    }
+
+    let mut out = String::new();
+    for pragma in imports.pragmas() {
+        writeln!(out, "{{-# LANGUAGE {} #-}}", pragma)?;
+    }
+    writeln!(out)?;
+    writeln!(
+        out,
+        "-- | Generated by haskell-ffi from the Rust type definitions. Do not edit."
+    )?;
+    writeln!(out, "module {} (", module_name)?;
+    for (ix, decl) in declared.iter().enumerate() {
+        let sep = if ix == 0 { " " } else { "," };
+        writeln!(out, "  {} {}(..)", sep, decl.name)?;
+    }
+    writeln!(out, "  ) where")?;
+    writeln!(out)?;
+    for import in imports.imports() {
+        writeln!(out, "import {}", import)?;
+    }
+    for decl in decls {
+        writeln!(out)?;
+        out.push_str(&decl);
+    }
+    Ok(out)
+}
+
+/*******************************************************************************
+  Declarations
+*******************************************************************************/
+
+fn render_decl(decl: &TypeDecl, types: &TypeMap, imports: &mut Imports) -> Result<String> {
+    imports.import("Codec.Borsh");
+    imports.import("GHC.Generics (Generic)");
+    imports.pragma("DeriveGeneric");
+    imports.pragma("DerivingStrategies");
+
+    let params: Vec<String> = decl.params.iter().map(|p| type_var(p)).collect();
+    let head = std::iter::once(decl.name.clone())
+        .chain(params.iter().cloned())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let mut out = String::new();
+    match &decl.body {
+        TypeBody::Transparent(ty) => {
+            imports.pragma("GeneralizedNewtypeDeriving");
+            let ty = render_arg(ty, &params, types, imports)?;
+            writeln!(out, "newtype {} = {} {}", head, decl.name, ty)?;
+            writeln!(out, "  deriving (Generic)")?;
+            writeln!(out, "  deriving newtype (BorshSize, ToBorsh, FromBorsh)")?;
+            return Ok(out);
+        }
+        TypeBody::Struct(Fields::Named(fields)) if !fields.is_empty() => {
+            imports.pragma("DuplicateRecordFields");
+            writeln!(out, "data {} = {}", head, decl.name)?;
+            for (ix, field) in fields.iter().enumerate() {
+                let sep = if ix == 0 { '{' } else { ',' };
+                let ty = render_type(&field.ty, &params, types, imports)?;
+                writeln!(
+                    out,
+                    "  {} {} :: {}",
+                    sep,
+                    escape_keyword(field.name.clone()),
+                    ty
+                )?;
+            }
+            writeln!(out, "  }}")?;
+        }
+        TypeBody::Struct(fields) => {
+            let con = render_constructor(&decl.name, fields, &params, types, imports)?;
+            writeln!(out, "data {} = {}", head, con)?;
+        }
+        TypeBody::Enum(variants) if variants.is_empty() => {
+            writeln!(out, "data {}", head)?;
+        }
+        TypeBody::Enum(variants) => {
+            writeln!(out, "data {}", head)?;
+            for (ix, variant) in variants.iter().enumerate() {
+                let sep = if ix == 0 { '=' } else { '|' };
+                let con =
+                    render_constructor(&variant.name, &variant.fields, &params, types, imports)?;
+                writeln!(out, "  {} {}", sep, con)?;
+            }
+        }
+    }
+    imports.pragma("DeriveAnyClass");
+    writeln!(out, "  deriving (Generic)")?;
+    writeln!(out, "  deriving anyclass (BorshSize, ToBorsh, FromBorsh)")?;
+    Ok(out)
+}
+
+/// Constructor with positional arguments
+fn render_constructor(
+    name: &str,
+    fields: &Fields,
+    params: &[String],
+    types: &TypeMap,
+    imports: &mut Imports,
+) -> Result<String> {
+    let mut con = name.to_string();
+    for ty in fields.types() {
+        con.push(' ');
+        con.push_str(&render_arg(ty, params, types, imports)?);
+    }
+    Ok(con)
+}
+
+/*******************************************************************************
+  Types
+*******************************************************************************/
+
+/// Render type in argument position (parenthesizing type applications)
+pub(super) fn render_arg(
+    ty: &TypeExpr,
+    params: &[String],
+    types: &TypeMap,
+    imports: &mut Imports,
+) -> Result<String> {
+    let rendered = render_type(ty, params, types, imports)?;
+    let is_application = match ty {
+        TypeExpr::Option(_)
+        | TypeExpr::Result(_, _)
+        | TypeExpr::Array(_, _)
+        | TypeExpr::HashMap(_, _)
+        | TypeExpr::HashSet(_) => true,
+        TypeExpr::Named { args, .. } => !args.is_empty(),
+        _ => false,
+    };
+    Ok(if is_application {
+        format!("({})", rendered)
+    } else {
+        rendered
+    })
+}
+
+pub(super) fn render_type(
+    ty: &TypeExpr,
+    params: &[String],
+    types: &TypeMap,
+    imports: &mut Imports,
+) -> Result<String> {
+    Ok(match ty {
+        TypeExpr::Primitive(prim) => render_primitive(*prim, imports).to_string(),
+        TypeExpr::Vec(t) => format!("[{}]", render_type(t, params, types, imports)?),
+        TypeExpr::Option(t) => format!("Maybe {}", render_arg(t, params, types, imports)?),
+        TypeExpr::Result(ok, err) => format!(
+            "Either {} {}",
+            render_arg(err, params, types, imports)?,
+            render_arg(ok, params, types, imports)?
+        ),
+        TypeExpr::Tuple(ts) => {
+            let ts = ts
+                .iter()
+                .map(|t| render_type(t, params, types, imports))
+                .collect::<Result<Vec<_>>>()?;
+            format!("({})", ts.join(", "))
+        }
+        TypeExpr::Array(t, n) => {
+            imports.pragma("DataKinds");
+            format!(
+                "FixedSizeArray {} {}",
+                n,
+                render_arg(t, params, types, imports)?
+            )
+        }
+        TypeExpr::HashMap(k, v) => {
+            imports.import("Data.Map.Strict (Map)");
+            format!(
+                "Map {} {}",
+                render_arg(k, params, types, imports)?,
+                render_arg(v, params, types, imports)?
+            )
+        }
+        TypeExpr::HashSet(t) => {
+            imports.import("Data.Set (Set)");
+            format!("Set {}", render_arg(t, params, types, imports)?)
+        }
+        TypeExpr::Named { name, args } => {
+            let mut rendered = match types.get_decl(name) {
+                Some(haskell_name) => mapped_name(haskell_name, imports),
+                None => name.clone(),
+            };
+            for arg in args {
+                rendered.push(' ');
+                rendered.push_str(&render_arg(arg, params, types, imports)?);
+            }
+            rendered
+        }
+        TypeExpr::Var(n) => match params.get(*n) {
+            Some(param) => param.clone(),
+            None => {
+                return Err(Error::custom(CodegenError::new(format!(
+                    "unknown type parameter {}",
+                    n
+                ))))
+            }
+        },
+        TypeExpr::Borsh(rust_type) => match types.get(rust_type)? {
+            Some(haskell_name) => mapped_name(haskell_name, imports),
+            None => {
+                return Err(Error::custom(CodegenError::new(format!(
+                    "no Haskell type known for {} (encoded using Borsh)",
+                    rust_type
+                ))))
+            }
+        },
+        TypeExpr::Bincode(_) => {
+            imports.import("Data.ByteString (ByteString)");
+            "ByteString".to_string()
+        }
+        TypeExpr::Opaque(rust_type) => match types.get(rust_type)? {
+            Some(haskell_name) => mapped_name(haskell_name, imports),
+            None => {
+                return Err(Error::custom(CodegenError::new(format!(
+                    "no Haskell type known for {} (no HaskellType instance)",
+                    rust_type
+                ))))
+            }
+        },
+    })
+}
+
+fn render_primitive(prim: Primitive, imports: &mut Imports) -> &'static str {
+    match prim {
+        Primitive::U8 | Primitive::U16 | Primitive::U32 | Primitive::U64 => {
+            imports.import("Data.Word");
+        }
+        Primitive::I8 | Primitive::I16 | Primitive::I32 | Primitive::I64 => {
+            imports.import("Data.Int");
+        }
+        Primitive::U128 | Primitive::I128 => imports.import("Data.WideWord"),
+        Primitive::String => imports.import("Data.Text (Text)"),
+        Primitive::F32 | Primitive::F64 | Primitive::Bool | Primitive::Unit => (),
+    }
+    match prim {
+        Primitive::U8 => "Word8",
+        Primitive::U16 => "Word16",
+        Primitive::U32 => "Word32",
+        Primitive::U64 => "Word64",
+        Primitive::U128 => "Word128",
+        Primitive::I8 => "Int8",
+        Primitive::I16 => "Int16",
+        Primitive::I32 => "Int32",
+        Primitive::I64 => "Int64",
+        Primitive::I128 => "Int128",
+        Primitive::F32 => "Float",
+        Primitive::F64 => "Double",
+        Primitive::Bool => "Bool",
+        Primitive::Unit => "()",
+        Primitive::String => "Text",
+    }
+}
+
+/*******************************************************************************
+  Names
+*******************************************************************************/
+
+/// Unqualified name of a mapped type, importing it from its module
+fn mapped_name(haskell_name: &str, imports: &mut Imports) -> String {
+    let (name, import) = split_qualified(haskell_name);
+    if let Some(import) = import {
+        imports.import(&import);
+    }
+    name.to_string()
+}
+
+/// Haskell type variable for a Rust type parameter (`T` becomes `t`)
+fn type_var(param: &str) -> String {
+    escape_keyword(param.to_lowercase())
+}
+
+/// Haskell variable for a Rust identifier (`max_retries` becomes `maxRetries`)
+///
+/// Used for the names of functions and their arguments; the names of record
+/// fields are determined by the `HaskellType` instances (see `naming`).
+pub(super) fn field_name(rust_name: &str) -> String {
+    escape_keyword(naming::field_name(rust_name, None, None))
+}
+
+/// Reserved identifiers in Haskell
+///
+/// Keep in sync with `HASKELL_KEYWORDS` in `haskell_ffi_derive::attr`, which
+/// rejects them as field names (the derive crate cannot depend on this one).
+const HASKELL_KEYWORDS: [&str; 23] = [
+    "_", "case", "class", "data", "default", "deriving", "do", "else", "foreign", "if", "import",
+    "in", "infix", "infixl", "infixr", "instance", "let", "module", "newtype", "of", "then",
+    "type", "where",
+];
+
+fn escape_keyword(name: String) -> String {
+    if HASKELL_KEYWORDS.contains(&name.as_str()) {
+        name + "'"
+    } else {
+        name
+    }
+}
+
+/*******************************************************************************
+  Imports and language pragmas
+*******************************************************************************/
+
+#[derive(Default)]
+pub(super) struct Imports {
+    imports: BTreeSet<String>,
+    pragmas: BTreeSet<&'static str>,
+}
+
+impl Imports {
+    pub(super) fn import(&mut self, import: &str) {
+        if !self.imports.contains(import) {
+            self.imports.insert(import.to_string());
+        }
+    }
+
+    pub(super) fn pragma(&mut self, pragma: &'static str) {
+        self.pragmas.insert(pragma);
+    }
+
+    pub(super) fn imports(&self) -> impl Iterator<Item = &String> {
+        self.imports.iter()
+    }
+
+    pub(super) fn pragmas(&self) -> impl Iterator<Item = &&'static str> {
+        self.pragmas.iter()
+    }
+}
+
+/*******************************************************************************
+  Tests
+*******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, marker::PhantomData};
+
+    use borsh::BorshSerialize;
+
+    use super::*;
+    use crate::{use_borsh::UseBorsh, ToHaskell};
+
+    enum ExampleTag {}
+
+    #[derive(HaskellType)]
+    #[allow(dead_code)]
+    struct Config {
+        max_retries: u8,
+        timeout: Option<u64>,
+        #[haskell(skip)]
+        cache: Vec<u8>,
+    }
+
+    #[derive(HaskellType)]
+    #[allow(dead_code)]
+    enum Shape<T> {
+        Empty,
+        Circle(T),
+        Rect { width: T, height: T },
+    }
+
+    #[derive(HaskellType)]
+    #[allow(dead_code)]
+    #[haskell(transparent)]
+    struct Lamports(u64);
+
+    #[derive(HaskellType)]
+    #[allow(dead_code)]
+    struct Account<'a> {
+        owner: &'a [u8; 32],
+        balance: Lamports,
+        shapes: Vec<Shape<i128>>,
+        meta: HashMap<String, (bool, f64)>,
+        result: core::result::Result<Lamports, String>,
+    }
+
+    /// Type without a `HaskellType` instance
+    struct Marker;
+
+    #[derive(HaskellType)]
+    #[allow(dead_code)]
+    struct Tagged<M, T> {
+        value: T,
+        #[haskell(skip)]
+        marker: PhantomData<M>,
+    }
+
+    #[test]
+    fn phantom_param() {
+        let mut schema = Schema::new();
+        schema.add::<ExampleTag, Tagged<Marker, u8>>();
+        assert_eq!(schema.get("Tagged").unwrap().params, ["T"]);
+        assert_eq!(
+            <Tagged<Marker, u8> as HaskellType<ExampleTag>>::haskell_type(PhantomData),
+            TypeExpr::Named {
+                name: "Tagged".to_string(),
+                args: vec![TypeExpr::Primitive(Primitive::U8)],
+            }
+        );
+    }
+
+    #[test]
+    fn schema() {
+        let mut schema = Schema::new();
+        schema.add::<ExampleTag, Account>();
+        let names: Vec<&str> = schema.decls().iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, ["Lamports", "Shape", "Account"]);
+        assert_eq!(schema.get("Shape").unwrap().params, ["T"]);
+    }
+
+    #[test]
+    fn render() -> Result<()> {
+        let mut module = HaskellModule::new("Example.Types");
+        module
+            .add::<ExampleTag, Config>()
+            .add::<ExampleTag, Account>();
+        let expected = r#"{-# LANGUAGE DataKinds #-}
+{-# LANGUAGE DeriveAnyClass #-}
+{-# LANGUAGE DeriveGeneric #-}
+{-# LANGUAGE DerivingStrategies #-}
+{-# LANGUAGE DuplicateRecordFields #-}
+{-# LANGUAGE GeneralizedNewtypeDeriving #-}
+
+-- | Generated by haskell-ffi from the Rust type definitions. Do not edit.
+module Example.Types (
+    Config(..)
+  , Lamports(..)
+  , Shape(..)
+  , Account(..)
+  ) where
+
+import Codec.Borsh
+import Data.Map.Strict (Map)
+import Data.Text (Text)
+import Data.WideWord
+import Data.Word
+import GHC.Generics (Generic)
+
+data Config = Config
+  { maxRetries :: Word8
+  , timeout :: Maybe Word64
+  }
+  deriving (Generic)
+  deriving anyclass (BorshSize, ToBorsh, FromBorsh)
+
+newtype Lamports = Lamports Word64
+  deriving (Generic)
+  deriving newtype (BorshSize, ToBorsh, FromBorsh)
+
+data Shape t
+  = Empty
+  | Circle t
+  | Rect t t
+  deriving (Generic)
+  deriving anyclass (BorshSize, ToBorsh, FromBorsh)
+
+data Account = Account
+  { owner :: FixedSizeArray 32 Word8
+  , balance :: Lamports
+  , shapes :: [Shape Int128]
+  , meta :: Map Text (Bool, Double)
+  , result :: Either Text Lamports
+  }
+  deriving (Generic)
+  deriving anyclass (BorshSize, ToBorsh, FromBorsh)
+"#;
+        assert_eq!(module.render()?, expected);
+        Ok(())
+    }
+
+    #[derive(HaskellType, ToHaskell)]
+    #[allow(dead_code)]
+    #[haskell(rename = "RetryConfig", field_prefix = "cfg")]
+    struct Retries {
+        max_retries: u8,
+        #[haskell(rename = "timeoutMillis")]
+        timeout_ms: u64,
+    }
+
+    #[derive(HaskellType, ToHaskell)]
+    #[allow(dead_code)]
+    #[haskell(rename_all = "snake_case")]
+    enum Event {
+        #[haskell(rename = "EventStarted")]
+        Started {
+            retry_config: Retries,
+        },
+        Stopped,
+    }
+
+    #[test]
+    fn renaming() -> Result<()> {
+        let mut module = HaskellModule::new("Example.Types");
+        module.add::<ExampleTag, Event>();
+        match &module.schema().get("Event").unwrap().body {
+            TypeBody::Enum(variants) => match &variants[0].fields {
+                Fields::Named(fields) => assert_eq!(fields[0].name, "retry_config"),
+                fields => panic!("unexpected fields {:?}", fields),
+            },
+            body => panic!("unexpected body {:?}", body),
+        }
+
+        let rendered = module.render()?;
+        assert!(rendered.contains(
+            "data RetryConfig = RetryConfig
+  { cfgMaxRetries :: Word8
+  , timeoutMillis :: Word64
+  }"
+        ));
+        assert!(rendered.contains(
+            "data Event
+  = EventStarted RetryConfig
+  | Stopped"
+        ));
+
+        // Names do not affect the encoding
+        let event = Event::Started {
+            retry_config: Retries {
+                max_retries: 3,
+                timeout_ms: 500,
+            },
+        };
+        let bytes = event.to_haskell_vec(PhantomData::<ExampleTag>)?;
+        assert_eq!(bytes, (0u8, 3u8, 500u64).try_to_vec()?);
+        Ok(())
+    }
+
+    #[derive(HaskellType)]
+    #[allow(dead_code)]
+    #[haskell(maps_to = "Solana.Types.Pubkey")]
+    struct Pubkey([u8; 32]);
+
+    /// Rust type without a `HaskellType` instance
+    #[allow(dead_code)]
+    struct Signature([u8; 64]);
+
+    #[derive(HaskellType)]
+    #[allow(dead_code)]
+    struct Transfer {
+        from: Pubkey,
+        signature: UseBorsh<Signature>,
+        amount: Lamports,
+        shapes: Vec<Shape<u8>>,
+    }
+
+    #[test]
+    fn type_mapping() -> Result<()> {
+        let mut module = HaskellModule::new("Example.Types");
+        module
+            .add::<ExampleTag, Transfer>()
+            .map_type(std::any::type_name::<Signature>(), "Solana.Types.Signature")
+            .map_type(
+                concat!(module_path!(), "::Lamports"),
+                "Solana.Types.Lamports",
+            )
+            .import("Example.Shapes");
+        assert_eq!(
+            module
+                .schema()
+                .mapped_types()
+                .get(concat!(module_path!(), "::Pubkey")),
+            Some(&"Solana.Types.Pubkey".to_string())
+        );
+        let expected = r#"{-# LANGUAGE DeriveAnyClass #-}
+{-# LANGUAGE DeriveGeneric #-}
+{-# LANGUAGE DerivingStrategies #-}
+{-# LANGUAGE DuplicateRecordFields #-}
+
+-- | Generated by haskell-ffi from the Rust type definitions. Do not edit.
+module Example.Types (
+    Shape(..)
+  , Transfer(..)
+  ) where
+
+import Codec.Borsh
+import Data.Word
+import Example.Shapes
+import GHC.Generics (Generic)
+import Solana.Types (Lamports)
+import Solana.Types (Pubkey)
+import Solana.Types (Signature)
+
+data Shape t
+  = Empty
+  | Circle t
+  | Rect t t
+  deriving (Generic)
+  deriving anyclass (BorshSize, ToBorsh, FromBorsh)
+
+data Transfer = Transfer
+  { from :: Pubkey
+  , signature :: Signature
+  , amount :: Lamports
+  , shapes :: [Shape Word8]
+  }
+  deriving (Generic)
+  deriving anyclass (BorshSize, ToBorsh, FromBorsh)
+"#;
+        assert_eq!(module.render()?, expected);
+        Ok(())
+    }
+
+    #[derive(HaskellType)]
+    #[allow(dead_code)]
+    struct Opaque {
+        #[haskell(via = "borsh")]
+        x: u32,
+    }
+
+    struct NoInstance;
+
+    #[test]
+    fn unknown_opaque_type() -> Result<()> {
+        let ty = TypeExpr::Opaque(std::any::type_name::<NoInstance>().to_string());
+        let err = render_type(&ty, &[], &TypeMap::new(), &mut Imports::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "no Haskell type known for haskell_ffi::codegen::haskell::tests::NoInstance \
+             (no HaskellType instance)"
+        );
+
+        let mut types = TypeMap::new();
+        types.map(
+            std::any::type_name::<NoInstance>(),
+            "Example.Other.NoInstance",
+        );
+        let mut imports = Imports::default();
+        assert_eq!(render_type(&ty, &[], &types, &mut imports)?, "NoInstance");
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_borsh_type() {
+        let mut module = HaskellModule::new("Example.Types");
+        module.add::<ExampleTag, Opaque>();
+        let err = module.render().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "no Haskell type known for u32 (encoded using Borsh)"
+        );
+    }
+}
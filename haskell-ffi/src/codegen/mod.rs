@@ -0,0 +1,111 @@
+//! Generating code for the Haskell side of the FFI boundary
+
+pub mod c_header;
+pub mod docs;
+pub mod foreign;
+pub mod haskell;
+pub mod type_map;
+
+use std::{
+    fmt::{self, Display},
+    path::PathBuf,
+};
+
+use crate::{
+    codegen::type_map::TypeMap,
+    error::{Error, Result},
+    schema::SchemaDump,
+};
+
+/*******************************************************************************
+  Generating all bindings
+*******************************************************************************/
+
+/// Names of the generated modules and header, and the mapped types
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Module declaring the types (for example `Example.Types`)
+    pub types_module: String,
+
+    /// Module containing the foreign imports (for example `Example.FFI`)
+    pub ffi_module: String,
+
+    /// File name of the C header (for example `example.h`)
+    pub header: String,
+
+    /// Rust types with an existing Haskell counterpart
+    pub type_map: TypeMap,
+}
+
+/// Generated file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeneratedFile {
+    /// Path relative to the output directory
+    pub path: PathBuf,
+
+    pub contents: String,
+}
+
+/// Haskell modules for the schema and the functions
+///
+/// The returned paths are relative to the Haskell source directory.
+pub fn generate_haskell(dump: &SchemaDump, config: &Config) -> Result<Vec<GeneratedFile>> {
+    let types = GeneratedFile {
+        path: haskell::module_path(&PathBuf::new(), &config.types_module),
+        contents: haskell::render_module(&config.types_module, &dump.schema, &config.type_map)?,
+    };
+    let ffi = GeneratedFile {
+        path: haskell::module_path(&PathBuf::new(), &config.ffi_module),
+        contents: foreign::render_foreign_module(
+            &config.ffi_module,
+            &config.header,
+            std::slice::from_ref(&config.types_module),
+            &dump.functions,
+            &dump.schema,
+            &config.type_map.resolve(&dump.schema)?,
+        )?,
+    };
+    Ok(vec![types, ffi])
+}
+
+/// C header declaring the functions
+///
+/// The returned path is relative to the directory containing the header.
+pub fn generate_header(dump: &SchemaDump, config: &Config) -> Result<GeneratedFile> {
+    Ok(GeneratedFile {
+        path: PathBuf::from(&config.header),
+        contents: c_header::render_header(&config.header, &dump.functions)?,
+    })
+}
+
+/*******************************************************************************
+  Errors
+*******************************************************************************/
+
+/// The schema cannot be translated
+#[derive(Debug)]
+pub struct CodegenError {
+    pub message: String,
+}
+
+impl CodegenError {
+    pub fn new(message: impl Into<String>) -> Self {
+        CodegenError {
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for CodegenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for CodegenError {}
+
+impl From<CodegenError> for Error {
+    fn from(err: CodegenError) -> Self {
+        Error::custom(err)
+    }
+}
@@ -0,0 +1,198 @@
+//! Mapping Rust types onto existing Haskell types
+//!
+//! Some Rust types already have a hand-written Haskell counterpart (for
+//! example, a Solana `Pubkey` encoded using `UseBorsh`, for which a Haskell
+//! library already provides a type with a matching Borsh instance). Rather
+//! than generating a declaration for such types, the generated code refers to
+//! the existing type, and imports it:
+//!
+//! ```ignore
+//! let mut type_map = TypeMap::new();
+//! type_map.map("solana_program::pubkey::Pubkey", "Solana.Types.Pubkey");
+//! ```
+//!
+//! Types are identified by their Rust path: the path reported by
+//! `std::any::type_name` for `UseBorsh<T>` and types without a `HaskellType`
+//! instance, and the `rust_path` of the declaration for types that do have
+//! one. When the bindings are generated from the source code, types are only
+//! known by the path as written; a mapping therefore also applies to a suffix
+//! of its path (`pubkey::Pubkey` matches the mapping for
+//! `solana_program::pubkey::Pubkey`), provided that the suffix matches only
+//! one mapping. A longer path never matches a shorter mapping
+//! (`my_crate::Pubkey` does not match a mapping for `Pubkey`).
+//!
+//! Types deriving `HaskellType` can also declare their Haskell counterpart
+//! using `#[haskell(maps_to = "Solana.Types.Pubkey")]`; such mappings are
+//! recorded in the schema (see `Schema::map_type`).
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{codegen::CodegenError, error::Result, schema::Schema};
+
+/// Mapping from Rust paths to module-qualified Haskell type names
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TypeMap {
+    types: BTreeMap<String, String>,
+
+    /// Additional import lines (without the `import` keyword)
+    imports: BTreeSet<String>,
+
+    /// Mapped declarations in the schema, by their Haskell name
+    ///
+    /// Computed by `resolve`.
+    decls: BTreeMap<String, String>,
+}
+
+impl TypeMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Map the Rust type onto the (module-qualified) Haskell type
+    ///
+    /// For a qualified name such as `Solana.Types.Pubkey`, the generated code
+    /// imports `Solana.Types (Pubkey)` and refers to the type as `Pubkey`. An
+    /// unqualified name is used as is; the module declaring it should then be
+    /// imported using `import`.
+    pub fn map(&mut self, rust_path: &str, haskell_name: &str) -> &mut Self {
+        self.types
+            .insert(rust_path.to_string(), haskell_name.to_string());
+        self
+    }
+
+    /// Add an import to the generated modules (for example `Solana.Types`)
+    pub fn import(&mut self, import: &str) -> &mut Self {
+        self.imports.insert(import.to_string());
+        self
+    }
+
+    /// Qualified Haskell name for the Rust type, if it is mapped
+    ///
+    /// Fails if the path is a suffix of more than one mapped path.
+    pub fn get(&self, rust_path: &str) -> Result<Option<&str>> {
+        let rust_path = rust_path.trim_start_matches("::");
+        if let Some(haskell_name) = self.types.get(rust_path) {
+            return Ok(Some(haskell_name));
+        }
+        let candidates: Vec<(&String, &String)> = self
+            .types
+            .iter()
+            .filter(|(mapped, _)| is_path_suffix(mapped, rust_path))
+            .collect();
+        match candidates.as_slice() {
+            [] => Ok(None),
+            [(_, haskell_name)] => Ok(Some(haskell_name)),
+            _ => Err(CodegenError::new(format!(
+                "{} is ambiguous: it could refer to any of {}",
+                rust_path,
+                candidates
+                    .iter()
+                    .map(|(mapped, _)| mapped.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+            .into()),
+        }
+    }
+
+    pub fn imports(&self) -> impl Iterator<Item = &str> {
+        self.imports.iter().map(|import| import.as_str())
+    }
+
+    /// Qualified Haskell name for a declaration in the schema, if it is mapped
+    pub(super) fn get_decl(&self, haskell_name: &str) -> Option<&str> {
+        self.decls.get(haskell_name).map(|name| name.as_str())
+    }
+
+    /// Combine with the mappings declared in the schema
+    ///
+    /// Mappings in `self` take precedence over those in the schema.
+    pub fn resolve(&self, schema: &Schema) -> Result<TypeMap> {
+        let mut type_map = self.clone();
+        for (rust_path, haskell_name) in schema.mapped_types() {
+            if self.get(rust_path)?.is_none() {
+                type_map.map(rust_path, haskell_name);
+            }
+        }
+        for decl in schema.decls() {
+            if let Some(haskell_name) = type_map.get(&decl.rust_path)? {
+                let haskell_name = haskell_name.to_string();
+                type_map.decls.insert(decl.name.clone(), haskell_name);
+            }
+        }
+        Ok(type_map)
+    }
+}
+
+/// Is `suffix` a suffix of `path`, consisting of complete segments?
+fn is_path_suffix(path: &str, suffix: &str) -> bool {
+    path.strip_suffix(suffix)
+        .is_some_and(|prefix| prefix.ends_with("::"))
+}
+
+/// Unqualified name and import for a qualified Haskell name
+///
+/// `Solana.Types.Pubkey` becomes `Pubkey`, with import `Solana.Types (Pubkey)`.
+pub(super) fn split_qualified(haskell_name: &str) -> (&str, Option<String>) {
+    match haskell_name.rsplit_once('.') {
+        Some((module, name)) => (name, Some(format!("{} ({})", module, name))),
+        None => (haskell_name, None),
+    }
+}
+
+/*******************************************************************************
+  Sanity checks
+*******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup() -> Result<()> {
+        let mut type_map = TypeMap::new();
+        type_map.map("solana_program::pubkey::Pubkey", "Solana.Types.Pubkey");
+        assert_eq!(
+            type_map.get("solana_program::pubkey::Pubkey")?,
+            Some("Solana.Types.Pubkey")
+        );
+        assert_eq!(type_map.get("pubkey::Pubkey")?, Some("Solana.Types.Pubkey"));
+        assert_eq!(type_map.get("Pubkey")?, Some("Solana.Types.Pubkey"));
+        assert_eq!(type_map.get("MyPubkey")?, None);
+        assert_eq!(
+            split_qualified("Solana.Types.Pubkey"),
+            ("Pubkey", Some("Solana.Types (Pubkey)".to_string()))
+        );
+        assert_eq!(split_qualified("Pubkey"), ("Pubkey", None));
+        Ok(())
+    }
+
+    #[test]
+    fn unrelated_same_name() -> Result<()> {
+        let mut type_map = TypeMap::new();
+        type_map.map("Pubkey", "Solana.Types.Pubkey");
+        assert_eq!(type_map.get("Pubkey")?, Some("Solana.Types.Pubkey"));
+        assert_eq!(type_map.get("my_crate::Pubkey")?, None);
+        assert_eq!(type_map.get("my_crate::keys::Pubkey")?, None);
+
+        let mut type_map = TypeMap::new();
+        type_map.map("solana_program::pubkey::Pubkey", "Solana.Types.Pubkey");
+        assert_eq!(type_map.get("my_crate::pubkey::Pubkey")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn ambiguous() -> Result<()> {
+        let mut type_map = TypeMap::new();
+        type_map.map("solana_program::pubkey::Pubkey", "Solana.Types.Pubkey");
+        type_map.map("other_chain::keys::Pubkey", "Other.Types.Pubkey");
+        assert_eq!(type_map.get("keys::Pubkey")?, Some("Other.Types.Pubkey"));
+        let err = type_map.get("Pubkey").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Pubkey is ambiguous: it could refer to any of \
+             other_chain::keys::Pubkey, solana_program::pubkey::Pubkey"
+        );
+        Ok(())
+    }
+}
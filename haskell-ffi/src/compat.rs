@@ -0,0 +1,586 @@
+//! Comparing schemas for wire compatibility
+//!
+//! Given the schema dumps of two versions of a library (for example, of the
+//! previous release and of the current build), `diff` lists the changes to the
+//! wire format, classified as breaking or compatible:
+//!
+//! ```ignore
+//! let old = SchemaDump::read_from(Path::new("schema-1.2.json"))?;
+//! let new = SchemaDump::read_from(Path::new("schema-1.3.json"))?;
+//! let diff = compat::diff(&old, &new);
+//! if !diff.is_compatible() {
+//!     panic!("{}", diff);
+//! }
+//! ```
+//!
+//! A change is compatible if the new version decodes everything the old
+//! version encodes, and vice versa for the values that the old version knows
+//! about. Since the Borsh encoding does not contain any names, renaming fields
+//! and constructors is compatible, while reordering fields, changing their
+//! types, and adding or removing fields are not. For enums, appending variants
+//! is compatible (old values are still decoded correctly), but removing or
+//! reordering variants is not.
+//!
+//! Types are matched up by their Haskell name, and functions by their symbol.
+
+use std::fmt::{self, Display};
+
+use crate::{
+    fingerprint::unqualified,
+    schema::{Fields, FunctionDecl, SchemaDump, TypeBody, TypeDecl, TypeExpr},
+};
+
+/*******************************************************************************
+  Changes
+*******************************************************************************/
+
+/// Change to a type or function
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    /// Type or function that changed (for example `type Point`)
+    pub item: String,
+
+    pub description: String,
+}
+
+impl Change {
+    fn new(item: &str, description: impl Into<String>) -> Self {
+        Change {
+            item: item.to_string(),
+            description: description.into(),
+        }
+    }
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.item, self.description)
+    }
+}
+
+/// Differences between two schemas
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SchemaDiff {
+    /// Changes to the wire format
+    pub breaking: Vec<Change>,
+
+    /// Changes that do not affect existing encodings
+    pub compatible: Vec<Change>,
+}
+
+impl SchemaDiff {
+    pub fn is_compatible(&self) -> bool {
+        self.breaking.is_empty()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.breaking.is_empty() && self.compatible.is_empty()
+    }
+
+    fn breaking(&mut self, item: &str, description: impl Into<String>) {
+        self.breaking.push(Change::new(item, description));
+    }
+
+    fn compatible(&mut self, item: &str, description: impl Into<String>) {
+        self.compatible.push(Change::new(item, description));
+    }
+}
+
+impl Display for SchemaDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "no changes");
+        }
+        if !self.breaking.is_empty() {
+            writeln!(f, "Breaking changes:")?;
+            for change in &self.breaking {
+                writeln!(f, "  {}", change)?;
+            }
+        }
+        if !self.compatible.is_empty() {
+            writeln!(f, "Compatible changes:")?;
+            for change in &self.compatible {
+                writeln!(f, "  {}", change)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/*******************************************************************************
+  Comparing schemas
+*******************************************************************************/
+
+/// Changes from the `old` schema to the `new` one
+pub fn diff(old: &SchemaDump, new: &SchemaDump) -> SchemaDiff {
+    let mut diff = SchemaDiff::default();
+
+    for old_decl in old.schema.decls() {
+        let item = format!("type {}", old_decl.name);
+        match new.schema.get(&old_decl.name) {
+            None => diff.breaking(&item, "removed"),
+            Some(new_decl) => diff_decls(&item, old_decl, new_decl, &mut diff),
+        }
+    }
+    for new_decl in new.schema.decls() {
+        if old.schema.get(&new_decl.name).is_none() {
+            diff.compatible(&format!("type {}", new_decl.name), "added");
+        }
+    }
+
+    for old_function in &old.functions {
+        let item = format!("function {}", old_function.symbol);
+        match new
+            .functions
+            .iter()
+            .find(|f| f.symbol == old_function.symbol)
+        {
+            None => diff.breaking(&item, "removed"),
+            Some(new_function) => diff_functions(&item, old_function, new_function, &mut diff),
+        }
+    }
+    for new_function in &new.functions {
+        if old
+            .functions
+            .iter()
+            .all(|f| f.symbol != new_function.symbol)
+        {
+            diff.compatible(&format!("function {}", new_function.symbol), "added");
+        }
+    }
+
+    diff
+}
+
+fn diff_decls(item: &str, old: &TypeDecl, new: &TypeDecl, diff: &mut SchemaDiff) {
+    if old.params.len() != new.params.len() {
+        diff.breaking(
+            item,
+            format!(
+                "number of type parameters changed from {} to {}",
+                old.params.len(),
+                new.params.len()
+            ),
+        );
+        return;
+    }
+    match (&old.body, &new.body) {
+        (TypeBody::Struct(old_fields), TypeBody::Struct(new_fields)) => {
+            diff_fields(item, old_fields, new_fields, diff)
+        }
+        (TypeBody::Transparent(old_ty), TypeBody::Transparent(new_ty)) => {
+            if !same_type(old_ty, new_ty) {
+                diff.breaking(item, format!("type changed from {} to {}", old_ty, new_ty));
+            }
+        }
+        (TypeBody::Enum(old_variants), TypeBody::Enum(new_variants)) => {
+            for (ix, old_variant) in old_variants.iter().enumerate() {
+                let moved_to = new_variants
+                    .iter()
+                    .position(|new_variant| new_variant.name == old_variant.name);
+                let new_variant = match (moved_to, new_variants.get(ix)) {
+                    (Some(new_ix), _) if new_ix != ix => {
+                        diff.breaking(
+                            item,
+                            format!(
+                                "variant {} moved from index {} to {}",
+                                old_variant.name, ix, new_ix
+                            ),
+                        );
+                        continue;
+                    }
+                    (Some(_), Some(new_variant)) => new_variant,
+                    (None, Some(new_variant))
+                        if old_variants.iter().all(|v| v.name != new_variant.name) =>
+                    {
+                        diff.compatible(
+                            item,
+                            format!(
+                                "variant {} renamed to {}",
+                                old_variant.name, new_variant.name
+                            ),
+                        );
+                        new_variant
+                    }
+                    _ => {
+                        diff.breaking(item, format!("variant {} removed", old_variant.name));
+                        continue;
+                    }
+                };
+                let variant_item = format!("{}, variant {}", item, new_variant.name);
+                diff_fields(
+                    &variant_item,
+                    &old_variant.fields,
+                    &new_variant.fields,
+                    diff,
+                );
+            }
+            for new_variant in new_variants.iter().skip(old_variants.len()) {
+                diff.compatible(item, format!("variant {} appended", new_variant.name));
+            }
+        }
+        (old_body, new_body) => diff.breaking(
+            item,
+            format!(
+                "changed from {} to {}",
+                body_kind(old_body),
+                body_kind(new_body)
+            ),
+        ),
+    }
+}
+
+fn diff_fields(item: &str, old: &Fields, new: &Fields, diff: &mut SchemaDiff) {
+    let old_types = old.types();
+    let new_types = new.types();
+    let old_names = field_names(old);
+    let new_names = field_names(new);
+
+    // Named fields whose position changed
+    let mut reordered = Vec::new();
+    for (ix, name) in old_names.iter().enumerate() {
+        if let Some(new_ix) = new_names.iter().position(|new_name| new_name == name) {
+            if new_ix != ix {
+                reordered.push(name.as_str());
+            }
+        }
+    }
+    if !reordered.is_empty() {
+        diff.breaking(item, format!("fields reordered ({})", reordered.join(", ")));
+    }
+
+    // Compare the types of the fields in the same position (unless reordered)
+    let common = if reordered.is_empty() {
+        old_types.len().min(new_types.len())
+    } else {
+        0
+    };
+    for (ix, (old_ty, new_ty)) in old_types.iter().zip(&new_types).take(common).enumerate() {
+        let name = field_label(&new_names, ix);
+        if !same_type(old_ty, new_ty) {
+            diff.breaking(
+                item,
+                format!("{} changed type from {} to {}", name, old_ty, new_ty),
+            );
+        } else if let (Some(old_name), Some(new_name)) = (old_names.get(ix), new_names.get(ix)) {
+            if old_name != new_name {
+                diff.compatible(item, format!("field {} renamed to {}", old_name, new_name));
+            }
+        }
+    }
+    for ix in new_types.len()..old_types.len() {
+        diff.breaking(item, format!("{} removed", field_label(&old_names, ix)));
+    }
+    for ix in old_types.len()..new_types.len() {
+        diff.breaking(item, format!("{} added", field_label(&new_names, ix)));
+    }
+}
+
+fn diff_functions(item: &str, old: &FunctionDecl, new: &FunctionDecl, diff: &mut SchemaDiff) {
+    if old.args.len() != new.args.len() {
+        diff.breaking(
+            item,
+            format!(
+                "number of arguments changed from {} to {}",
+                old.args.len(),
+                new.args.len()
+            ),
+        );
+    } else {
+        for (old_arg, new_arg) in old.args.iter().zip(&new.args) {
+            if !same_type(&old_arg.ty, &new_arg.ty) {
+                diff.breaking(
+                    item,
+                    format!(
+                        "argument {} changed type from {} to {}",
+                        new_arg.name, old_arg.ty, new_arg.ty
+                    ),
+                );
+            } else if old_arg.marshalling != new_arg.marshalling {
+                diff.breaking(
+                    item,
+                    format!(
+                        "argument {} changed marshalling from {:?} to {:?}",
+                        new_arg.name, old_arg.marshalling, new_arg.marshalling
+                    ),
+                );
+            }
+        }
+    }
+    match (&old.result, &new.result) {
+        (None, None) => (),
+        (Some(old_result), Some(new_result)) => {
+            if !same_type(&old_result.ty, &new_result.ty) {
+                diff.breaking(
+                    item,
+                    format!(
+                        "result changed type from {} to {}",
+                        old_result.ty, new_result.ty
+                    ),
+                );
+            } else if old_result.marshalling != new_result.marshalling {
+                diff.breaking(
+                    item,
+                    format!(
+                        "result changed marshalling from {:?} to {:?}",
+                        old_result.marshalling, new_result.marshalling
+                    ),
+                );
+            }
+        }
+        (None, Some(_)) => diff.breaking(item, "now returns a result"),
+        (Some(_), None) => diff.breaking(item, "no longer returns a result"),
+    }
+    match (old.checked, new.checked) {
+        (false, true) => diff.breaking(item, "now returns a status code"),
+        (true, false) => diff.breaking(item, "no longer returns a status code"),
+        _ => (),
+    }
+}
+
+/*******************************************************************************
+  Auxiliary
+*******************************************************************************/
+
+/// Do the types have the same encoding?
+///
+/// Declared types are compared by name (changes to their declarations are
+/// reported separately). Types identified by their Rust type are compared
+/// without the path, which depends on how the schema was obtained.
+fn same_type(old: &TypeExpr, new: &TypeExpr) -> bool {
+    let all_same = |olds: &[TypeExpr], news: &[TypeExpr]| {
+        olds.len() == news.len() && olds.iter().zip(news).all(|(a, b)| same_type(a, b))
+    };
+    match (old, new) {
+        (TypeExpr::Vec(a), TypeExpr::Vec(b))
+        | (TypeExpr::Option(a), TypeExpr::Option(b))
+        | (TypeExpr::HashSet(a), TypeExpr::HashSet(b)) => same_type(a, b),
+        (TypeExpr::Result(a1, a2), TypeExpr::Result(b1, b2))
+        | (TypeExpr::HashMap(a1, a2), TypeExpr::HashMap(b1, b2)) => {
+            same_type(a1, b1) && same_type(a2, b2)
+        }
+        (TypeExpr::Array(a, n), TypeExpr::Array(b, m)) => n == m && same_type(a, b),
+        (TypeExpr::Tuple(olds), TypeExpr::Tuple(news)) => all_same(olds, news),
+        (
+            TypeExpr::Named { name, args },
+            TypeExpr::Named {
+                name: new_name,
+                args: new_args,
+            },
+        ) => name == new_name && all_same(args, new_args),
+        (TypeExpr::Borsh(a), TypeExpr::Borsh(b))
+        | (TypeExpr::Bincode(a), TypeExpr::Bincode(b))
+        | (TypeExpr::Opaque(a), TypeExpr::Opaque(b)) => unqualified(a) == unqualified(b),
+        (old, new) => old == new,
+    }
+}
+
+fn field_names(fields: &Fields) -> Vec<String> {
+    match fields {
+        Fields::Named(fields) => fields.iter().map(|field| field.name.clone()).collect(),
+        Fields::Unnamed(_) | Fields::Unit => Vec::new(),
+    }
+}
+
+/// `field x` for named fields, `field 0` for positional ones
+fn field_label(names: &[String], ix: usize) -> String {
+    match names.get(ix) {
+        Some(name) => format!("field {}", name),
+        None => format!("field {}", ix),
+    }
+}
+
+fn body_kind(body: &TypeBody) -> &'static str {
+    match body {
+        TypeBody::Struct(_) => "struct",
+        TypeBody::Enum(_) => "enum",
+        TypeBody::Transparent(_) => "transparent newtype",
+    }
+}
+
+/*******************************************************************************
+  Sanity checks
+*******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{FunctionArg, FunctionResult, Marshalling, Primitive};
+
+    enum ExampleTag {}
+
+    mod v1 {
+        use crate::HaskellType;
+
+        #[derive(HaskellType)]
+        #[allow(dead_code)]
+        pub struct Point {
+            pub x: i32,
+            pub y: i32,
+        }
+
+        #[derive(HaskellType)]
+        #[allow(dead_code)]
+        pub struct Account {
+            pub owner: [u8; 32],
+            pub balance: u64,
+        }
+
+        #[derive(HaskellType)]
+        #[allow(dead_code)]
+        pub enum Shape {
+            Empty,
+            Circle(Point, u32),
+            Polygon(Vec<Point>),
+        }
+
+        #[derive(HaskellType)]
+        #[allow(dead_code)]
+        pub enum Event {
+            Started,
+            Stopped,
+            Paused,
+        }
+
+        #[derive(HaskellType)]
+        #[allow(dead_code)]
+        pub struct Legacy(u8);
+    }
+
+    mod v2 {
+        use crate::HaskellType;
+
+        #[derive(HaskellType)]
+        #[allow(dead_code)]
+        pub struct Point {
+            pub y: i32,
+            pub x: i32,
+        }
+
+        #[derive(HaskellType)]
+        #[allow(dead_code)]
+        pub struct Account {
+            pub key: [u8; 32],
+            pub balance: u128,
+        }
+
+        #[derive(HaskellType)]
+        #[allow(dead_code)]
+        pub enum Shape {
+            Empty,
+            Disc(Point, u32),
+            Polygon(Vec<Point>),
+            Rect(Point, Point),
+        }
+
+        #[derive(HaskellType)]
+        #[allow(dead_code)]
+        pub enum Event {
+            Started,
+            Paused,
+        }
+
+        #[derive(HaskellType)]
+        #[allow(dead_code)]
+        pub struct Label(String);
+    }
+
+    fn dump_v1() -> SchemaDump {
+        let mut dump = SchemaDump::new();
+        dump.add::<ExampleTag, v1::Account>()
+            .add::<ExampleTag, v1::Shape>()
+            .add::<ExampleTag, v1::Event>()
+            .add::<ExampleTag, v1::Legacy>();
+        dump
+    }
+
+    fn dump_v2() -> SchemaDump {
+        let mut dump = SchemaDump::new();
+        dump.add::<ExampleTag, v2::Account>()
+            .add::<ExampleTag, v2::Shape>()
+            .add::<ExampleTag, v2::Event>()
+            .add::<ExampleTag, v2::Label>();
+        dump
+    }
+
+    #[test]
+    fn unchanged() {
+        let diff = diff(&dump_v1(), &dump_v1());
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_string(), "no changes\n");
+    }
+
+    #[test]
+    fn changes() {
+        let diff = diff(&dump_v1(), &dump_v2());
+        assert!(!diff.is_compatible());
+        assert_eq!(
+            diff.to_string(),
+            "Breaking changes:
+  type Account: field balance changed type from u64 to u128
+  type Point: fields reordered (x, y)
+  type Event: variant Stopped removed
+  type Event: variant Paused moved from index 2 to 1
+  type Legacy: removed
+Compatible changes:
+  type Account: field owner renamed to key
+  type Shape: variant Circle renamed to Disc
+  type Shape: variant Rect appended
+  type Label: added
+"
+        );
+    }
+
+    fn function(symbol: &str, args: Vec<TypeExpr>, result: Option<TypeExpr>) -> FunctionDecl {
+        FunctionDecl {
+            symbol: symbol.to_string(),
+            args: args
+                .into_iter()
+                .enumerate()
+                .map(|(ix, ty)| FunctionArg {
+                    name: format!("arg{}", ix),
+                    ty,
+                    marshalling: Marshalling::Var,
+                })
+                .collect(),
+            result: result.map(|ty| FunctionResult {
+                ty,
+                marshalling: Marshalling::Var,
+            }),
+            checked: true,
+        }
+    }
+
+    #[test]
+    fn functions() {
+        let u32 = TypeExpr::Primitive(Primitive::U32);
+        let string = TypeExpr::Primitive(Primitive::String);
+        let mut old = SchemaDump::new();
+        old.functions = vec![
+            function("lookup", vec![u32.clone()], Some(string.clone())),
+            function("reset", vec![], None),
+            function("version", vec![], Some(u32.clone())),
+        ];
+        let mut new = SchemaDump::new();
+        new.functions = vec![
+            function("lookup", vec![string.clone()], Some(string)),
+            function("reset", vec![], Some(u32.clone())),
+            function("stats", vec![], Some(u32)),
+        ];
+        new.functions[0].checked = false;
+        let diff = diff(&old, &new);
+        assert_eq!(
+            diff.breaking,
+            [
+                Change::new(
+                    "function lookup",
+                    "argument arg0 changed type from u32 to String"
+                ),
+                Change::new("function lookup", "no longer returns a status code"),
+                Change::new("function reset", "now returns a result"),
+                Change::new("function version", "removed"),
+            ]
+        );
+        assert_eq!(diff.compatible, [Change::new("function stats", "added")]);
+    }
+}
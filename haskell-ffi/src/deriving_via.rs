@@ -103,7 +103,7 @@ impl<Tag, T: ToHaskell<Tag>> BorshSerialize for Haskell<Tag, T> {
     fn serialize<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
         match self.0.to_haskell(writer, PhantomData) {
             Ok(_) => Ok(()),
-            Err(e) => Err(std::io::Error::new(std::io::ErrorKind::Other, e)),
+            Err(e) => Err(std::io::Error::other(e)),
         }
     }
 }
@@ -113,7 +113,7 @@ impl<Tag, T: FromHaskell<Tag>> BorshDeserialize for Haskell<Tag, T> {
         let tag: PhantomData<Tag> = PhantomData;
         match T::from_haskell(buf, tag).map(tag_val) {
             Ok(x) => Ok(x),
-            Err(e) => Err(std::io::Error::new(std::io::ErrorKind::Other, e)),
+            Err(e) => Err(std::io::Error::other(e)),
         }
     }
 }
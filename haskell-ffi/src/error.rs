@@ -1,2 +1,344 @@
-pub type Error = Box<dyn std::error::Error + Send + Sync>;
+use std::{
+    cell::RefCell,
+    fmt::{self, Display},
+    io::{self, ErrorKind},
+    num::TryFromIntError,
+    panic::{self, AssertUnwindSafe},
+};
+
 pub type Result<T> = core::result::Result<T, Error>;
+
+/*******************************************************************************
+  Errors
+*******************************************************************************/
+
+/// Failure to encode or decode a value
+///
+/// Decoding failures have their own variants, so that callers (and tests) can
+/// match on the cause; see `Error::cause` to look past the field context added
+/// by the derived instances. Errors from elsewhere (I/O, `bincode`, user code)
+/// are kept as they are, and remain available through `source`.
+#[derive(Debug)]
+pub enum Error {
+    /// The input ended before the value was fully decoded
+    UnexpectedEof,
+
+    /// The value was decoded, but not all of the input was used
+    TrailingBytes,
+
+    /// Invalid variant index when decoding an enum
+    InvalidTag { type_name: &'static str, tag: u8 },
+
+    /// `bool` encoded as something other than 0 or 1
+    InvalidBool(u8),
+
+    /// The buffer sizes on the Haskell and Rust sides do not agree
+    SizeMismatch {
+        expected: usize,
+        actual: usize,
+        type_name: &'static str,
+    },
+
+    /// Failure to decode a specific field
+    Field(Box<FieldError>),
+
+    /// I/O error (other than running out of input)
+    Io(io::Error),
+
+    /// Any other error
+    Custom(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl Error {
+    /// Wrap an arbitrary error (or error message)
+    pub fn custom(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        Error::Custom(err.into())
+    }
+
+    /// Buffer of size `actual` where a buffer for a `T` of size `expected` was
+    /// expected
+    pub fn size_mismatch<T: ?Sized>(expected: usize, actual: usize) -> Self {
+        Error::SizeMismatch {
+            expected,
+            actual,
+            type_name: std::any::type_name::<T>(),
+        }
+    }
+
+    /// The underlying error, without the field context
+    pub fn cause(&self) -> &Error {
+        match self {
+            Error::Field(err) => err.source.cause(),
+            _ => self,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnexpectedEof => write!(f, "Unexpected end of input"),
+            Error::TrailingBytes => write!(f, "Not all bytes read"),
+            Error::InvalidTag { type_name, tag } => {
+                write!(f, "Unexpected variant index {} for {}", tag, type_name)
+            }
+            Error::InvalidBool(b) => write!(f, "Invalid bool {}", b),
+            Error::SizeMismatch {
+                expected,
+                actual,
+                type_name,
+            } => write!(
+                f,
+                "expected buffer of size {} for {}, but got {}",
+                expected, type_name, actual
+            ),
+            Error::Field(err) => err.fmt(f),
+            Error::Io(err) => err.fmt(f),
+            Error::Custom(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Field(err) => Some(&err.source),
+            Error::Io(err) => err.source(),
+            Error::Custom(err) => err.source(),
+            _ => None,
+        }
+    }
+}
+
+/*******************************************************************************
+  Conversions
+*******************************************************************************/
+
+/// Message used by `borsh` when the input is too short
+const BORSH_UNEXPECTED_LENGTH_OF_INPUT: &str = "Unexpected length of input";
+
+/// Message used by `borsh` when not all of the input was used
+const BORSH_NOT_ALL_BYTES_READ: &str = "Not all bytes read";
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        // Our own errors come back wrapped in an `io::Error` when a `Borsh`
+        // instance forwards to a `FromHaskell` instance (see `deriving_via`)
+        if err.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            let inner = err.into_inner().expect("checked above");
+            return *inner.downcast::<Error>().expect("checked above");
+        }
+        // `borsh` reports all decoding errors as `InvalidInput` or `InvalidData`
+        let message = err.get_ref().map(|inner| inner.to_string());
+        match (err.kind(), message.as_deref()) {
+            (ErrorKind::UnexpectedEof, _) => Error::UnexpectedEof,
+            (ErrorKind::InvalidInput, Some(BORSH_UNEXPECTED_LENGTH_OF_INPUT)) => {
+                Error::UnexpectedEof
+            }
+            (ErrorKind::InvalidData, Some(BORSH_NOT_ALL_BYTES_READ)) => Error::TrailingBytes,
+            _ => Error::Io(err),
+        }
+    }
+}
+
+impl From<fmt::Error> for Error {
+    fn from(err: fmt::Error) -> Self {
+        Error::custom(err)
+    }
+}
+
+impl From<TryFromIntError> for Error {
+    fn from(err: TryFromIntError) -> Self {
+        Error::custom(err)
+    }
+}
+
+impl From<bincode::Error> for Error {
+    fn from(err: bincode::Error) -> Self {
+        Error::Custom(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::custom(err)
+    }
+}
+
+/*******************************************************************************
+  Additional context
+*******************************************************************************/
+
+/// Failure to decode a specific field
+///
+/// This is used by the derived `FromHaskell` instances, so that errors point
+/// to the field that the Haskell side got wrong (e.g. `Config.timeout`).
+#[derive(Debug)]
+pub struct FieldError {
+    pub type_name: &'static str,
+    pub field: &'static str,
+    pub source: Error,
+}
+
+impl FieldError {
+    pub fn wrap(type_name: &'static str, field: &'static str, source: Error) -> Error {
+        Error::Field(Box::new(FieldError {
+            type_name,
+            field,
+            source,
+        }))
+    }
+}
+
+impl Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}: {}", self.type_name, self.field, self.source)
+    }
+}
+
+impl std::error::Error for FieldError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Invalid variant index when decoding an enum
+pub fn invalid_variant_index(type_name: &'static str, ix: u8) -> Error {
+    Error::InvalidTag { type_name, tag: ix }
+}
+
+/*******************************************************************************
+  Status codes
+*******************************************************************************/
+
+/// Outcome of a call across the C ABI
+///
+/// Returned by the `catch_marshall_*` functions, and by the functions exported
+/// using `#[haskell_export]` (unless they are marked `unchecked`).
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Status {
+    Ok = 0,
+
+    /// The value could not be encoded or decoded, or the buffer sizes on the
+    /// Haskell and Rust sides do not agree
+    Error = 1,
+
+    /// The Rust code panicked
+    Panic = 2,
+}
+
+impl Status {
+    /// Run `f`, catching panics as well as errors
+    ///
+    /// The error (or the panic message) is recorded as the last error on the
+    /// current thread (see `last_error`).
+    pub fn catch<T>(f: impl FnOnce() -> Result<T>) -> core::result::Result<T, Status> {
+        match panic::catch_unwind(AssertUnwindSafe(f)) {
+            Ok(Ok(t)) => Ok(t),
+            Ok(Err(err)) => {
+                set_last_error(Status::Error, err.to_string());
+                Err(Status::Error)
+            }
+            Err(payload) => {
+                let message = if let Some(message) = payload.downcast_ref::<&str>() {
+                    message.to_string()
+                } else if let Some(message) = payload.downcast_ref::<String>() {
+                    message.clone()
+                } else {
+                    "panic with non-string payload".to_string()
+                };
+                set_last_error(Status::Panic, message);
+                Err(Status::Panic)
+            }
+        }
+    }
+}
+
+impl<T> From<core::result::Result<T, Status>> for Status {
+    fn from(result: core::result::Result<T, Status>) -> Self {
+        match result {
+            Ok(_) => Status::Ok,
+            Err(status) => status,
+        }
+    }
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Ok => write!(f, "ok"),
+            Status::Error => write!(f, "invalid encoding"),
+            Status::Panic => write!(f, "panic"),
+        }
+    }
+}
+
+/*******************************************************************************
+  Last error
+
+  The status code only says that a call failed; the reason is recorded in
+  thread-local storage, from where the Haskell side can retrieve it using the
+  `haskell_ffi_last_error_*` functions. Successful calls do not clear it.
+
+  Since the error is recorded per OS thread, it should be retrieved right after
+  the failed call, on the same OS thread. The generated Haskell wrappers (see
+  `codegen::foreign`) therefore make the call in a bound thread; hand-written
+  Haskell code should do the same.
+*******************************************************************************/
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<(Status, String)>> = const { RefCell::new(None) };
+}
+
+/// Last error recorded on the current thread, along with its kind
+pub fn last_error() -> Option<(Status, String)> {
+    LAST_ERROR.with(|last_error| last_error.borrow().clone())
+}
+
+pub fn set_last_error(kind: Status, message: String) {
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some((kind, message)));
+}
+
+pub fn clear_last_error() {
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = None);
+}
+
+/// Kind of the last error (`Ok` if there is none)
+///
+/// User code should not normally need to call this directly (it is called by
+/// the generated Haskell wrappers).
+#[no_mangle]
+pub extern "C" fn haskell_ffi_last_error_kind() -> Status {
+    LAST_ERROR.with(|last_error| match &*last_error.borrow() {
+        Some((kind, _)) => *kind,
+        None => Status::Ok,
+    })
+}
+
+/// Pointer to the UTF-8 encoded message of the last error (null if none)
+///
+/// The pointer is valid until the next error on the same thread, or until
+/// `haskell_ffi_last_error_clear` is called.
+#[no_mangle]
+pub extern "C" fn haskell_ffi_last_error_ptr() -> *const u8 {
+    LAST_ERROR.with(|last_error| match &*last_error.borrow() {
+        Some((_, message)) => message.as_ptr(),
+        None => std::ptr::null(),
+    })
+}
+
+/// Length (in bytes) of the message of the last error
+#[no_mangle]
+pub extern "C" fn haskell_ffi_last_error_len() -> usize {
+    LAST_ERROR.with(|last_error| match &*last_error.borrow() {
+        Some((_, message)) => message.len(),
+        None => 0,
+    })
+}
+
+/// Clear the last error
+#[no_mangle]
+pub extern "C" fn haskell_ffi_last_error_clear() {
+    clear_last_error()
+}
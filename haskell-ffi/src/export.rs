@@ -0,0 +1,535 @@
+//! Support for `#[haskell_export]`
+//!
+//! The wrappers generated by `haskell_export` need to choose between fixed,
+//! max and variable-sized marshalling, depending on which instances are
+//! available for the argument and result types. Since this is not known to the
+//! macro, we use "autoref specialization": method resolution tries the receiver
+//! `&&&Marshaller` first, then `&&Marshaller`, etc., and we give the preferred
+//! implementation the receiver that is tried first.
+//!
+//! By default, the wrappers catch panics (in the exported function as well as
+//! during marshalling) and return a `Status` (see the `catch_marshall_*`
+//! functions); unwinding out of an `extern "C"` function would abort the
+//! process. Functions marked `#[haskell_export(unchecked)]` panic instead.
+//!
+//! Arguments cannot be called `out`, since the wrapper passes the argument `x`
+//! as `x_ptr` and `x_len`, and the output buffer as `out` and `out_len`:
+//!
+//! ```compile_fail
+//! use haskell_ffi::haskell_export;
+//!
+//! #[haskell_export]
+//! fn copy(out: u32) -> u32 {
+//!     out
+//! }
+//! ```
+
+use std::marker::PhantomData;
+
+use crate::{
+    error::Status,
+    from_haskell::{
+        catch_marshall_from_haskell_fixed, catch_marshall_from_haskell_var,
+        marshall_from_haskell_fixed, marshall_from_haskell_var,
+    },
+    schema::{FunctionDecl, Marshalling, Schema, TypeExpr},
+    to_haskell::{
+        catch_marshall_to_haskell_fixed, catch_marshall_to_haskell_max,
+        catch_marshall_to_haskell_var, marshall_to_haskell_fixed, marshall_to_haskell_max,
+        marshall_to_haskell_var,
+    },
+    FromHaskell, HaskellMaxSize, HaskellSize, HaskellType, ToHaskell,
+};
+
+pub use haskell_ffi_derive::haskell_export;
+
+/*******************************************************************************
+  Results
+*******************************************************************************/
+
+/// Marshall results of type `T`
+#[doc(hidden)]
+pub struct Marshaller<Tag, T>(PhantomData<(Tag, T)>);
+
+impl<Tag, T> Marshaller<Tag, T> {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Marshaller(PhantomData)
+    }
+}
+
+/// Preferred: types with a `HaskellSize` instance
+#[doc(hidden)]
+pub trait MarshallFixed<Tag, T> {
+    fn marshall(&self, t: &T, out: *mut u8, out_len: *mut usize, tag: PhantomData<Tag>);
+
+    fn catch_marshall(
+        &self,
+        t: &T,
+        out: *mut u8,
+        out_len: *mut usize,
+        tag: PhantomData<Tag>,
+    ) -> Status;
+
+    fn marshalling(&self) -> Marshalling {
+        Marshalling::Fixed
+    }
+}
+
+/// Otherwise: types with a `HaskellMaxSize` instance
+#[doc(hidden)]
+pub trait MarshallMax<Tag, T> {
+    fn marshall(&self, t: &T, out: *mut u8, out_len: *mut usize, tag: PhantomData<Tag>);
+
+    fn catch_marshall(
+        &self,
+        t: &T,
+        out: *mut u8,
+        out_len: *mut usize,
+        tag: PhantomData<Tag>,
+    ) -> Status;
+
+    fn marshalling(&self) -> Marshalling {
+        Marshalling::Max
+    }
+}
+
+/// Fallback: variable-sized encoding
+#[doc(hidden)]
+pub trait MarshallVar<Tag, T> {
+    fn marshall(&self, t: &T, out: *mut u8, out_len: *mut usize, tag: PhantomData<Tag>);
+
+    fn catch_marshall(
+        &self,
+        t: &T,
+        out: *mut u8,
+        out_len: *mut usize,
+        tag: PhantomData<Tag>,
+    ) -> Status;
+
+    fn marshalling(&self) -> Marshalling {
+        Marshalling::Var
+    }
+}
+
+impl<Tag, T> MarshallFixed<Tag, T> for &&Marshaller<Tag, T>
+where
+    T: HaskellSize<Tag> + ToHaskell<Tag>,
+{
+    fn marshall(&self, t: &T, out: *mut u8, out_len: *mut usize, tag: PhantomData<Tag>) {
+        marshall_to_haskell_fixed(t, out, unsafe { *out_len }, tag)
+    }
+
+    fn catch_marshall(
+        &self,
+        t: &T,
+        out: *mut u8,
+        out_len: *mut usize,
+        tag: PhantomData<Tag>,
+    ) -> Status {
+        catch_marshall_to_haskell_fixed(t, out, unsafe { *out_len }, tag)
+    }
+}
+
+impl<Tag, T> MarshallMax<Tag, T> for &Marshaller<Tag, T>
+where
+    T: HaskellMaxSize<Tag> + ToHaskell<Tag>,
+{
+    fn marshall(&self, t: &T, out: *mut u8, out_len: *mut usize, tag: PhantomData<Tag>) {
+        marshall_to_haskell_max(t, out, unsafe { *out_len }, tag)
+    }
+
+    fn catch_marshall(
+        &self,
+        t: &T,
+        out: *mut u8,
+        out_len: *mut usize,
+        tag: PhantomData<Tag>,
+    ) -> Status {
+        catch_marshall_to_haskell_max(t, out, unsafe { *out_len }, tag)
+    }
+}
+
+impl<Tag, T> MarshallVar<Tag, T> for Marshaller<Tag, T>
+where
+    T: ToHaskell<Tag>,
+{
+    fn marshall(&self, t: &T, out: *mut u8, out_len: *mut usize, tag: PhantomData<Tag>) {
+        marshall_to_haskell_var(t, out, unsafe { &mut *out_len }, tag)
+    }
+
+    fn catch_marshall(
+        &self,
+        t: &T,
+        out: *mut u8,
+        out_len: *mut usize,
+        tag: PhantomData<Tag>,
+    ) -> Status {
+        catch_marshall_to_haskell_var(t, out, unsafe { &mut *out_len }, tag)
+    }
+}
+
+/*******************************************************************************
+  Arguments
+*******************************************************************************/
+
+/// Unmarshall arguments of type `T`
+#[doc(hidden)]
+pub struct Unmarshaller<Tag, T>(PhantomData<(Tag, T)>);
+
+impl<Tag, T> Unmarshaller<Tag, T> {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Unmarshaller(PhantomData)
+    }
+}
+
+/// Preferred: types with a `HaskellSize` instance
+#[doc(hidden)]
+pub trait UnmarshallFixed<Tag, T> {
+    fn unmarshall(&self, inp: *const u8, inp_len: usize, tag: PhantomData<Tag>) -> T;
+    fn catch_unmarshall(
+        &self,
+        inp: *const u8,
+        inp_len: usize,
+        tag: PhantomData<Tag>,
+    ) -> core::result::Result<T, Status>;
+
+    fn marshalling(&self) -> Marshalling {
+        Marshalling::Fixed
+    }
+}
+
+/// Fallback: variable-sized encoding
+#[doc(hidden)]
+pub trait UnmarshallVar<Tag, T> {
+    fn unmarshall(&self, inp: *const u8, inp_len: usize, tag: PhantomData<Tag>) -> T;
+    fn catch_unmarshall(
+        &self,
+        inp: *const u8,
+        inp_len: usize,
+        tag: PhantomData<Tag>,
+    ) -> core::result::Result<T, Status>;
+
+    fn marshalling(&self) -> Marshalling {
+        Marshalling::Var
+    }
+}
+
+impl<Tag, T> UnmarshallFixed<Tag, T> for &Unmarshaller<Tag, T>
+where
+    T: HaskellSize<Tag> + FromHaskell<Tag>,
+{
+    fn unmarshall(&self, inp: *const u8, inp_len: usize, tag: PhantomData<Tag>) -> T {
+        marshall_from_haskell_fixed(inp, inp_len, tag)
+    }
+
+    fn catch_unmarshall(
+        &self,
+        inp: *const u8,
+        inp_len: usize,
+        tag: PhantomData<Tag>,
+    ) -> core::result::Result<T, Status> {
+        catch_marshall_from_haskell_fixed(inp, inp_len, tag)
+    }
+}
+
+impl<Tag, T> UnmarshallVar<Tag, T> for Unmarshaller<Tag, T>
+where
+    T: FromHaskell<Tag>,
+{
+    fn unmarshall(&self, inp: *const u8, inp_len: usize, tag: PhantomData<Tag>) -> T {
+        marshall_from_haskell_var(inp, inp_len, tag)
+    }
+
+    fn catch_unmarshall(
+        &self,
+        inp: *const u8,
+        inp_len: usize,
+        tag: PhantomData<Tag>,
+    ) -> core::result::Result<T, Status> {
+        catch_marshall_from_haskell_var(inp, inp_len, tag)
+    }
+}
+
+/*******************************************************************************
+  Describing types
+
+  Arguments and results do not need a `HaskellType` instance; if they do not
+  have one, they are described by the name of the Rust type.
+*******************************************************************************/
+
+/// Describe types `T`
+#[doc(hidden)]
+pub struct Describer<Tag, T>(PhantomData<(Tag, T)>);
+
+impl<Tag, T> Describer<Tag, T> {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Describer(PhantomData)
+    }
+}
+
+/// Preferred: types with a `HaskellType` instance
+#[doc(hidden)]
+pub trait DescribeKnown<Tag> {
+    fn describe(&self, schema: &mut Schema) -> TypeExpr;
+}
+
+/// Fallback: opaque types
+#[doc(hidden)]
+pub trait DescribeOpaque<Tag> {
+    fn describe(&self, schema: &mut Schema) -> TypeExpr;
+}
+
+impl<Tag, T: HaskellType<Tag>> DescribeKnown<Tag> for &Describer<Tag, T> {
+    fn describe(&self, schema: &mut Schema) -> TypeExpr {
+        T::haskell_decls(PhantomData, schema);
+        T::haskell_type(PhantomData)
+    }
+}
+
+impl<Tag, T> DescribeOpaque<Tag> for Describer<Tag, T> {
+    fn describe(&self, _schema: &mut Schema) -> TypeExpr {
+        TypeExpr::Opaque(std::any::type_name::<T>().to_string())
+    }
+}
+
+/*******************************************************************************
+  Registry of exported functions
+*******************************************************************************/
+
+/// Function exported using `#[haskell_export]`
+pub struct HaskellExport {
+    /// Name of the exported symbol
+    pub symbol: &'static str,
+
+    /// Describe the function, adding the types it uses to the schema
+    pub describe: fn(&mut Schema) -> FunctionDecl,
+}
+
+inventory::collect!(HaskellExport);
+
+/// All functions exported using `#[haskell_export]`, ordered by symbol
+///
+/// This includes the functions exported by all crates linked into the
+/// current binary.
+pub fn exports() -> Vec<&'static HaskellExport> {
+    let mut exports: Vec<_> = inventory::iter::<HaskellExport>.into_iter().collect();
+    exports.sort_by_key(|export| export.symbol);
+    exports
+}
+
+/*******************************************************************************
+  Sanity checks
+*******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use borsh::BorshSerialize;
+
+    use super::*;
+    use crate::{
+        error::{
+            haskell_ffi_last_error_clear, haskell_ffi_last_error_kind, haskell_ffi_last_error_len,
+            haskell_ffi_last_error_ptr, last_error, Result,
+        },
+        schema::Primitive,
+        to_haskell::haskell_ffi_external_free,
+    };
+
+    #[derive(Debug, PartialEq, ToHaskell, FromHaskell, HaskellMaxSize)]
+    enum Reply {
+        Ack,
+        Value(u64),
+    }
+
+    #[haskell_export(name = "haskell_ffi_test_add")]
+    fn add(a: u32, b: &u32) -> u64 {
+        a as u64 + *b as u64
+    }
+
+    #[haskell_export(name = "haskell_ffi_test_greet")]
+    fn greet(name: String) -> core::result::Result<String, String> {
+        if name.is_empty() {
+            Err("empty name".to_string())
+        } else {
+            Ok(format!("Hello, {}!", name))
+        }
+    }
+
+    #[haskell_export(name = "haskell_ffi_test_join")]
+    fn join(sep: &str, words: &[String]) -> String {
+        words.join(sep)
+    }
+
+    #[haskell_export(name = "haskell_ffi_test_reply")]
+    fn reply(value: Option<u64>) -> Reply {
+        value.map_or(Reply::Ack, Reply::Value)
+    }
+
+    #[haskell_export(name = "haskell_ffi_test_bytes", external)]
+    fn bytes(n: u8) -> Vec<u8> {
+        vec![n; n as usize]
+    }
+
+    #[haskell_export(name = "haskell_ffi_test_halve")]
+    fn halve(n: u32) -> u32 {
+        assert!(n.is_multiple_of(2), "odd number");
+        n / 2
+    }
+
+    #[haskell_export(name = "haskell_ffi_test_double", unchecked)]
+    fn double(n: u32) -> u32 {
+        n * 2
+    }
+
+    #[test]
+    fn fixed() -> Result<()> {
+        let a = 1u32.try_to_vec()?;
+        let b = 2u32.try_to_vec()?;
+        let mut out = [0u8; 8];
+        let mut out_len = out.len();
+        let status = __haskell_export_add(
+            a.as_ptr(),
+            a.len(),
+            b.as_ptr(),
+            b.len(),
+            out.as_mut_ptr(),
+            &mut out_len,
+        );
+        assert_eq!(status, Status::Ok);
+        assert_eq!(out.to_vec(), 3u64.try_to_vec()?);
+        Ok(())
+    }
+
+    #[test]
+    fn var() -> Result<()> {
+        let name = "Haskell".to_string().try_to_vec()?;
+        let expected = Ok::<_, String>("Hello, Haskell!".to_string()).try_to_vec()?;
+
+        // Buffer too small: only the required size is reported
+        let mut out = vec![0u8; 4];
+        let mut out_len = out.len();
+        let status =
+            __haskell_export_greet(name.as_ptr(), name.len(), out.as_mut_ptr(), &mut out_len);
+        assert_eq!(status, Status::Ok);
+        assert_eq!(out_len, expected.len());
+
+        out.resize(out_len, 0);
+        let status =
+            __haskell_export_greet(name.as_ptr(), name.len(), out.as_mut_ptr(), &mut out_len);
+        assert_eq!(status, Status::Ok);
+        assert_eq!(out, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn borrowed() -> Result<()> {
+        let sep = ", ".to_string().try_to_vec()?;
+        let words = vec!["a".to_string(), "b".to_string()].try_to_vec()?;
+        let mut out = vec![0u8; 16];
+        let mut out_len = out.len();
+        let status = __haskell_export_join(
+            sep.as_ptr(),
+            sep.len(),
+            words.as_ptr(),
+            words.len(),
+            out.as_mut_ptr(),
+            &mut out_len,
+        );
+        assert_eq!(status, Status::Ok);
+        out.truncate(out_len);
+        assert_eq!(out, "a, b".to_string().try_to_vec()?);
+
+        // Described as `String` and `Vec<String>`
+        let export = exports()
+            .into_iter()
+            .find(|export| export.symbol == "haskell_ffi_test_join")
+            .unwrap();
+        let decl = (export.describe)(&mut Schema::new());
+        let string = TypeExpr::Primitive(Primitive::String);
+        assert_eq!(decl.args[0].ty, string);
+        assert_eq!(decl.args[1].ty, TypeExpr::Vec(Box::new(string)));
+        Ok(())
+    }
+
+    #[test]
+    fn max() -> Result<()> {
+        let tag: PhantomData<()> = PhantomData;
+        let value = Some(5u64).try_to_vec()?;
+        let mut out = vec![0u8; Reply::haskell_max_size(tag)];
+        let mut out_len = out.len();
+        let status =
+            __haskell_export_reply(value.as_ptr(), value.len(), out.as_mut_ptr(), &mut out_len);
+        assert_eq!(status, Status::Ok);
+        assert_eq!(Reply::from_haskell_slice(&out, tag)?, Reply::Value(5));
+        Ok(())
+    }
+
+    #[test]
+    fn external() -> Result<()> {
+        let n = 3u8.try_to_vec()?;
+        let mut vec = std::ptr::null_mut();
+        let status = __haskell_export_bytes(n.as_ptr(), n.len(), &mut vec);
+        assert_eq!(status, Status::Ok);
+        assert_eq!(unsafe { &*vec }, &vec![3u8, 3, 3].try_to_vec()?);
+        haskell_ffi_external_free(vec);
+        Ok(())
+    }
+
+    #[test]
+    fn status() -> Result<()> {
+        let mut out = [0u8; 4];
+        let mut out_len = out.len();
+
+        let even = 4u32.try_to_vec()?;
+        let status =
+            __haskell_export_halve(even.as_ptr(), even.len(), out.as_mut_ptr(), &mut out_len);
+        assert_eq!(status, Status::Ok);
+        assert_eq!(out.to_vec(), 2u32.try_to_vec()?);
+
+        // The function panics
+        let odd = 3u32.try_to_vec()?;
+        let status =
+            __haskell_export_halve(odd.as_ptr(), odd.len(), out.as_mut_ptr(), &mut out_len);
+        assert_eq!(status, Status::Panic);
+        assert_eq!(
+            last_error(),
+            Some((Status::Panic, "odd number".to_string()))
+        );
+
+        // Argument of the wrong size
+        let short = 4u16.try_to_vec()?;
+        let status =
+            __haskell_export_halve(short.as_ptr(), short.len(), out.as_mut_ptr(), &mut out_len);
+        assert_eq!(status, Status::Error);
+        let message = "expected buffer of size 4 for u32, but got 2".to_string();
+        assert_eq!(last_error(), Some((Status::Error, message)));
+
+        // Output buffer of the wrong size
+        let mut out_len = 2;
+        let status =
+            __haskell_export_halve(even.as_ptr(), even.len(), out.as_mut_ptr(), &mut out_len);
+        assert_eq!(status, Status::Error);
+        assert_eq!(haskell_ffi_last_error_kind(), Status::Error);
+        let message = unsafe {
+            std::slice::from_raw_parts(haskell_ffi_last_error_ptr(), haskell_ffi_last_error_len())
+        };
+        assert_eq!(message, b"expected buffer of size 4 for u32, but got 2");
+        haskell_ffi_last_error_clear();
+        assert_eq!(last_error(), None);
+        assert!(haskell_ffi_last_error_ptr().is_null());
+        Ok(())
+    }
+
+    #[test]
+    fn unchecked() -> Result<()> {
+        let n = 21u32.try_to_vec()?;
+        let mut out = [0u8; 4];
+        let mut out_len = out.len();
+        let () = __haskell_export_double(n.as_ptr(), n.len(), out.as_mut_ptr(), &mut out_len);
+        assert_eq!(out.to_vec(), 42u32.try_to_vec()?);
+        Ok(())
+    }
+}
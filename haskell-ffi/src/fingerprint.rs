@@ -0,0 +1,568 @@
+//! Fingerprints of the wire format, and the load-time schema check
+//!
+//! The fingerprint of a type is a hash of its wire format, as described by its
+//! `HaskellType` instance: the structure of the type, including the types it
+//! refers to, but not the names of fields and constructors (which do not
+//! affect the encoding). Since the generated Haskell code is derived from the
+//! same description, the fingerprints can be compared to detect that the
+//! Haskell code is out of date with respect to the Rust library.
+//!
+//! For this purpose every library using `haskell_ffi` exports the function
+//! `haskell_ffi_check_schema`. The generated Haskell module with the foreign
+//! imports contains the fingerprints of all functions as they were when the
+//! code was generated, along with a function `checkSchema`, which passes them
+//! to `haskell_ffi_check_schema` and fails with an error such as "schema
+//! mismatch for type Point (used by function translate)" if the Rust library
+//! disagrees. It should be called once, when the Haskell application starts.
+//!
+//! Fingerprints are not compile-time constants: the description of a type is
+//! only available by calling the methods of its `HaskellType` instance, which
+//! build a `Schema` at runtime. Instead, the fingerprint of each type and of
+//! each exported function is computed the first time it is needed, and cached
+//! for the lifetime of the process.
+
+use std::{
+    any::TypeId,
+    collections::HashMap,
+    marker::PhantomData,
+    sync::{Mutex, OnceLock},
+};
+
+use crate::{
+    export::{exports, HaskellExport},
+    haskell_export,
+    haskell_type::HaskellType,
+    schema::{Fields, FunctionDecl, Primitive, Schema, TypeBody, TypeDecl, TypeExpr},
+};
+
+/// Symbol of the exported function that checks the fingerprints
+pub const CHECK_SCHEMA_SYMBOL: &str = "haskell_ffi_check_schema";
+
+/// Fingerprint of the wire format of `T`
+///
+/// Computed once per `Tag` and `T`, see the module documentation.
+pub fn type_fingerprint<Tag: 'static, T: HaskellType<Tag> + ?Sized + 'static>() -> u64 {
+    static CACHE: OnceLock<Mutex<HashMap<(TypeId, TypeId), u64>>> = OnceLock::new();
+
+    let key = (TypeId::of::<Tag>(), TypeId::of::<T>());
+    let cache = CACHE.get_or_init(Default::default);
+    if let Some(fingerprint) = cache.lock().unwrap().get(&key) {
+        return *fingerprint;
+    }
+    // The lock is not held while computing the fingerprint, since that may
+    // (in user-defined instances) require other fingerprints
+    let mut schema = Schema::new();
+    schema.add::<Tag, T>();
+    let fingerprint = Fingerprinter::new(&schema).type_expr(&T::haskell_type(PhantomData));
+    cache.lock().unwrap().insert(key, fingerprint);
+    fingerprint
+}
+
+/*******************************************************************************
+  Signatures
+*******************************************************************************/
+
+/// Fingerprints of an exported function
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureFingerprint {
+    pub symbol: String,
+
+    /// Fingerprint of the types of the arguments and result, how they are
+    /// marshalled, and whether the function returns a status code
+    pub signature: u64,
+
+    /// Fingerprints of all declared types used by the function, along with
+    /// their Haskell name (types come after the types they depend on)
+    pub types: Vec<(String, u64)>,
+}
+
+impl SignatureFingerprint {
+    /// Fingerprints of the function (whose types are declared in `schema`)
+    pub fn new(schema: &Schema, decl: &FunctionDecl) -> Self {
+        let fingerprinter = Fingerprinter::new(schema);
+
+        let mut hasher = Fnv::new();
+        hasher.write_usize(decl.args.len());
+        let mut used = Vec::new();
+        for arg in &decl.args {
+            hasher.write_u64(fingerprinter.type_expr(&arg.ty));
+            hasher.write_u8(arg.marshalling as u8);
+            used.push(&arg.ty);
+        }
+        match &decl.result {
+            None => hasher.write_u8(0),
+            Some(result) => {
+                hasher.write_u8(1);
+                hasher.write_u64(fingerprinter.type_expr(&result.ty));
+                hasher.write_u8(result.marshalling as u8);
+                used.push(&result.ty);
+            }
+        }
+        hasher.write_u8(decl.checked as u8);
+
+        let mut names = Vec::new();
+        for ty in used {
+            collect_names(schema, ty, &mut names);
+        }
+        let types = schema
+            .decls()
+            .iter()
+            .filter(|decl| names.contains(&decl.name))
+            .map(|decl| (decl.name.clone(), fingerprinter.decl(decl)))
+            .collect();
+
+        SignatureFingerprint {
+            symbol: decl.symbol.clone(),
+            signature: hasher.finish(),
+            types,
+        }
+    }
+
+    /// Fingerprints of a function exported using `#[haskell_export]`
+    pub fn of_export(export: &HaskellExport) -> Self {
+        let mut schema = Schema::new();
+        let decl = (export.describe)(&mut schema);
+        Self::new(&schema, &decl)
+    }
+
+    /// Compare with the fingerprints of the Rust side
+    ///
+    /// Returns a description of the first difference, if any.
+    pub fn compare(&self, actual: &SignatureFingerprint) -> Option<String> {
+        for (name, fingerprint) in &actual.types {
+            let expected = self.types.iter().find(|(other, _)| other == name);
+            if expected.map(|(_, expected)| expected) != Some(fingerprint) {
+                return Some(format!(
+                    "schema mismatch for type {} (used by function {})",
+                    name, self.symbol
+                ));
+            }
+        }
+        if self.signature != actual.signature || self.types.len() != actual.types.len() {
+            return Some(format!("schema mismatch for function {}", self.symbol));
+        }
+        None
+    }
+}
+
+/// Check the fingerprints expected by the Haskell side
+///
+/// This is the implementation of `haskell_ffi_check_schema`.
+pub fn check_signatures(expected: &[SignatureFingerprint]) -> Result<(), String> {
+    let exported = exported_fingerprints();
+    for signature in expected {
+        let actual = exported.get(signature.symbol.as_str()).ok_or_else(|| {
+            format!(
+                "schema mismatch for function {} (not exported by the Rust library)",
+                signature.symbol
+            )
+        })?;
+        if let Some(err) = signature.compare(actual) {
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+/// Fingerprints of all functions exported using `#[haskell_export]`, by symbol
+///
+/// Computed once, see the module documentation.
+fn exported_fingerprints() -> &'static HashMap<&'static str, SignatureFingerprint> {
+    static FINGERPRINTS: OnceLock<HashMap<&'static str, SignatureFingerprint>> = OnceLock::new();
+    FINGERPRINTS.get_or_init(|| {
+        exports()
+            .into_iter()
+            .map(|export| (export.symbol, SignatureFingerprint::of_export(export)))
+            .collect()
+    })
+}
+
+/// `SignatureFingerprint` as passed by the Haskell side: the symbol, the
+/// fingerprint of the signature, and the fingerprints of the types
+type Expected = (String, u64, Vec<(String, u64)>);
+
+/// Check the fingerprints of the generated Haskell code
+///
+/// Returns an error message if any of them disagree with the Rust side.
+#[haskell_export(name = "haskell_ffi_check_schema")]
+fn check_schema(expected: Vec<Expected>) -> Option<String> {
+    let expected: Vec<SignatureFingerprint> = expected
+        .into_iter()
+        .map(|(symbol, signature, types)| SignatureFingerprint {
+            symbol,
+            signature,
+            types,
+        })
+        .collect();
+    check_signatures(&expected).err()
+}
+
+/*******************************************************************************
+  Hashing
+*******************************************************************************/
+
+/// Computes fingerprints of types declared in a schema
+struct Fingerprinter<'a> {
+    decls: HashMap<&'a str, &'a TypeDecl>,
+}
+
+impl<'a> Fingerprinter<'a> {
+    fn new(schema: &'a Schema) -> Self {
+        Fingerprinter {
+            decls: schema
+                .decls()
+                .iter()
+                .map(|decl| (decl.name.as_str(), decl))
+                .collect(),
+        }
+    }
+
+    fn type_expr(&self, ty: &TypeExpr) -> u64 {
+        let mut hasher = Fnv::new();
+        self.hash_type(ty, &mut Vec::new(), &mut hasher);
+        hasher.finish()
+    }
+
+    fn decl(&self, decl: &'a TypeDecl) -> u64 {
+        let mut hasher = Fnv::new();
+        self.hash_decl(decl, &mut vec![decl.name.as_str()], &mut hasher);
+        hasher.finish()
+    }
+
+    /// `stack` contains the declarations we are in the process of hashing,
+    /// so that references to them (in recursive types) are hashed by position
+    fn hash_decl(&self, decl: &'a TypeDecl, stack: &mut Vec<&'a str>, hasher: &mut Fnv) {
+        hasher.write_usize(decl.params.len());
+        match &decl.body {
+            TypeBody::Struct(fields) => {
+                hasher.write_u8(0);
+                self.hash_fields(fields, stack, hasher);
+            }
+            TypeBody::Enum(variants) => {
+                hasher.write_u8(1);
+                hasher.write_usize(variants.len());
+                for variant in variants {
+                    self.hash_fields(&variant.fields, stack, hasher);
+                }
+            }
+            TypeBody::Transparent(ty) => {
+                hasher.write_u8(2);
+                self.hash_type(ty, stack, hasher);
+            }
+        }
+    }
+
+    fn hash_fields(&self, fields: &'a Fields, stack: &mut Vec<&'a str>, hasher: &mut Fnv) {
+        let types = fields.types();
+        hasher.write_usize(types.len());
+        for ty in types {
+            self.hash_type(ty, stack, hasher);
+        }
+    }
+
+    fn hash_type(&self, ty: &TypeExpr, stack: &mut Vec<&'a str>, hasher: &mut Fnv) {
+        match ty {
+            TypeExpr::Primitive(prim) => {
+                hasher.write_u8(0);
+                hasher.write_u8(primitive_index(*prim));
+            }
+            TypeExpr::Vec(t) => {
+                hasher.write_u8(1);
+                self.hash_type(t, stack, hasher);
+            }
+            TypeExpr::Option(t) => {
+                hasher.write_u8(2);
+                self.hash_type(t, stack, hasher);
+            }
+            TypeExpr::Result(ok, err) => {
+                hasher.write_u8(3);
+                self.hash_type(ok, stack, hasher);
+                self.hash_type(err, stack, hasher);
+            }
+            TypeExpr::Tuple(ts) => {
+                hasher.write_u8(4);
+                hasher.write_usize(ts.len());
+                for t in ts {
+                    self.hash_type(t, stack, hasher);
+                }
+            }
+            TypeExpr::Array(t, n) => {
+                hasher.write_u8(5);
+                hasher.write_usize(*n);
+                self.hash_type(t, stack, hasher);
+            }
+            TypeExpr::HashMap(k, v) => {
+                hasher.write_u8(6);
+                self.hash_type(k, stack, hasher);
+                self.hash_type(v, stack, hasher);
+            }
+            TypeExpr::HashSet(t) => {
+                hasher.write_u8(7);
+                self.hash_type(t, stack, hasher);
+            }
+            TypeExpr::Named { name, args } => {
+                if let Some(ix) = stack.iter().position(|other| other == name) {
+                    hasher.write_u8(8);
+                    hasher.write_usize(ix);
+                } else if let Some(decl) = self.decls.get(name.as_str()) {
+                    hasher.write_u8(9);
+                    stack.push(&decl.name);
+                    self.hash_decl(decl, stack, hasher);
+                    stack.pop();
+                } else {
+                    hasher.write_u8(10);
+                    hasher.write_str(name);
+                }
+                hasher.write_usize(args.len());
+                for arg in args {
+                    self.hash_type(arg, stack, hasher);
+                }
+            }
+            TypeExpr::Var(n) => {
+                hasher.write_u8(11);
+                hasher.write_usize(*n);
+            }
+            // Only the name of the Rust type is known; the path depends on how
+            // the schema was obtained (see `build::parse_crate`)
+            TypeExpr::Borsh(rust_type) => {
+                hasher.write_u8(12);
+                hasher.write_str(unqualified(rust_type));
+            }
+            TypeExpr::Bincode(rust_type) => {
+                hasher.write_u8(13);
+                hasher.write_str(unqualified(rust_type));
+            }
+            TypeExpr::Opaque(rust_type) => {
+                hasher.write_u8(14);
+                hasher.write_str(unqualified(rust_type));
+            }
+        }
+    }
+}
+
+fn primitive_index(prim: Primitive) -> u8 {
+    match prim {
+        Primitive::U8 => 0,
+        Primitive::U16 => 1,
+        Primitive::U32 => 2,
+        Primitive::U64 => 3,
+        Primitive::U128 => 4,
+        Primitive::I8 => 5,
+        Primitive::I16 => 6,
+        Primitive::I32 => 7,
+        Primitive::I64 => 8,
+        Primitive::I128 => 9,
+        Primitive::F32 => 10,
+        Primitive::F64 => 11,
+        Primitive::Bool => 12,
+        Primitive::Unit => 13,
+        Primitive::String => 14,
+    }
+}
+
+/// Name of a Rust type without its path or arguments (`my_crate::Pair<u8>` becomes `Pair`)
+pub(crate) fn unqualified(rust_type: &str) -> &str {
+    let without_args = rust_type.split('<').next().unwrap_or(rust_type);
+    without_args.rsplit("::").next().unwrap_or(without_args)
+}
+
+/// Names of all declared types that occur in the type
+fn collect_names(schema: &Schema, ty: &TypeExpr, names: &mut Vec<String>) {
+    match ty {
+        TypeExpr::Primitive(_)
+        | TypeExpr::Var(_)
+        | TypeExpr::Borsh(_)
+        | TypeExpr::Bincode(_)
+        | TypeExpr::Opaque(_) => (),
+        TypeExpr::Vec(t) | TypeExpr::Option(t) | TypeExpr::HashSet(t) | TypeExpr::Array(t, _) => {
+            collect_names(schema, t, names)
+        }
+        TypeExpr::Result(a, b) | TypeExpr::HashMap(a, b) => {
+            collect_names(schema, a, names);
+            collect_names(schema, b, names);
+        }
+        TypeExpr::Tuple(ts) => {
+            for t in ts {
+                collect_names(schema, t, names);
+            }
+        }
+        TypeExpr::Named { name, args } => {
+            for arg in args {
+                collect_names(schema, arg, names);
+            }
+            if names.contains(name) {
+                return;
+            }
+            names.push(name.clone());
+            if let Some(decl) = schema.get(name) {
+                let field_types = match &decl.body {
+                    TypeBody::Struct(fields) => fields.types(),
+                    TypeBody::Enum(variants) => variants
+                        .iter()
+                        .flat_map(|variant| variant.fields.types())
+                        .collect(),
+                    TypeBody::Transparent(ty) => vec![ty],
+                };
+                for ty in field_types {
+                    collect_names(schema, ty, names);
+                }
+            }
+        }
+    }
+}
+
+/// 64-bit FNV-1a
+///
+/// We do not use `std::hash`, whose output is not guaranteed to be stable
+/// across Rust versions: the fingerprints in the generated Haskell code may
+/// have been computed by a different build than the one they are checked by.
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write_u8(&mut self, byte: u8) {
+        self.0 ^= u64::from(byte);
+        self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        for byte in n.to_le_bytes() {
+            self.write_u8(byte);
+        }
+    }
+
+    fn write_usize(&mut self, n: usize) {
+        self.write_u64(n as u64);
+    }
+
+    fn write_str(&mut self, s: &str) {
+        self.write_usize(s.len());
+        for byte in s.bytes() {
+            self.write_u8(byte);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/*******************************************************************************
+  Sanity checks
+*******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FromHaskell, ToHaskell};
+
+    #[derive(HaskellType)]
+    #[allow(dead_code)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    #[derive(HaskellType)]
+    #[allow(dead_code)]
+    struct Renamed {
+        #[haskell(rename = "horizontal")]
+        a: i32,
+        b: i32,
+    }
+
+    #[derive(HaskellType)]
+    #[allow(dead_code)]
+    struct Swapped {
+        x: i32,
+        y: i64,
+    }
+
+    #[derive(HaskellType)]
+    #[allow(dead_code)]
+    enum Tree {
+        Leaf(Point),
+        Node(Vec<Tree>),
+    }
+
+    #[test]
+    fn wire_shape() {
+        let point = type_fingerprint::<(), Point>();
+        assert_eq!(point, type_fingerprint::<(), Renamed>());
+        assert_ne!(point, type_fingerprint::<(), Swapped>());
+        assert_ne!(point, type_fingerprint::<(), (i32, i32)>());
+        assert_ne!(
+            type_fingerprint::<(), Tree>(),
+            type_fingerprint::<(), Point>()
+        );
+    }
+
+    #[derive(HaskellType, ToHaskell, FromHaskell)]
+    struct Segment {
+        from: i32,
+        to: i32,
+    }
+
+    #[crate::haskell_export(name = "fingerprint_test_length")]
+    fn length(segment: Segment) -> u32 {
+        segment.from.abs_diff(segment.to)
+    }
+
+    fn expected() -> SignatureFingerprint {
+        let export = exports()
+            .into_iter()
+            .find(|export| export.symbol == "fingerprint_test_length")
+            .unwrap();
+        SignatureFingerprint::of_export(export)
+    }
+
+    #[test]
+    fn check() {
+        let expected = expected();
+        assert_eq!(expected.types[0].0, "Segment");
+        assert_eq!(check_signatures(std::slice::from_ref(&expected)), Ok(()));
+
+        let mut changed_type = expected.clone();
+        changed_type.types[0].1 = type_fingerprint::<(), Swapped>();
+        assert_eq!(
+            check_signatures(&[changed_type]),
+            Err(
+                "schema mismatch for type Segment (used by function fingerprint_test_length)"
+                    .to_string()
+            )
+        );
+
+        let mut changed_signature = expected.clone();
+        changed_signature.signature ^= 1;
+        assert_eq!(
+            check_signatures(&[changed_signature]),
+            Err("schema mismatch for function fingerprint_test_length".to_string())
+        );
+
+        let mut unknown = expected;
+        unknown.symbol = "fingerprint_test_unknown".to_string();
+        assert_eq!(
+            check_signatures(&[unknown]),
+            Err("schema mismatch for function fingerprint_test_unknown (not exported by the Rust library)".to_string())
+        );
+    }
+
+    #[test]
+    fn exported() {
+        let expected = expected();
+        assert_eq!(
+            check_schema(vec![(expected.symbol, expected.signature, expected.types)]),
+            None
+        );
+        assert_eq!(
+            check_schema(vec![("fingerprint_test_length".to_string(), 0, vec![])]),
+            Some(
+                "schema mismatch for type Segment (used by function fingerprint_test_length)"
+                    .to_string()
+            )
+        );
+    }
+}
@@ -1,13 +1,16 @@
-use std::{io::ErrorKind, marker::PhantomData};
+use std::marker::PhantomData;
 
-use crate::{error::Error, HaskellSize};
+use crate::{
+    error::{Error, Status},
+    HaskellSize,
+};
+
+pub use haskell_ffi_derive::FromHaskell;
 
 /*******************************************************************************
   Main class definition
 *******************************************************************************/
 
-const ERROR_NOT_ALL_BYTES_READ: &str = "Not all bytes read";
-
 pub trait FromHaskell<Tag>: Sized {
     /// Deserialize data sent from Haskell
     ///
@@ -20,10 +23,7 @@ pub trait FromHaskell<Tag>: Sized {
         let mut slice_mut = slice;
         let result = Self::from_haskell(&mut slice_mut, tag)?;
         if !slice_mut.is_empty() {
-            return Err(Box::new(std::io::Error::new(
-                ErrorKind::InvalidData,
-                ERROR_NOT_ALL_BYTES_READ,
-            )));
+            return Err(Error::TrailingBytes);
         }
         Ok(result)
     }
@@ -41,11 +41,7 @@ pub fn marshall_from_haskell_var<Tag, T>(inp: *const u8, len: usize, tag: Phanto
 where
     T: FromHaskell<Tag>,
 {
-    let mut vec: Vec<u8> = vec![0; len];
-    unsafe {
-        std::ptr::copy(inp, vec.as_mut_ptr(), len);
-    }
-    match T::from_haskell_slice(vec.as_ref(), tag) {
+    match try_marshall_from_haskell_var(inp, len, tag) {
         Ok(t) => t,
         Err(e) => panic!("{}", e),
     }
@@ -63,14 +59,387 @@ pub fn marshall_from_haskell_fixed<Tag, T>(
 where
     T: FromHaskell<Tag> + HaskellSize<Tag>,
 {
-    let expected_len = T::haskell_size(tag);
+    match try_marshall_from_haskell_fixed(inp, inp_len, tag) {
+        Ok(t) => t,
+        Err(e) => panic!("{}", e),
+    }
+}
+
+/*******************************************************************************
+  Fallible variants
+
+  See the corresponding section in `to_haskell`.
+*******************************************************************************/
 
+/// Variant of `marshall_from_haskell_var` that returns an error
+pub fn try_marshall_from_haskell_var<Tag, T>(
+    inp: *const u8,
+    len: usize,
+    tag: PhantomData<Tag>,
+) -> Result<T, Error>
+where
+    T: FromHaskell<Tag>,
+{
+    let mut vec: Vec<u8> = vec![0; len];
+    unsafe {
+        std::ptr::copy(inp, vec.as_mut_ptr(), len);
+    }
+    T::from_haskell_slice(vec.as_ref(), tag)
+}
+
+/// Variant of `marshall_from_haskell_fixed` that returns an error
+pub fn try_marshall_from_haskell_fixed<Tag, T>(
+    inp: *const u8,
+    inp_len: usize,
+    tag: PhantomData<Tag>,
+) -> Result<T, Error>
+where
+    T: FromHaskell<Tag> + HaskellSize<Tag>,
+{
+    let expected_len = T::haskell_size(tag);
     if inp_len != expected_len {
-        panic!(
-            "expected buffer of size {}, but got {}",
-            expected_len, inp_len
-        )
-    } else {
-        marshall_from_haskell_var(inp, inp_len, tag)
+        return Err(Error::size_mismatch::<T>(expected_len, inp_len));
+    }
+    try_marshall_from_haskell_var(inp, inp_len, tag)
+}
+
+/*******************************************************************************
+  Without panicking
+
+  Like the fallible variants, but also catching panics; see the corresponding
+  section in `to_haskell`.
+*******************************************************************************/
+
+/// Variant of `marshall_from_haskell_var` that does not panic
+pub fn catch_marshall_from_haskell_var<Tag, T>(
+    inp: *const u8,
+    len: usize,
+    tag: PhantomData<Tag>,
+) -> core::result::Result<T, Status>
+where
+    T: FromHaskell<Tag>,
+{
+    Status::catch(|| try_marshall_from_haskell_var(inp, len, tag))
+}
+
+/// Variant of `marshall_from_haskell_fixed` that does not panic
+pub fn catch_marshall_from_haskell_fixed<Tag, T>(
+    inp: *const u8,
+    inp_len: usize,
+    tag: PhantomData<Tag>,
+) -> core::result::Result<T, Status>
+where
+    T: FromHaskell<Tag> + HaskellSize<Tag>,
+{
+    Status::catch(|| try_marshall_from_haskell_fixed(inp, inp_len, tag))
+}
+
+/*******************************************************************************
+  Sanity checks
+*******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use borsh::BorshSerialize;
+
+    use super::*;
+    use crate::{HaskellMaxSize, HaskellSize, ToHaskell};
+
+    enum ExampleTag {}
+
+    #[derive(Debug, PartialEq, ToHaskell, FromHaskell)]
+    struct EmptyStruct;
+
+    #[derive(Debug, PartialEq, ToHaskell, FromHaskell)]
+    struct UnnamedStruct(u16, (u8, u32));
+
+    #[derive(Debug, PartialEq, ToHaskell, FromHaskell)]
+    struct NamedStruct<T> {
+        a: u8,
+        b: String,
+        c: Vec<T>,
+    }
+
+    #[derive(Debug, PartialEq, ToHaskell, FromHaskell)]
+    struct Config {
+        retries: u8,
+        timeout: u64,
+    }
+
+    #[derive(Debug, PartialEq, ToHaskell, FromHaskell)]
+    enum Shape<T> {
+        Empty,
+        Circle(T),
+        Rect { width: T, height: T },
+    }
+
+    #[derive(Debug, PartialEq, ToHaskell, FromHaskell, HaskellSize, HaskellMaxSize)]
+    #[haskell(transparent)]
+    struct Lamports(u64);
+
+    #[derive(Debug, PartialEq, ToHaskell, FromHaskell)]
+    #[haskell(transparent)]
+    struct Labelled<T> {
+        value: T,
+        #[haskell(skip)]
+        label: String,
+    }
+
+    /// Type with Borsh instances but no `ToHaskell`/`FromHaskell` instances
+    #[derive(Debug, PartialEq, BorshSerialize, borsh::BorshDeserialize)]
+    struct Foreign {
+        x: u32,
+    }
+
+    /// Encode `u64` as `u32`
+    mod narrow {
+        use super::*;
+        use crate::{HaskellSize, ToHaskell};
+
+        pub fn to_haskell<Tag, W: std::io::Write>(
+            t: &u64,
+            writer: &mut W,
+            tag: PhantomData<Tag>,
+        ) -> Result<(), Error> {
+            u32::try_from(*t)?.to_haskell(writer, tag)
+        }
+
+        pub fn from_haskell<Tag>(buf: &mut &[u8], tag: PhantomData<Tag>) -> Result<u64, Error> {
+            Ok(u32::from_haskell(buf, tag)?.into())
+        }
+
+        pub fn haskell_size<Tag>(tag: PhantomData<Tag>) -> usize {
+            u32::haskell_size(tag)
+        }
+    }
+
+    #[derive(Debug, PartialEq, ToHaskell, FromHaskell)]
+    struct Overrides {
+        #[haskell(via = "borsh")]
+        a: Foreign,
+        #[haskell(via = "bincode")]
+        b: (u8, String),
+        #[haskell(with = "narrow")]
+        c: u64,
+    }
+
+    #[derive(HaskellSize)]
+    struct OverridesSize {
+        #[haskell(via = "borsh")]
+        _a: u16,
+        #[haskell(with = "narrow")]
+        _b: u64,
+    }
+
+    #[derive(Debug, PartialEq, ToHaskell, FromHaskell, HaskellSize)]
+    struct Skipped<T> {
+        a: u32,
+        #[haskell(skip)]
+        cache: Vec<String>,
+        #[haskell(default = "default_retries")]
+        retries: u8,
+        #[haskell(skip)]
+        marker: PhantomData<T>,
+    }
+
+    fn default_retries() -> u8 {
+        3
+    }
+
+    #[derive(Debug, PartialEq, ToHaskell, FromHaskell, HaskellSize)]
+    enum SkippedEnum {
+        A(u16, #[haskell(skip)] String),
+        B(#[haskell(default)] Option<u64>, i16),
+    }
+
+    #[test]
+    fn roundtrip() -> Result<(), Error> {
+        let tag: PhantomData<ExampleTag> = PhantomData;
+
+        let encoded = EmptyStruct.to_haskell_vec(tag)?;
+        assert_eq!(EmptyStruct::from_haskell_slice(&encoded, tag)?, EmptyStruct);
+
+        let x = UnnamedStruct(1, (2, 3));
+        let encoded = x.to_haskell_vec(tag)?;
+        assert_eq!(UnnamedStruct::from_haskell_slice(&encoded, tag)?, x);
+
+        let x = NamedStruct {
+            a: 1,
+            b: "hello".to_string(),
+            c: vec![(2u32, true), (3, false)],
+        };
+        let encoded = x.to_haskell_vec(tag)?;
+        assert_eq!(NamedStruct::from_haskell_slice(&encoded, tag)?, x);
+
+        for x in [
+            Shape::Empty,
+            Shape::Circle(1u32),
+            Shape::Rect {
+                width: 2,
+                height: 3,
+            },
+        ] {
+            let encoded = x.to_haskell_vec(tag)?;
+            assert_eq!(Shape::from_haskell_slice(&encoded, tag)?, x);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn field_context() -> Result<(), Error> {
+        let tag: PhantomData<ExampleTag> = PhantomData;
+        let truncated = (1u8, 2u32).try_to_vec()?;
+        let err = Config::from_haskell_slice(&truncated, tag).unwrap_err();
+        assert!(err.to_string().starts_with("Config.timeout: "), "{}", err);
+        Ok(())
+    }
+
+    #[test]
+    fn transparent() -> Result<(), Error> {
+        let tag: PhantomData<ExampleTag> = PhantomData;
+        let x = Lamports(1_000_000);
+        let encoded = x.to_haskell_vec(tag)?;
+        assert_eq!(encoded, 1_000_000u64.try_to_vec()?);
+        assert_eq!(Lamports::from_haskell_slice(&encoded, tag)?, x);
+        assert_eq!(Lamports::haskell_size(tag), 8);
+        assert_eq!(Lamports::haskell_max_size(tag), 8);
+
+        // Errors are those of the inner type, without additional context
+        let err = Lamports::from_haskell_slice(&[1, 2], tag).unwrap_err();
+        let inner = u64::from_haskell_slice(&[1, 2], tag).unwrap_err();
+        assert_eq!(err.to_string(), inner.to_string());
+
+        let x = Labelled {
+            value: vec![1u8, 2],
+            label: "ignored".to_string(),
+        };
+        let encoded = x.to_haskell_vec(tag)?;
+        assert_eq!(encoded, vec![1u8, 2].try_to_vec()?);
+        let decoded = <Labelled<Vec<u8>>>::from_haskell_slice(&encoded, tag)?;
+        assert_eq!(decoded.value, x.value);
+        assert_eq!(decoded.label, "");
+        Ok(())
+    }
+
+    #[test]
+    fn fallible() -> Result<(), Error> {
+        let tag: PhantomData<ExampleTag> = PhantomData;
+        let encoded = 5u64.try_to_vec()?;
+        let x: Lamports = try_marshall_from_haskell_fixed(encoded.as_ptr(), encoded.len(), tag)?;
+        assert_eq!(x, Lamports(5));
+        let err =
+            try_marshall_from_haskell_fixed::<_, Lamports>(encoded.as_ptr(), 4, tag).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expected buffer of size 8 for haskell_ffi::from_haskell::tests::Lamports, but got 4"
+        );
+
+        let truncated = (1u8, 2u32).try_to_vec()?;
+        let err =
+            try_marshall_from_haskell_var::<_, Config>(truncated.as_ptr(), truncated.len(), tag)
+                .unwrap_err();
+        assert!(err.to_string().starts_with("Config.timeout: "), "{}", err);
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_variant() -> Result<(), Error> {
+        let tag: PhantomData<ExampleTag> = PhantomData;
+        let err = <Shape<u32>>::from_haskell_slice(&[3], tag).unwrap_err();
+        assert_eq!(err.to_string(), "Unexpected variant index 3 for Shape");
+        let err = <Shape<u32>>::from_haskell_slice(&[2, 0, 0, 0, 0], tag).unwrap_err();
+        assert!(
+            err.to_string().starts_with("Shape::Rect.height: "),
+            "{}",
+            err
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn causes() -> Result<(), Error> {
+        let tag: PhantomData<ExampleTag> = PhantomData;
+
+        let truncated = (1u8, 2u32).try_to_vec()?;
+        let err = Config::from_haskell_slice(&truncated, tag).unwrap_err();
+        assert!(matches!(err.cause(), Error::UnexpectedEof), "{:?}", err);
+        let source = std::error::Error::source(&err).expect("field context");
+        assert!(source.is::<Error>());
+
+        let err = bool::from_haskell_slice(&[2], tag).unwrap_err();
+        assert!(matches!(err, Error::InvalidBool(2)), "{:?}", err);
+        let err = u8::from_haskell_slice(&[1, 2], tag).unwrap_err();
+        assert!(matches!(err, Error::TrailingBytes), "{:?}", err);
+        let err = <Shape<u32>>::from_haskell_slice(&[3], tag).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                Error::InvalidTag {
+                    type_name: "Shape",
+                    tag: 3
+                }
+            ),
+            "{:?}",
+            err
+        );
+
+        // Errors survive being passed through `borsh` (here, for the elements)
+        let err = <Vec<bool>>::from_haskell_slice(&[1, 0, 0, 0, 2], tag).unwrap_err();
+        assert!(matches!(err, Error::InvalidBool(2)), "{:?}", err);
+        Ok(())
+    }
+
+    #[test]
+    fn overrides() -> Result<(), Error> {
+        let tag: PhantomData<ExampleTag> = PhantomData;
+        let x = Overrides {
+            a: Foreign { x: 1 },
+            b: (2, "three".to_string()),
+            c: 4,
+        };
+        let encoded = x.to_haskell_vec(tag)?;
+        let bincoded = bincode::serialize(&x.b)?;
+        assert_eq!(encoded, (1u32, bincoded, 4u32).try_to_vec()?);
+        assert_eq!(Overrides::from_haskell_slice(&encoded, tag)?, x);
+        assert_eq!(OverridesSize::haskell_size(tag), 6);
+        Ok(())
+    }
+
+    #[test]
+    fn skipped() -> Result<(), Error> {
+        let tag: PhantomData<ExampleTag> = PhantomData;
+
+        let x: Skipped<u8> = Skipped {
+            a: 1,
+            cache: vec!["cached".to_string()],
+            retries: 5,
+            marker: PhantomData,
+        };
+        let encoded = x.to_haskell_vec(tag)?;
+        assert_eq!(encoded, 1u32.try_to_vec()?);
+        assert_eq!(encoded.len(), <Skipped<u8>>::haskell_size(tag));
+        let decoded = <Skipped<u8>>::from_haskell_slice(&encoded, tag)?;
+        assert_eq!(decoded.a, 1);
+        assert_eq!(decoded.cache, Vec::<String>::new());
+        assert_eq!(decoded.retries, 3);
+
+        assert_eq!(SkippedEnum::haskell_size(tag), 3);
+        for x in [
+            SkippedEnum::A(1, "skipped".to_string()),
+            SkippedEnum::B(Some(2), 3),
+        ] {
+            let encoded = x.to_haskell_vec(tag)?;
+            assert_eq!(encoded.len(), SkippedEnum::haskell_size(tag));
+            let decoded = SkippedEnum::from_haskell_slice(&encoded, tag)?;
+            match (x, decoded) {
+                (SkippedEnum::A(a, _), SkippedEnum::A(b, s)) => {
+                    assert_eq!((a, s), (b, String::new()))
+                }
+                (SkippedEnum::B(_, a), SkippedEnum::B(None, b)) => assert_eq!(a, b),
+                (_, decoded) => panic!("unexpected {:?}", decoded),
+            }
+        }
+        Ok(())
     }
 }
@@ -2,10 +2,10 @@ use std::{cmp::max, marker::PhantomData};
 
 use crate::{derive_max_size_tuple_instance, fold_types, haskell_size::HaskellSize};
 
+pub use haskell_ffi_derive::HaskellMaxSize;
+
 /*******************************************************************************
   Main class definition
-
-  TODO: We do not currently support deriving 'HaskellMaxSize'.
 *******************************************************************************/
 
 pub trait HaskellMaxSize<Tag> {
@@ -156,3 +156,73 @@ derive_max_size_tuple_instance!(
 derive_max_size_tuple_instance!(
     T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15, T16, T17, T18, T19
 );
+
+/*******************************************************************************
+  Sanity checks
+*******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use std::io::Error;
+
+    use borsh::BorshSerialize;
+
+    use super::*;
+
+    enum ExampleTag {}
+
+    #[derive(HaskellMaxSize, BorshSerialize)]
+    struct EmptyStruct;
+
+    #[derive(HaskellMaxSize, BorshSerialize)]
+    struct NamedStruct {
+        a: u8,
+        b: Option<u32>,
+        c: Result<u16, (u64, u64)>,
+    }
+
+    #[derive(HaskellMaxSize, BorshSerialize)]
+    enum ParamEnum<T> {
+        A,
+        B(u8, T),
+        C { x: [T; 2] },
+    }
+
+    #[derive(HaskellMaxSize)]
+    enum Void {}
+
+    #[test]
+    fn empty() -> Result<(), Error> {
+        let tag: PhantomData<ExampleTag> = PhantomData;
+        assert_eq!(EmptyStruct::haskell_max_size(tag), 0);
+        assert_eq!(Void::haskell_max_size(tag), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn named() -> Result<(), Error> {
+        let tag: PhantomData<ExampleTag> = PhantomData;
+        assert_eq!(NamedStruct::haskell_max_size(tag), 23);
+        let encoded = NamedStruct {
+            a: 1,
+            b: Some(2),
+            c: Err((3, 4)),
+        }
+        .try_to_vec()?;
+        assert_eq!(encoded.len(), NamedStruct::haskell_max_size(tag));
+        Ok(())
+    }
+
+    #[test]
+    fn param() -> Result<(), Error> {
+        let tag: PhantomData<ExampleTag> = PhantomData;
+        assert_eq!(<ParamEnum<u32>>::haskell_max_size(tag), 9);
+        let encoded = ParamEnum::C { x: [1u32, 2] }.try_to_vec()?;
+        assert_eq!(encoded.len(), <ParamEnum<u32>>::haskell_max_size(tag));
+        let encoded = ParamEnum::B(1, 2u32).try_to_vec()?;
+        assert!(encoded.len() < <ParamEnum<u32>>::haskell_max_size(tag));
+        let encoded = ParamEnum::<u32>::A.try_to_vec()?;
+        assert!(encoded.len() < <ParamEnum<u32>>::haskell_max_size(tag));
+        Ok(())
+    }
+}
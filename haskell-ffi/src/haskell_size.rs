@@ -8,6 +8,20 @@ pub use haskell_ffi_derive::HaskellSize;
   Main class definition
 *******************************************************************************/
 
+/// Types with an encoding of a statically known size
+///
+/// The derived instance for an enum requires that all variants visibly have
+/// payloads of the same size; otherwise, derive `HaskellMaxSize` instead:
+///
+/// ```compile_fail
+/// use haskell_ffi::HaskellSize;
+///
+/// #[derive(HaskellSize)]
+/// enum ParamEnum<T> {
+///     A(T),
+///     B(u64),
+/// }
+/// ```
 pub trait HaskellSize<Tag> {
     /// Statically known size (in bytes)
     fn haskell_size(tag: PhantomData<Tag>) -> usize;
@@ -143,6 +157,50 @@ derive_size_tuple_instance!(
     T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15, T16, T17, T18, T19
 );
 
+/*******************************************************************************
+  Probing for instances
+
+  The derived `HaskellType` instances record the size of the types they
+  describe in the schema (see `Schema::record_size`), if they have a
+  `HaskellSize` instance. Whether they do is resolved at compile time using
+  autoref specialization: `(&&SizeProbe::<Tag, T>::new()).size()` resolves to
+  `SizeKnown` if `T: HaskellSize<Tag>`, and to `SizeUnknown` otherwise.
+*******************************************************************************/
+
+#[doc(hidden)]
+pub struct SizeProbe<Tag, T: ?Sized>(PhantomData<Tag>, PhantomData<T>);
+
+impl<Tag, T: ?Sized> SizeProbe<Tag, T> {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        SizeProbe(PhantomData, PhantomData)
+    }
+}
+
+/// Preferred: types with a `HaskellSize` instance
+#[doc(hidden)]
+pub trait SizeKnown {
+    fn size(&self) -> Option<usize>;
+}
+
+/// Fallback: types without a statically known size
+#[doc(hidden)]
+pub trait SizeUnknown {
+    fn size(&self) -> Option<usize>;
+}
+
+impl<Tag, T: HaskellSize<Tag> + ?Sized> SizeKnown for &SizeProbe<Tag, T> {
+    fn size(&self) -> Option<usize> {
+        Some(T::haskell_size(PhantomData))
+    }
+}
+
+impl<Tag, T: ?Sized> SizeUnknown for SizeProbe<Tag, T> {
+    fn size(&self) -> Option<usize> {
+        None
+    }
+}
+
 /*******************************************************************************
   Sanity checks
 *******************************************************************************/
@@ -176,6 +234,32 @@ mod tests {
         b: (T, T, T),
     }
 
+    #[derive(HaskellSize, BorshSerialize)]
+    enum Fieldless {
+        A,
+        B,
+        C,
+    }
+
+    #[derive(HaskellSize, BorshSerialize)]
+    enum SameSize {
+        A(u32, u16),
+        B(i32, [u8; 2]),
+        C { x: (i16, u8, u8, u16) },
+    }
+
+    #[derive(HaskellSize, BorshSerialize)]
+    struct ConstParam<T, const N: usize> {
+        a: u8,
+        b: [T; N],
+    }
+
+    #[derive(HaskellSize, BorshSerialize)]
+    enum ParamEnum<T> {
+        A(T),
+        B { x: T },
+    }
+
     #[test]
     fn empty() -> Result<(), Error> {
         let tag: PhantomData<ExampleTag> = PhantomData;
@@ -220,4 +304,51 @@ mod tests {
         assert_eq!(encoded.len(), <ParamStruct<f64>>::haskell_size(tag));
         Ok(())
     }
+
+    #[test]
+    fn fieldless() -> Result<(), Error> {
+        let tag: PhantomData<ExampleTag> = PhantomData;
+        assert_eq!(Fieldless::haskell_size(tag), 1);
+        for x in [Fieldless::A, Fieldless::B, Fieldless::C] {
+            assert_eq!(x.try_to_vec()?.len(), Fieldless::haskell_size(tag));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn same_size() -> Result<(), Error> {
+        let tag: PhantomData<ExampleTag> = PhantomData;
+        assert_eq!(SameSize::haskell_size(tag), 7);
+        for x in [
+            SameSize::A(1, 2),
+            SameSize::B(3, [4, 5]),
+            SameSize::C { x: (6, 7, 8, 9) },
+        ] {
+            assert_eq!(x.try_to_vec()?.len(), SameSize::haskell_size(tag));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn param_enum() -> Result<(), Error> {
+        let tag: PhantomData<ExampleTag> = PhantomData;
+        assert_eq!(<ParamEnum<f64>>::haskell_size(tag), 9);
+        for x in [ParamEnum::A(1.0), ParamEnum::B { x: 2.0 }] {
+            assert_eq!(x.try_to_vec()?.len(), <ParamEnum<f64>>::haskell_size(tag));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn const_param() -> Result<(), Error> {
+        let tag: PhantomData<ExampleTag> = PhantomData;
+        assert_eq!(<ConstParam<u16, 3>>::haskell_size(tag), 7);
+        let encoded = ConstParam {
+            a: 1,
+            b: [2u16, 3, 4],
+        }
+        .try_to_vec()?;
+        assert_eq!(encoded.len(), <ConstParam<u16, 3>>::haskell_size(tag));
+        Ok(())
+    }
 }
@@ -0,0 +1,211 @@
+use std::{
+    collections::{HashMap, HashSet},
+    marker::PhantomData,
+};
+
+use crate::{
+    derive_haskell_type_tuple_instance,
+    schema::{Primitive, Schema, TypeDecl, TypeExpr},
+    use_borsh::UseBorsh,
+};
+
+pub use haskell_ffi_derive::HaskellType;
+
+/*******************************************************************************
+  Main class definition
+*******************************************************************************/
+
+/// Description of the wire format of a type
+///
+/// This is used to generate Haskell declarations that match the Rust types
+/// (see `codegen::haskell`). Instances should agree with the corresponding
+/// `ToHaskell` and `FromHaskell` instances.
+pub trait HaskellType<Tag> {
+    /// Type expression describing this type
+    fn haskell_type(tag: PhantomData<Tag>) -> TypeExpr;
+
+    /// Add the declarations needed to use this type to the schema
+    ///
+    /// This includes the declaration of the type itself (if any), and the
+    /// declarations of all types it refers to.
+    fn haskell_decls(_tag: PhantomData<Tag>, _schema: &mut Schema) {}
+
+    /// Declaration of the type itself
+    ///
+    /// For generic types, this is called on the instantiation of the type to
+    /// `TypeVar`s. Used by the derived instances.
+    #[doc(hidden)]
+    fn haskell_decl(_tag: PhantomData<Tag>, _schema: &mut Schema) -> Option<TypeDecl> {
+        None
+    }
+}
+
+/// Placeholder for the n-th type parameter of a generic type
+///
+/// The derived `HaskellType` instances instantiate all type parameters to
+/// `TypeVar`s to compute the declaration of a generic type.
+pub enum TypeVar<const N: usize> {}
+
+impl<Tag, const N: usize> HaskellType<Tag> for TypeVar<N> {
+    fn haskell_type(_tag: PhantomData<Tag>) -> TypeExpr {
+        TypeExpr::Var(N)
+    }
+}
+
+/*******************************************************************************
+  Simple instances
+*******************************************************************************/
+
+macro_rules! primitive_instance {
+    ($t:ty, $prim:ident) => {
+        impl<Tag> HaskellType<Tag> for $t {
+            fn haskell_type(_tag: PhantomData<Tag>) -> TypeExpr {
+                TypeExpr::Primitive(Primitive::$prim)
+            }
+        }
+    };
+}
+
+primitive_instance!(u8, U8);
+primitive_instance!(u16, U16);
+primitive_instance!(u32, U32);
+primitive_instance!(u64, U64);
+primitive_instance!(u128, U128);
+primitive_instance!(i8, I8);
+primitive_instance!(i16, I16);
+primitive_instance!(i32, I32);
+primitive_instance!(i64, I64);
+primitive_instance!(i128, I128);
+primitive_instance!(f32, F32);
+primitive_instance!(f64, F64);
+primitive_instance!(bool, Bool);
+primitive_instance!((), Unit);
+primitive_instance!(String, String);
+
+/*******************************************************************************
+  Composite instances
+*******************************************************************************/
+
+impl<Tag, T: HaskellType<Tag>> HaskellType<Tag> for &T {
+    fn haskell_type(tag: PhantomData<Tag>) -> TypeExpr {
+        T::haskell_type(tag)
+    }
+
+    fn haskell_decls(tag: PhantomData<Tag>, schema: &mut Schema) {
+        T::haskell_decls(tag, schema)
+    }
+}
+
+impl<Tag, T: HaskellType<Tag>> HaskellType<Tag> for Vec<T> {
+    fn haskell_type(tag: PhantomData<Tag>) -> TypeExpr {
+        TypeExpr::Vec(Box::new(T::haskell_type(tag)))
+    }
+
+    fn haskell_decls(tag: PhantomData<Tag>, schema: &mut Schema) {
+        T::haskell_decls(tag, schema)
+    }
+}
+
+impl<Tag, T: HaskellType<Tag>> HaskellType<Tag> for Option<T> {
+    fn haskell_type(tag: PhantomData<Tag>) -> TypeExpr {
+        TypeExpr::Option(Box::new(T::haskell_type(tag)))
+    }
+
+    fn haskell_decls(tag: PhantomData<Tag>, schema: &mut Schema) {
+        T::haskell_decls(tag, schema)
+    }
+}
+
+impl<Tag, T: HaskellType<Tag>, E: HaskellType<Tag>> HaskellType<Tag>
+    for core::result::Result<T, E>
+{
+    fn haskell_type(tag: PhantomData<Tag>) -> TypeExpr {
+        TypeExpr::Result(
+            Box::new(T::haskell_type(tag)),
+            Box::new(E::haskell_type(tag)),
+        )
+    }
+
+    fn haskell_decls(tag: PhantomData<Tag>, schema: &mut Schema) {
+        T::haskell_decls(tag, schema);
+        E::haskell_decls(tag, schema);
+    }
+}
+
+impl<Tag, T: HaskellType<Tag>, const N: usize> HaskellType<Tag> for [T; N] {
+    fn haskell_type(tag: PhantomData<Tag>) -> TypeExpr {
+        TypeExpr::Array(Box::new(T::haskell_type(tag)), N)
+    }
+
+    fn haskell_decls(tag: PhantomData<Tag>, schema: &mut Schema) {
+        T::haskell_decls(tag, schema)
+    }
+}
+
+impl<Tag, K: HaskellType<Tag>, V: HaskellType<Tag>> HaskellType<Tag> for HashMap<K, V> {
+    fn haskell_type(tag: PhantomData<Tag>) -> TypeExpr {
+        TypeExpr::HashMap(
+            Box::new(K::haskell_type(tag)),
+            Box::new(V::haskell_type(tag)),
+        )
+    }
+
+    fn haskell_decls(tag: PhantomData<Tag>, schema: &mut Schema) {
+        K::haskell_decls(tag, schema);
+        V::haskell_decls(tag, schema);
+    }
+}
+
+impl<Tag, T: HaskellType<Tag>> HaskellType<Tag> for HashSet<T> {
+    fn haskell_type(tag: PhantomData<Tag>) -> TypeExpr {
+        TypeExpr::HashSet(Box::new(T::haskell_type(tag)))
+    }
+
+    fn haskell_decls(tag: PhantomData<Tag>, schema: &mut Schema) {
+        T::haskell_decls(tag, schema)
+    }
+}
+
+impl<Tag, T> HaskellType<Tag> for UseBorsh<T> {
+    fn haskell_type(_tag: PhantomData<Tag>) -> TypeExpr {
+        TypeExpr::Borsh(std::any::type_name::<T>().to_string())
+    }
+}
+
+/*******************************************************************************
+  Tuples
+
+  We support the same sizes of tuples as `borsh` does.
+*******************************************************************************/
+
+derive_haskell_type_tuple_instance!(T0, T1);
+derive_haskell_type_tuple_instance!(T0, T1, T2);
+derive_haskell_type_tuple_instance!(T0, T1, T2, T3);
+derive_haskell_type_tuple_instance!(T0, T1, T2, T3, T4);
+derive_haskell_type_tuple_instance!(T0, T1, T2, T3, T4, T5);
+derive_haskell_type_tuple_instance!(T0, T1, T2, T3, T4, T5, T6);
+derive_haskell_type_tuple_instance!(T0, T1, T2, T3, T4, T5, T6, T7);
+derive_haskell_type_tuple_instance!(T0, T1, T2, T3, T4, T5, T6, T7, T8);
+derive_haskell_type_tuple_instance!(T0, T1, T2, T3, T4, T5, T6, T7, T8, T9);
+derive_haskell_type_tuple_instance!(T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10);
+derive_haskell_type_tuple_instance!(T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11);
+derive_haskell_type_tuple_instance!(T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12);
+derive_haskell_type_tuple_instance!(T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13);
+derive_haskell_type_tuple_instance!(
+    T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14
+);
+derive_haskell_type_tuple_instance!(
+    T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15
+);
+derive_haskell_type_tuple_instance!(
+    T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15, T16
+);
+derive_haskell_type_tuple_instance!(
+    T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15, T16, T17
+);
+derive_haskell_type_tuple_instance!(
+    T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15, T16, T17, T18
+);
+derive_haskell_type_tuple_instance!(
+    T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15, T16, T17, T18, T19
+);
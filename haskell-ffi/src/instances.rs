@@ -7,14 +7,14 @@ use borsh::{BorshDeserialize, BorshSerialize};
 use std::{
     collections::{HashMap, HashSet},
     hash::Hash,
-    io::{ErrorKind, Write},
+    io::Write,
     marker::PhantomData,
 };
 
 use crate::{
     derive_array_instances, derive_simple_instances, derive_tuple_instances,
     deriving_via::{tag_ref, untag_val, Haskell},
-    error::Result,
+    error::{Error, Result},
     from_haskell::FromHaskell,
     map_tuple, map_tuple_ref,
     to_haskell::ToHaskell,
@@ -262,10 +262,7 @@ impl<Tag> FromHaskell<Tag> for bool {
         match as_u8 {
             0 => Ok(false),
             1 => Ok(true),
-            _ => Err(Box::new(std::io::Error::new(
-                ErrorKind::InvalidData,
-                "Invalid bool",
-            ))),
+            _ => Err(Error::InvalidBool(as_u8)),
         }
     }
 }
@@ -1,18 +1,39 @@
-#![feature(array_methods)]
-#![feature(trace_macros)]
+// The `marshall_*` functions take raw pointers from the Haskell side; they are
+// only meant to be called from `extern "C"` functions.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+// Allow the derived code (which refers to `::haskell_ffi`) to be used in this crate.
+extern crate self as haskell_ffi;
 
 mod instances;
 mod macros;
 
 pub mod bincode;
+#[cfg(feature = "build")]
+pub mod build;
+pub mod codegen;
+pub mod compat;
 pub mod deriving_via;
 pub mod error;
+pub mod export;
+pub mod fingerprint;
 pub mod from_haskell;
 pub mod haskell_max_size;
 pub mod haskell_size;
+pub mod haskell_type;
+pub mod naming;
+pub mod schema;
 pub mod to_haskell;
 pub mod use_borsh;
 
+// Used by the code generated by `haskell_export`
+#[doc(hidden)]
+pub use inventory;
+
+pub use error::{Error, Result};
+pub use export::haskell_export;
 pub use from_haskell::FromHaskell;
+pub use haskell_max_size::HaskellMaxSize;
 pub use haskell_size::HaskellSize;
+pub use haskell_type::HaskellType;
 pub use to_haskell::ToHaskell;
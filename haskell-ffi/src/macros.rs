@@ -200,3 +200,19 @@ macro_rules! derive_max_size_tuple_instance {
         }
     };
 }
+
+/// Derive `HaskellType` instance for tuple with the specified type arguments.
+#[macro_export]
+macro_rules! derive_haskell_type_tuple_instance {
+    ($($ts:ident),*) => {
+        impl<Tag, $($ts: HaskellType<Tag> ),* > HaskellType<Tag> for ( $($ts),* ) {
+            fn haskell_type(tag: PhantomData<Tag>) -> TypeExpr {
+                TypeExpr::Tuple(vec![ $( <$ts>::haskell_type(tag) ),* ])
+            }
+
+            fn haskell_decls(tag: PhantomData<Tag>, schema: &mut Schema) {
+                $( <$ts>::haskell_decls(tag, schema); )*
+            }
+        }
+    };
+}
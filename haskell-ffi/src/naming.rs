@@ -0,0 +1,138 @@
+//! Haskell names for Rust identifiers
+//!
+//! Renaming only affects the generated Haskell code; the wire format does not
+//! depend on any names. The derived `HaskellType` instances use these
+//! functions to compute the names of record fields (see the `rename_all`,
+//! `field_prefix` and `rename` attributes).
+//!
+//! Renamed fields and field prefixes must be valid Haskell field names,
+//! starting with a lowercase letter or `_`:
+//!
+//! ```compile_fail
+//! use haskell_ffi::HaskellType;
+//!
+//! #[derive(HaskellType)]
+//! struct Config {
+//!     #[haskell(rename = "Retries")]
+//!     max_retries: u32,
+//! }
+//! ```
+//!
+//! ```compile_fail
+//! use haskell_ffi::HaskellType;
+//!
+//! #[derive(HaskellType)]
+//! #[haskell(field_prefix = "my-")]
+//! struct Config {
+//!     max_retries: u32,
+//! }
+//! ```
+//!
+//! Similarly, renamed types and constructors must be valid Haskell type names:
+//!
+//! ```compile_fail
+//! use haskell_ffi::HaskellType;
+//!
+//! #[derive(HaskellType)]
+//! enum Shape {
+//!     #[haskell(rename = "Bad-Name")]
+//!     Circle,
+//! }
+//! ```
+
+/// Naming convention for record fields (`#[haskell(rename_all = "..")]`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RenameRule {
+    /// `max_retries` becomes `maxRetries` (the default)
+    CamelCase,
+
+    /// `max_retries` is left as is
+    SnakeCase,
+
+    /// `max_retries` becomes `maxretries`
+    LowerCase,
+}
+
+impl RenameRule {
+    /// All rules, along with their name in `rename_all`
+    pub const ALL: [(&'static str, RenameRule); 3] = [
+        ("camelCase", RenameRule::CamelCase),
+        ("snake_case", RenameRule::SnakeCase),
+        ("lowercase", RenameRule::LowerCase),
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|(rule_name, _)| *rule_name == name)
+            .map(|(_, rule)| *rule)
+    }
+
+    /// Apply the rule to a snake_case identifier
+    pub fn apply(self, snake_case: &str) -> String {
+        let snake_case = snake_case.trim_start_matches("r#");
+        match self {
+            RenameRule::SnakeCase => snake_case.to_string(),
+            RenameRule::LowerCase => snake_case.replace('_', "").to_lowercase(),
+            RenameRule::CamelCase => {
+                let mut name = String::new();
+                let mut upper = false;
+                for c in snake_case.chars() {
+                    if c == '_' && !name.is_empty() {
+                        upper = true;
+                    } else if upper {
+                        name.extend(c.to_uppercase());
+                        upper = false;
+                    } else {
+                        name.push(c);
+                    }
+                }
+                name
+            }
+        }
+    }
+}
+
+/// Haskell name for a record field
+///
+/// The prefix (if any) is treated as the first word of the field name, so that
+/// `max_retries` with prefix `cfg` becomes `cfgMaxRetries` using `camelCase`,
+/// and `cfg_max_retries` using `snake_case`.
+pub fn field_name(rust_name: &str, rename_all: Option<RenameRule>, prefix: Option<&str>) -> String {
+    let rust_name = rust_name.trim_start_matches("r#");
+    let rule = rename_all.unwrap_or(RenameRule::CamelCase);
+    match prefix {
+        Some(prefix) => rule.apply(&format!("{}_{}", prefix, rust_name)),
+        None => rule.apply(rust_name),
+    }
+}
+
+/*******************************************************************************
+  Sanity checks
+*******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules() {
+        let camel = Some(RenameRule::CamelCase);
+        let snake = Some(RenameRule::SnakeCase);
+        let lower = Some(RenameRule::LowerCase);
+        assert_eq!(field_name("max_retries", None, None), "maxRetries");
+        assert_eq!(
+            field_name("max_retries", camel, Some("cfg")),
+            "cfgMaxRetries"
+        );
+        assert_eq!(
+            field_name("max_retries", snake, Some("cfg")),
+            "cfg_max_retries"
+        );
+        assert_eq!(field_name("max_retries", lower, None), "maxretries");
+        assert_eq!(field_name("r#type", None, None), "type");
+        assert_eq!(field_name("_private", None, None), "_private");
+        assert_eq!(RenameRule::from_name("snake_case"), snake);
+        assert_eq!(RenameRule::from_name("kebab-case"), None);
+    }
+}
@@ -0,0 +1,484 @@
+//! Description of the wire format of Rust types
+//!
+//! This is the information we need to generate Haskell declarations that match
+//! the Rust types. See `HaskellType` for how the schema is constructed.
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    fs,
+    marker::PhantomData,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Result, export::exports, fingerprint, haskell_type::HaskellType};
+
+/*******************************************************************************
+  Type expressions
+*******************************************************************************/
+
+/// Primitive types
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Primitive {
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    F32,
+    F64,
+    Bool,
+    Unit,
+    String,
+}
+
+/// Type of a field (or argument to a type)
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TypeExpr {
+    Primitive(Primitive),
+    Vec(Box<TypeExpr>),
+    Option(Box<TypeExpr>),
+
+    /// `Result<T, E>`; the first argument is the type of the `Ok` case
+    Result(Box<TypeExpr>, Box<TypeExpr>),
+
+    Tuple(Vec<TypeExpr>),
+    Array(Box<TypeExpr>, usize),
+    HashMap(Box<TypeExpr>, Box<TypeExpr>),
+    HashSet(Box<TypeExpr>),
+
+    /// User-defined type, with a declaration in the schema
+    ///
+    /// The name is the name of the Haskell type.
+    Named {
+        name: String,
+        args: Vec<TypeExpr>,
+    },
+
+    /// The n-th type parameter of the enclosing declaration
+    Var(usize),
+
+    /// Rust type encoded using its Borsh instance (`UseBorsh`, `via = "borsh"`)
+    ///
+    /// The argument is the name of the Rust type (`std::any::type_name`).
+    Borsh(String),
+
+    /// Rust type encoded using length-prefixed bincode (`via = "bincode"`)
+    Bincode(String),
+
+    /// Rust type without a `HaskellType` instance
+    ///
+    /// This only arises for the arguments and results of exported functions.
+    /// We assume that the Haskell side declares a type of the same name.
+    Opaque(String),
+}
+
+/// Rust syntax for the type (type parameters are shown as `T0`, `T1`, ..)
+impl Display for TypeExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |f: &mut fmt::Formatter<'_>, ts: &[TypeExpr]| -> fmt::Result {
+            for (ix, t) in ts.iter().enumerate() {
+                if ix > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", t)?;
+            }
+            Ok(())
+        };
+        match self {
+            TypeExpr::Primitive(prim) => write!(f, "{}", prim),
+            TypeExpr::Vec(t) => write!(f, "Vec<{}>", t),
+            TypeExpr::Option(t) => write!(f, "Option<{}>", t),
+            TypeExpr::Result(ok, err) => write!(f, "Result<{}, {}>", ok, err),
+            TypeExpr::Tuple(ts) => {
+                write!(f, "(")?;
+                list(f, ts)?;
+                if ts.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")
+            }
+            TypeExpr::Array(t, n) => write!(f, "[{}; {}]", t, n),
+            TypeExpr::HashMap(k, v) => write!(f, "HashMap<{}, {}>", k, v),
+            TypeExpr::HashSet(t) => write!(f, "HashSet<{}>", t),
+            TypeExpr::Named { name, args } if args.is_empty() => write!(f, "{}", name),
+            TypeExpr::Named { name, args } => {
+                write!(f, "{}<", name)?;
+                list(f, args)?;
+                write!(f, ">")
+            }
+            TypeExpr::Var(n) => write!(f, "T{}", n),
+            TypeExpr::Borsh(rust_type) => write!(f, "UseBorsh<{}>", rust_type),
+            TypeExpr::Bincode(rust_type) => write!(f, "{} (bincode)", rust_type),
+            TypeExpr::Opaque(rust_type) => write!(f, "{}", rust_type),
+        }
+    }
+}
+
+impl Display for Primitive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Primitive::U8 => "u8",
+            Primitive::U16 => "u16",
+            Primitive::U32 => "u32",
+            Primitive::U64 => "u64",
+            Primitive::U128 => "u128",
+            Primitive::I8 => "i8",
+            Primitive::I16 => "i16",
+            Primitive::I32 => "i32",
+            Primitive::I64 => "i64",
+            Primitive::I128 => "i128",
+            Primitive::F32 => "f32",
+            Primitive::F64 => "f64",
+            Primitive::Bool => "bool",
+            Primitive::Unit => "()",
+            Primitive::String => "String",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/*******************************************************************************
+  Declarations
+*******************************************************************************/
+
+/// Declaration of a user-defined type
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TypeDecl {
+    /// Name of the Haskell type
+    pub name: String,
+
+    /// Full path to the Rust type (used to detect name clashes)
+    pub rust_path: String,
+
+    /// Names of the type parameters (referred to by `TypeExpr::Var`)
+    pub params: Vec<String>,
+
+    pub body: TypeBody,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TypeBody {
+    Struct(Fields),
+    Enum(Vec<Variant>),
+
+    /// Type with the same encoding as its only field (`#[haskell(transparent)]`)
+    Transparent(TypeExpr),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Variant {
+    /// Name of the Haskell constructor
+    pub name: String,
+    pub fields: Fields,
+}
+
+/// Fields of a struct or enum variant
+///
+/// Skipped fields are not included.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Fields {
+    Named(Vec<NamedField>),
+    Unnamed(Vec<TypeExpr>),
+    Unit,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NamedField {
+    /// Name of the Haskell record field (see `naming`)
+    pub name: String,
+    pub ty: TypeExpr,
+}
+
+impl Fields {
+    /// Types of all fields, in order
+    pub fn types(&self) -> Vec<&TypeExpr> {
+        match self {
+            Fields::Named(fields) => fields.iter().map(|f| &f.ty).collect(),
+            Fields::Unnamed(fields) => fields.iter().collect(),
+            Fields::Unit => Vec::new(),
+        }
+    }
+}
+
+/*******************************************************************************
+  Functions
+*******************************************************************************/
+
+/// Function exported with `#[haskell_export]`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionDecl {
+    /// Name of the exported symbol
+    pub symbol: String,
+
+    pub args: Vec<FunctionArg>,
+
+    /// Result of the function (`None` for functions returning `()`)
+    pub result: Option<FunctionResult>,
+
+    /// Does the wrapper catch panics, and return a `Status`?
+    ///
+    /// This is the default; it is `false` for `#[haskell_export(unchecked)]`.
+    #[serde(default)]
+    pub checked: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionArg {
+    pub name: String,
+    pub ty: TypeExpr,
+    pub marshalling: Marshalling,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionResult {
+    pub ty: TypeExpr,
+    pub marshalling: Marshalling,
+}
+
+/// Which of the `marshall_*` functions is used for a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Marshalling {
+    Fixed,
+    Max,
+    Var,
+
+    /// Rust-allocated buffer (`marshall_to_haskell_external`)
+    External,
+}
+
+/*******************************************************************************
+  Schema
+*******************************************************************************/
+
+/// Collection of type declarations
+///
+/// Declarations are stored in the order in which they are completed, so that
+/// (non-recursive) types come after the types they depend on.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Schema {
+    decls: Vec<TypeDecl>,
+
+    /// Names of all declarations we have started, mapped to their Rust path
+    reserved: BTreeMap<String, String>,
+
+    /// Rust types that map to an already used name
+    conflicts: Vec<(String, String)>,
+
+    /// Rust types with an existing Haskell counterpart (`#[haskell(maps_to = "..")]`)
+    #[serde(default)]
+    mapped_types: BTreeMap<String, String>,
+
+    /// Sizes of the fixed-size types (according to `HaskellSize`), by their
+    /// type expression
+    #[serde(default)]
+    sizes: BTreeMap<String, usize>,
+}
+
+impl Schema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the declarations needed for `T` (for the specified tag)
+    pub fn add<Tag, T: HaskellType<Tag> + ?Sized>(&mut self) -> &mut Self {
+        T::haskell_decls(PhantomData, self);
+        self
+    }
+
+    pub fn decls(&self) -> &[TypeDecl] {
+        &self.decls
+    }
+
+    pub fn get(&self, name: &str) -> Option<&TypeDecl> {
+        self.decls.iter().find(|decl| decl.name == name)
+    }
+
+    /// Pairs of Rust types that were both given the same name
+    pub fn conflicts(&self) -> &[(String, String)] {
+        &self.conflicts
+    }
+
+    /// Rust types mapped onto existing Haskell types, by their Rust path
+    ///
+    /// See `codegen::type_map`.
+    pub fn mapped_types(&self) -> &BTreeMap<String, String> {
+        &self.mapped_types
+    }
+
+    /// Refer to the Rust type using the (module-qualified) Haskell type
+    ///
+    /// No declaration is generated for mapped types. Used by the derived
+    /// `HaskellType` instances for `#[haskell(maps_to = "..")]`.
+    pub fn map_type(&mut self, rust_path: &str, haskell_name: &str) -> &mut Self {
+        self.mapped_types
+            .insert(rust_path.to_string(), haskell_name.to_string());
+        self
+    }
+
+    /// Statically known size of the type, if recorded
+    ///
+    /// Sizes are recorded by the derived `HaskellType` instances, for the
+    /// types with a `HaskellSize` instance; schemas obtained from the source
+    /// code (see `build`) do not record any sizes.
+    pub fn size(&self, ty: &TypeExpr) -> Option<usize> {
+        self.sizes.get(&ty.to_string()).copied()
+    }
+
+    /// Record the size of the type (if it has a statically known size)
+    ///
+    /// Used by the derived `HaskellType` instances.
+    #[doc(hidden)]
+    pub fn record_size(&mut self, ty: TypeExpr, size: Option<usize>) {
+        if let Some(size) = size {
+            self.sizes.insert(ty.to_string(), size);
+        }
+    }
+
+    /// Reserve a name for a declaration
+    ///
+    /// Returns `false` if the declaration was already added (or is in the
+    /// process of being added, for recursive types). Used by the derived
+    /// `HaskellType` instances.
+    #[doc(hidden)]
+    pub fn reserve(&mut self, name: &str, rust_path: &str) -> bool {
+        match self.reserved.get(name) {
+            None => {
+                self.reserved
+                    .insert(name.to_string(), rust_path.to_string());
+                true
+            }
+            Some(existing) => {
+                if existing != rust_path {
+                    let conflict = (existing.clone(), rust_path.to_string());
+                    if !self.conflicts.contains(&conflict) {
+                        self.conflicts.push(conflict);
+                    }
+                }
+                false
+            }
+        }
+    }
+
+    /// Add a declaration (whose name must previously have been reserved)
+    #[doc(hidden)]
+    pub fn insert(&mut self, decl: TypeDecl) {
+        self.decls.push(decl);
+    }
+}
+
+/*******************************************************************************
+  Dumps
+*******************************************************************************/
+
+/// Schema together with the exported functions
+///
+/// This is everything needed to generate the Haskell side of the bindings; it
+/// can be written to a JSON file, to be read by `haskell-ffi-gen`:
+///
+/// ```ignore
+/// let mut dump = SchemaDump::new();
+/// dump.add::<ExampleTag, Config>().add_exports();
+/// dump.write_to(Path::new("schema.json"))?;
+/// ```
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SchemaDump {
+    pub schema: Schema,
+    pub functions: Vec<FunctionDecl>,
+}
+
+impl SchemaDump {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the declarations needed for `T` (for the specified tag)
+    pub fn add<Tag, T: HaskellType<Tag> + ?Sized>(&mut self) -> &mut Self {
+        self.schema.add::<Tag, T>();
+        self
+    }
+
+    /// Add all functions exported using `#[haskell_export]`
+    ///
+    /// The declarations of the types they use are added to the schema.
+    pub fn add_exports(&mut self) -> &mut Self {
+        for export in exports() {
+            let decl = (export.describe)(&mut self.schema);
+            self.functions.push(decl);
+        }
+        self
+    }
+
+    /// Add the function checking the fingerprints (see `fingerprint`)
+    ///
+    /// This function is exported by every library using `haskell_ffi`, and is
+    /// included by `add_exports`; it only needs to be added explicitly when
+    /// the functions are not obtained from the registry.
+    pub fn add_schema_check(&mut self) -> &mut Self {
+        let symbol = fingerprint::CHECK_SCHEMA_SYMBOL;
+        if self
+            .functions
+            .iter()
+            .all(|function| function.symbol != symbol)
+        {
+            if let Some(export) = exports().into_iter().find(|export| export.symbol == symbol) {
+                let decl = (export.describe)(&mut self.schema);
+                self.functions.push(decl);
+                self.functions.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+            }
+        }
+        self
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn write_to(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    pub fn read_from(path: &Path) -> Result<Self> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+}
+
+/*******************************************************************************
+  Sanity checks
+*******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HaskellType;
+
+    #[derive(HaskellType)]
+    #[allow(dead_code)]
+    enum Message<T> {
+        Ping,
+        Data { payload: Vec<T>, checksum: [u8; 4] },
+    }
+
+    #[test]
+    fn dump_roundtrip() -> Result<()> {
+        let mut dump = SchemaDump::new();
+        dump.add::<(), Message<u16>>();
+        let json = dump.to_json()?;
+        let roundtrip = SchemaDump::from_json(&json)?;
+        assert_eq!(roundtrip.schema.decls(), dump.schema.decls());
+        assert_eq!(roundtrip.to_json()?, json);
+        Ok(())
+    }
+}
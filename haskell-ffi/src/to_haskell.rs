@@ -1,6 +1,12 @@
 use std::{fmt::Display, io::Write, marker::PhantomData};
 
-use crate::{error::Result, haskell_max_size::HaskellMaxSize, HaskellSize};
+use crate::{
+    error::{Error, Result, Status},
+    haskell_max_size::HaskellMaxSize,
+    HaskellSize,
+};
+
+pub use haskell_ffi_derive::ToHaskell;
 
 /*******************************************************************************
   Main class definition
@@ -52,21 +58,8 @@ pub fn marshall_to_haskell_fixed<Tag, T>(t: &T, out: *mut u8, out_len: usize, ta
 where
     T: HaskellSize<Tag> + ToHaskell<Tag>,
 {
-    let expected_len: usize = T::haskell_size(tag);
-    if out_len != expected_len {
-        panic!(
-            "marshall_to_haskell_fixed: expected buffer of size {}, but got {}",
-            expected_len, out_len
-        )
-    } else {
-        let mut out_len_copy = out_len;
-        marshall_to_haskell_var(t, out, &mut out_len_copy, tag);
-        if out_len_copy != expected_len {
-            panic!(
-                "marshall_to_haskell_fixed: got buffer of expected size {}, but needed {}; bug in HaskellSize instance?",
-                expected_len, out_len_copy
-            );
-        }
+    if let Err(e) = try_marshall_to_haskell_fixed(t, out, out_len, tag) {
+        panic!("marshall_to_haskell_fixed: {}", e)
     }
 }
 
@@ -78,21 +71,8 @@ pub fn marshall_to_haskell_max<Tag, T>(t: &T, out: *mut u8, out_len: usize, tag:
 where
     T: HaskellMaxSize<Tag> + ToHaskell<Tag>,
 {
-    let max_len: usize = T::haskell_max_size(tag);
-    if out_len != max_len {
-        panic!(
-            "marshall_to_haskell_max: expected buffer of size {}, but got {}",
-            max_len, out_len
-        )
-    } else {
-        let mut out_len_copy = out_len;
-        marshall_to_haskell_var(t, out, &mut out_len_copy, tag);
-        if out_len_copy > max_len {
-            panic!(
-                "marshall_to_haskell_max: required size {} exceeds maximum {}; bug in HaskellMaxSize instance?",
-                out_len_copy, max_len
-            );
-        }
+    if let Err(e) = try_marshall_to_haskell_max(t, out, out_len, tag) {
+        panic!("marshall_to_haskell_max: {}", e)
     }
 }
 
@@ -105,19 +85,8 @@ pub fn marshall_to_haskell_var<Tag, T>(
 ) where
     T: ToHaskell<Tag>,
 {
-    match t.to_haskell_vec(tag) {
-        Ok(vec) => {
-            let slice: &[u8] = vec.as_ref();
-
-            if slice.len() <= *out_len {
-                unsafe {
-                    std::ptr::copy(slice.as_ptr(), out, slice.len());
-                }
-            }
-
-            *out_len = slice.len();
-        }
-        Err(e) => panic!("{}", e),
+    if let Err(e) = try_marshall_to_haskell_var(t, out, out_len, tag) {
+        panic!("{}", e)
     }
 }
 
@@ -131,11 +100,9 @@ pub fn marshall_result_to_haskell_var<Tag, T, E>(
     T: ToHaskell<Tag>,
     E: Display,
 {
-    let res: core::result::Result<&T, String> = match res {
-        Ok(t) => Ok(t),
-        Err(e) => Err(format!("{}", e)),
-    };
-    marshall_to_haskell_var(&res, out, out_len, tag);
+    if let Err(e) = try_marshall_result_to_haskell_var(res, out, out_len, tag) {
+        panic!("{}", e)
+    }
 }
 
 /*******************************************************************************
@@ -151,8 +118,8 @@ pub fn marshall_to_haskell_external<Tag, T>(t: &T, tag: PhantomData<Tag>) -> *mu
 where
     T: ToHaskell<Tag>,
 {
-    match t.to_haskell_vec(tag) {
-        Ok(vec) => Box::into_raw(Box::new(vec)),
+    match try_marshall_to_haskell_external(t, tag) {
+        Ok(vec) => vec,
         Err(e) => panic!("{}", e),
     }
 }
@@ -185,3 +152,401 @@ pub extern "C" fn haskell_ffi_external_len(vec: *mut Vec<u8>) -> usize {
 pub extern "C" fn haskell_ffi_external_free(vec: *mut Vec<u8>) {
     let _vec = unsafe { Box::from_raw(vec) };
 }
+
+/*******************************************************************************
+  Fallible variants
+
+  The functions above panic if the value cannot be encoded, or if the size of
+  the buffer is not what we expect. These variants return an error instead,
+  leaving it to the caller how to report it.
+*******************************************************************************/
+
+/// Variant of `marshall_to_haskell_fixed` that returns an error
+pub fn try_marshall_to_haskell_fixed<Tag, T>(
+    t: &T,
+    out: *mut u8,
+    out_len: usize,
+    tag: PhantomData<Tag>,
+) -> Result<()>
+where
+    T: HaskellSize<Tag> + ToHaskell<Tag>,
+{
+    let expected_len: usize = T::haskell_size(tag);
+    if out_len != expected_len {
+        return Err(Error::size_mismatch::<T>(expected_len, out_len));
+    }
+    let mut out_len_copy = out_len;
+    try_marshall_to_haskell_var(t, out, &mut out_len_copy, tag)?;
+    if out_len_copy != expected_len {
+        return Err(Error::custom(format!(
+            "got buffer of expected size {}, but needed {}; bug in HaskellSize instance?",
+            expected_len, out_len_copy
+        )));
+    }
+    Ok(())
+}
+
+/// Variant of `marshall_to_haskell_max` that returns an error
+pub fn try_marshall_to_haskell_max<Tag, T>(
+    t: &T,
+    out: *mut u8,
+    out_len: usize,
+    tag: PhantomData<Tag>,
+) -> Result<()>
+where
+    T: HaskellMaxSize<Tag> + ToHaskell<Tag>,
+{
+    let max_len: usize = T::haskell_max_size(tag);
+    if out_len != max_len {
+        return Err(Error::size_mismatch::<T>(max_len, out_len));
+    }
+    let mut out_len_copy = out_len;
+    try_marshall_to_haskell_var(t, out, &mut out_len_copy, tag)?;
+    if out_len_copy > max_len {
+        return Err(Error::custom(format!(
+            "required size {} exceeds maximum {}; bug in HaskellMaxSize instance?",
+            out_len_copy, max_len
+        )));
+    }
+    Ok(())
+}
+
+/// Variant of `marshall_to_haskell_var` that returns an error
+///
+/// The encoding is written to `out` only if it fits; `out_len` is set to the
+/// length of the encoding either way.
+pub fn try_marshall_to_haskell_var<Tag, T>(
+    t: &T,
+    out: *mut u8,
+    out_len: &mut usize,
+    tag: PhantomData<Tag>,
+) -> Result<()>
+where
+    T: ToHaskell<Tag>,
+{
+    let vec = t.to_haskell_vec(tag)?;
+    let slice: &[u8] = vec.as_ref();
+
+    if slice.len() <= *out_len {
+        unsafe {
+            std::ptr::copy(slice.as_ptr(), out, slice.len());
+        }
+    }
+
+    *out_len = slice.len();
+    Ok(())
+}
+
+/// Variant of `marshall_result_to_haskell_var` that returns an error
+pub fn try_marshall_result_to_haskell_var<Tag, T, E>(
+    res: &core::result::Result<T, E>,
+    out: *mut u8,
+    out_len: &mut usize,
+    tag: PhantomData<Tag>,
+) -> Result<()>
+where
+    T: ToHaskell<Tag>,
+    E: Display,
+{
+    let res: core::result::Result<&T, String> = match res {
+        Ok(t) => Ok(t),
+        Err(e) => Err(format!("{}", e)),
+    };
+    try_marshall_to_haskell_var(&res, out, out_len, tag)
+}
+
+/// Variant of `marshall_to_haskell_external` that returns an error
+pub fn try_marshall_to_haskell_external<Tag, T>(
+    t: &T,
+    tag: PhantomData<Tag>,
+) -> Result<*mut Vec<u8>>
+where
+    T: ToHaskell<Tag>,
+{
+    let vec = t.to_haskell_vec(tag)?;
+    Ok(Box::into_raw(Box::new(vec)))
+}
+
+/*******************************************************************************
+  Without panicking
+
+  Unwinding out of an `extern "C"` function aborts the process. These variants
+  of the functions above catch panics (including those in the `ToHaskell`
+  instances), and report failures using a status code instead.
+*******************************************************************************/
+
+/// Variant of `marshall_to_haskell_fixed` that does not panic
+pub fn catch_marshall_to_haskell_fixed<Tag, T>(
+    t: &T,
+    out: *mut u8,
+    out_len: usize,
+    tag: PhantomData<Tag>,
+) -> Status
+where
+    T: HaskellSize<Tag> + ToHaskell<Tag>,
+{
+    Status::catch(|| try_marshall_to_haskell_fixed(t, out, out_len, tag)).into()
+}
+
+/// Variant of `marshall_to_haskell_max` that does not panic
+pub fn catch_marshall_to_haskell_max<Tag, T>(
+    t: &T,
+    out: *mut u8,
+    out_len: usize,
+    tag: PhantomData<Tag>,
+) -> Status
+where
+    T: HaskellMaxSize<Tag> + ToHaskell<Tag>,
+{
+    Status::catch(|| try_marshall_to_haskell_max(t, out, out_len, tag)).into()
+}
+
+/// Variant of `marshall_to_haskell_var` that does not panic
+pub fn catch_marshall_to_haskell_var<Tag, T>(
+    t: &T,
+    out: *mut u8,
+    out_len: &mut usize,
+    tag: PhantomData<Tag>,
+) -> Status
+where
+    T: ToHaskell<Tag>,
+{
+    Status::catch(|| try_marshall_to_haskell_var(t, out, out_len, tag)).into()
+}
+
+/// Variant of `marshall_to_haskell_external` that does not panic
+///
+/// The buffer is written to `out`; it is set to null if marshalling fails.
+pub fn catch_marshall_to_haskell_external<Tag, T>(
+    t: &T,
+    out: *mut *mut Vec<u8>,
+    tag: PhantomData<Tag>,
+) -> Status
+where
+    T: ToHaskell<Tag>,
+{
+    let (buffer, status) = match Status::catch(|| try_marshall_to_haskell_external(t, tag)) {
+        Ok(vec) => (vec, Status::Ok),
+        Err(status) => (std::ptr::null_mut(), status),
+    };
+    unsafe {
+        *out = buffer;
+    }
+    status
+}
+
+/*******************************************************************************
+  Sanity checks
+*******************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use borsh::BorshSerialize;
+
+    use super::*;
+
+    enum ExampleTag {}
+
+    #[derive(ToHaskell, BorshSerialize)]
+    struct EmptyStruct;
+
+    #[derive(ToHaskell, BorshSerialize)]
+    struct UnnamedStruct(u16, (u8, u32));
+
+    #[derive(ToHaskell, BorshSerialize)]
+    struct NamedStruct {
+        a: u8,
+        b: String,
+        c: Vec<(u32, u64)>,
+    }
+
+    #[derive(ToHaskell, BorshSerialize)]
+    struct ParamStruct<T> {
+        a: u8,
+        b: Option<T>,
+    }
+
+    #[derive(ToHaskell, BorshSerialize)]
+    enum Shape<T> {
+        Empty,
+        Circle(T),
+        Rect { width: T, height: T },
+    }
+
+    #[derive(ToHaskell)]
+    #[allow(dead_code)]
+    enum Void {}
+
+    /// Type with an instance for `ExampleTag` only
+    struct OnlyExample(u8);
+
+    impl ToHaskell<ExampleTag> for OnlyExample {
+        fn to_haskell<W: Write>(&self, writer: &mut W, tag: PhantomData<ExampleTag>) -> Result<()> {
+            self.0.to_haskell(writer, tag)
+        }
+    }
+
+    #[derive(ToHaskell)]
+    #[haskell(tag = "ExampleTag")]
+    struct TagSpecific {
+        a: OnlyExample,
+        b: u16,
+    }
+
+    /// User type parameter that happens to be called `Tag`
+    #[derive(ToHaskell, BorshSerialize)]
+    struct ParamCalledTag<Tag> {
+        a: Tag,
+    }
+
+    #[derive(ToHaskell)]
+    struct Borrowed<'a, T> {
+        a: &'a T,
+        b: &'a [u8; 2],
+    }
+
+    /// There is no `ToHaskell` instance for arrays of arbitrary length
+    #[derive(ToHaskell)]
+    #[haskell(bound = "[T; N]: ToHaskell<Tag>")]
+    struct Window<T, const N: usize> {
+        xs: [T; N],
+    }
+
+    #[derive(ToHaskell)]
+    struct FieldBound<T, const N: usize> {
+        a: T,
+        #[haskell(bound(to_haskell = "[T; N]: ToHaskell<Tag>"))]
+        xs: [T; N],
+    }
+
+    #[test]
+    fn fallible() -> Result<()> {
+        let tag: PhantomData<ExampleTag> = PhantomData;
+        let mut out = [0u8; 4];
+        try_marshall_to_haskell_fixed(&7u32, out.as_mut_ptr(), out.len(), tag)?;
+        assert_eq!(out.to_vec(), 7u32.try_to_vec()?);
+        let err = try_marshall_to_haskell_fixed(&7u32, out.as_mut_ptr(), 2, tag).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::SizeMismatch {
+                expected: 4,
+                actual: 2,
+                type_name: "u32"
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "expected buffer of size 4 for u32, but got 2"
+        );
+
+        // The buffer is too small: only the required size is reported
+        let mut out_len = 2;
+        try_marshall_to_haskell_var(&"abc".to_string(), out.as_mut_ptr(), &mut out_len, tag)?;
+        assert_eq!(out_len, 7);
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "marshall_to_haskell_fixed: expected buffer of size 4 for u32, but got 2"
+    )]
+    fn wrong_size() {
+        let tag: PhantomData<ExampleTag> = PhantomData;
+        let mut out = [0u8; 4];
+        marshall_to_haskell_fixed(&7u32, out.as_mut_ptr(), 2, tag);
+    }
+
+    #[test]
+    fn empty() -> Result<()> {
+        let tag: PhantomData<ExampleTag> = PhantomData;
+        assert_eq!(EmptyStruct.to_haskell_vec(tag)?, EmptyStruct.try_to_vec()?);
+        Ok(())
+    }
+
+    #[test]
+    fn unnamed() -> Result<()> {
+        let tag: PhantomData<ExampleTag> = PhantomData;
+        let x = UnnamedStruct(1, (2, 3));
+        assert_eq!(x.to_haskell_vec(tag)?, x.try_to_vec()?);
+        Ok(())
+    }
+
+    #[test]
+    fn named() -> Result<()> {
+        let tag: PhantomData<ExampleTag> = PhantomData;
+        let x = NamedStruct {
+            a: 1,
+            b: "hello".to_string(),
+            c: vec![(2, 3), (4, 5)],
+        };
+        assert_eq!(x.to_haskell_vec(tag)?, x.try_to_vec()?);
+        Ok(())
+    }
+
+    #[test]
+    fn param() -> Result<()> {
+        let tag: PhantomData<ExampleTag> = PhantomData;
+        let x = ParamStruct {
+            a: 1,
+            b: Some(2.0f64),
+        };
+        assert_eq!(x.to_haskell_vec(tag)?, x.try_to_vec()?);
+        Ok(())
+    }
+
+    #[test]
+    fn enumeration() -> Result<()> {
+        let tag: PhantomData<ExampleTag> = PhantomData;
+        for x in [
+            Shape::Empty,
+            Shape::Circle(1u32),
+            Shape::Rect {
+                width: 2,
+                height: 3,
+            },
+        ] {
+            assert_eq!(x.to_haskell_vec(tag)?, x.try_to_vec()?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn tag_specific() -> Result<()> {
+        let tag: PhantomData<ExampleTag> = PhantomData;
+        let x = TagSpecific {
+            a: OnlyExample(1),
+            b: 2,
+        };
+        assert_eq!(x.to_haskell_vec(tag)?, (1u8, 2u16).try_to_vec()?);
+        Ok(())
+    }
+
+    #[test]
+    fn param_called_tag() -> Result<()> {
+        let tag: PhantomData<ExampleTag> = PhantomData;
+        let x = ParamCalledTag { a: 1u32 };
+        assert_eq!(x.to_haskell_vec(tag)?, x.try_to_vec()?);
+        Ok(())
+    }
+
+    #[test]
+    fn lifetime() -> Result<()> {
+        let tag: PhantomData<ExampleTag> = PhantomData;
+        let x = Borrowed {
+            a: &1u32,
+            b: &[2, 3],
+        };
+        assert_eq!(x.to_haskell_vec(tag)?, (1u32, [2u8, 3]).try_to_vec()?);
+        Ok(())
+    }
+
+    #[test]
+    fn custom_bound() -> Result<()> {
+        let tag: PhantomData<ExampleTag> = PhantomData;
+        let x = Window { xs: [1u16, 2, 3] };
+        assert_eq!(x.to_haskell_vec(tag)?, [1u16, 2, 3].try_to_vec()?);
+        let x = FieldBound { a: 1u8, xs: [2, 3] };
+        assert_eq!(x.to_haskell_vec(tag)?, [1u8, 2, 3].try_to_vec()?);
+        Ok(())
+    }
+}
@@ -42,12 +42,41 @@ impl<Tag, T: BorshDeserialize> FromHaskell<Tag> for UseBorsh<T> {
     }
 }
 
+/*******************************************************************************
+  Field-level support
+
+  These are used by `#[haskell(via = "borsh")]` in the derived instances,
+  which avoids the need to wrap the field in `UseBorsh`.
+*******************************************************************************/
+
+/// Implement `to_haskell` using `borsh`
+///
+/// This is equivalent to `UseBorsh(t).to_haskell(writer, tag)`.
+pub fn borsh_to_haskell<Tag, T, W>(t: &T, writer: &mut W, _: PhantomData<Tag>) -> Result<()>
+where
+    T: BorshSerialize,
+    W: Write,
+{
+    t.serialize(writer)?;
+    Ok(())
+}
+
+/// Implement `from_haskell` using `borsh`
+///
+/// See also `borsh_to_haskell`.
+pub fn borsh_from_haskell<Tag, T>(buf: &mut &[u8], tag: PhantomData<Tag>) -> Result<T>
+where
+    T: BorshDeserialize,
+{
+    UseBorsh::from_haskell(buf, tag).map(unwrap_use_borsh)
+}
+
 /*******************************************************************************
   Additional standard instances
 *******************************************************************************/
 
 impl<T: AsRef<T>> AsRef<T> for UseBorsh<T> {
     fn as_ref(&self) -> &T {
-        unwrap_use_borsh_ref(self).as_ref()
+        unwrap_use_borsh_ref(self)
     }
 }